@@ -2,12 +2,14 @@ const TWO_PI = 6.28318530718;
 
 const ALPASS_DFT = vec2<i32>(0, 4);
 const ALPASS_WAVEFORM = vec2<i32>(0, 6);
+const ALPASS_GENERATORS = vec2<i32>(0, 22);
+const ALPASS_TEMPO = vec2<i32>(0, 23);
 
 const AUDIOLINK_SAMPHIST = 3069;
 const AUDIOLINK_EXPBINS = 24;
 const AUDIOLINK_EXPOCT = 10;
 const AUDIOLINK_ETOTALBINS = (AUDIOLINK_EXPBINS * AUDIOLINK_EXPOCT);
-const AUDIOLINK_WIDTH = 128;
+const AUDIOLINK_WIDTH = #{AUDIOLINK_WIDTH};
 const AUDIOLINK_SPS = 48000;
 const AUDIOLINK_BOTTOM_FREQUENCY = 13.75;
 const AUDIOLINK_BASE_AMPLITUDE = 2.5;
@@ -40,10 +42,22 @@ const AUDIOLINK_LUT = array<f32, 240>(
 @group(0) @binding(0) var input: texture_storage_2d<rgba32float, read>;
 @group(0) @binding(1) var output: texture_storage_2d<rgba32float, write>;
 
-@group(0) @binding(2) var<storage, read> audiolink_data_audio_data: array<vec4<f32>, 4096>;
+@group(0) @binding(2) var<storage, read> audiolink_data_audio_data: array<vec4<f32>>;
 
 @group(0) @binding(3) var<uniform> audiolink_uniforms: AudiolinkUniforms;
 
+// One texel per LFO and envelope: value, phase, gate, zero
+@group(0) @binding(4) var<storage, read> audiolink_generator_data: array<vec4<f32>>;
+
+@group(0) @binding(5) var<uniform> audiolink_tempo: AudiolinkTempo;
+
+struct AudiolinkTempo {
+    phase: f32,
+    bpm: f32,
+    beats: f32,
+    pulse: f32,
+}
+
 struct AudiolinkUniforms {
     gain: f32,
     bass: f32,
@@ -122,13 +136,29 @@ fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
 
         var ret: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0); // [ native 48k mono, difference between left and right at 48k, native 24k mono, difference between left and right at 24k]
 
-        if frame < 4092 {
+        if frame < i32(arrayLength(&audiolink_data_audio_data)) {
             ret.x = audiolink_data_audio_data[frame].x;
             ret.y = audiolink_data_audio_data[frame].y;
             ret.z = audiolink_data_audio_data[frame].z;
             ret.w = audiolink_data_audio_data[frame].w;
         }
 
+        textureStore(output, location, ret);
+    } else if coordinateGlobal.y == ALPASS_GENERATORS.y {
+        var ret: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+
+        if coordinateGlobal.x < i32(arrayLength(&audiolink_generator_data)) {
+            ret = audiolink_generator_data[coordinateGlobal.x];
+        }
+
+        textureStore(output, location, ret);
+    } else if coordinateGlobal.y == ALPASS_TEMPO.y {
+        var ret: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+
+        if coordinateGlobal.x == ALPASS_TEMPO.x {
+            ret = vec4<f32>(audiolink_tempo.phase, audiolink_tempo.bpm, audiolink_tempo.beats, audiolink_tempo.pulse);
+        }
+
         textureStore(output, location, ret);
     } else {
         textureStore(output, location, vec4<f32>(0.0, 0.0, 0.0, 0.0));
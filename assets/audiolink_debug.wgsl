@@ -0,0 +1,47 @@
+#import bevy_ui::ui_vertex_output::UiVertexOutput
+
+struct AudiolinkDebugSettings {
+    channel: u32,
+    scale: f32,
+}
+
+@group(1) @binding(0) var audiolink_texture: texture_2d<f32>;
+@group(1) @binding(1) var<uniform> settings: AudiolinkDebugSettings;
+
+// Positive values are drawn white, negative values blue
+fn signed_color(value: f32) -> vec3<f32> {
+    if value < 0.0 {
+        return vec3<f32>(0.2, 0.4, 1.0) * -value;
+    }
+
+    return vec3<f32>(value);
+}
+
+@fragment
+fn fragment(in: UiVertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(audiolink_texture));
+    let texel = vec2<i32>(clamp(in.uv * size, vec2<f32>(0.0), size - 1.0));
+
+    let value = textureLoad(audiolink_texture, texel, 0) * settings.scale;
+
+    var color: vec3<f32>;
+    switch settings.channel {
+        case 1u: {
+            color = signed_color(value.r);
+        }
+        case 2u: {
+            color = signed_color(value.g);
+        }
+        case 3u: {
+            color = signed_color(value.b);
+        }
+        case 4u: {
+            color = signed_color(value.a);
+        }
+        default: {
+            color = abs(value.rgb);
+        }
+    }
+
+    return vec4<f32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
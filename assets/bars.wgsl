@@ -0,0 +1,71 @@
+#import bevy_pbr::{
+    mesh_functions::{get_world_from_local, mesh_normal_local_to_world, mesh_position_local_to_clip},
+}
+
+const ALPASS_DFT = vec2<i32>(0, 4);
+const DFT_BINS = 240.0;
+
+struct BarsSettings {
+    height: f32,
+    floor: f32,
+}
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // The bar's place in the spectrum, and one on the top face
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) spectrum: f32,
+    @location(2) magnitude: f32,
+    @location(3) height: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var audiolink_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var audiolink_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var palette_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var palette_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var<uniform> settings: BarsSettings;
+
+fn dft_magnitude(bin: i32) -> f32 {
+    let width = i32(textureDimensions(audiolink_texture).x);
+
+    return textureLoad(audiolink_texture, ALPASS_DFT + vec2<i32>(bin % width, bin / width), 0).y;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let bin = i32(vertex.uv.x * DFT_BINS);
+    let magnitude = dft_magnitude(bin);
+
+    // Bars are one unit tall in the mesh, the top vertices raised to the bin's magnitude
+    var position = vertex.position;
+    position.y *= settings.floor + magnitude * settings.height;
+
+    let world_from_local = get_world_from_local(vertex.instance_index);
+
+    var out: VertexOutput;
+    out.position = mesh_position_local_to_clip(world_from_local, vec4<f32>(position, 1.0));
+    out.world_normal = mesh_normal_local_to_world(vertex.normal, vertex.instance_index);
+    out.spectrum = vertex.uv.x;
+    out.magnitude = magnitude;
+    out.height = vertex.uv.y;
+
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(palette_texture, palette_sampler, vec2<f32>(in.spectrum, 0.5)).rgb;
+
+    // A fixed light from above and in front, with the tops glowing brighter the louder the bin
+    let light = 0.35 + 0.65 * max(dot(normalize(in.world_normal), normalize(vec3<f32>(0.3, 1.0, 0.6))), 0.0);
+    let glow = mix(0.4, 1.0 + in.magnitude, in.height);
+
+    return vec4<f32>(color * light * glow, 1.0);
+}
@@ -0,0 +1,64 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+const BLEND_NORMAL = 0u;
+const BLEND_ADD = 1u;
+const BLEND_SCREEN = 2u;
+const BLEND_MULTIPLY = 3u;
+const BLEND_DIFFERENCE = 4u;
+const BLEND_LUMA_KEY = 5u;
+
+struct CompositeSettings {
+    blend: u32,
+    has_base: u32,
+    opacity: f32,
+    key_threshold: f32,
+    key_softness: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var base_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var base_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var layer_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var layer_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var<uniform> settings: CompositeSettings;
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    var base = vec3<f32>(0.0);
+    if settings.has_base != 0u {
+        base = textureSample(base_texture, base_sampler, in.uv).rgb;
+    }
+
+    // Layers are cleared to transparent black and alpha blended into, so their color is premultiplied
+    let layer = textureSample(layer_texture, layer_sampler, in.uv);
+    let coverage = layer.a * settings.opacity;
+    let straight = layer.rgb / max(layer.a, 1e-4);
+
+    var color: vec3<f32>;
+    switch settings.blend {
+        case BLEND_ADD: {
+            color = base + layer.rgb * settings.opacity;
+        }
+        case BLEND_SCREEN: {
+            color = 1.0 - (1.0 - base) * (1.0 - layer.rgb * settings.opacity);
+        }
+        case BLEND_MULTIPLY: {
+            color = mix(base, base * straight, coverage);
+        }
+        case BLEND_DIFFERENCE: {
+            color = mix(base, abs(base - straight), coverage);
+        }
+        case BLEND_LUMA_KEY: {
+            let key = smoothstep(settings.key_threshold, settings.key_threshold + settings.key_softness, luminance(straight));
+            color = mix(base, straight, coverage * key);
+        }
+        case BLEND_NORMAL, default: {
+            color = base * (1.0 - coverage) + layer.rgb * settings.opacity;
+        }
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,35 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+struct HistorySettings {
+    resolution: vec2<f32>,
+    zoom: f32,
+    rotation: f32,
+    decay: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var frame_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var frame_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var previous_frame: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var previous_frame_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var<uniform> settings: HistorySettings;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = textureSample(frame_texture, frame_sampler, in.uv);
+
+    // Zoom and rotate around the center in square pixels, once per frame
+    let aspect = vec2<f32>(settings.resolution.x / settings.resolution.y, 1.0);
+    let centered = (in.uv - 0.5) * aspect / settings.zoom;
+    let rotated = vec2<f32>(
+        centered.x * cos(settings.rotation) - centered.y * sin(settings.rotation),
+        centered.x * sin(settings.rotation) + centered.y * cos(settings.rotation),
+    );
+    let previous_uv = rotated / aspect + 0.5;
+
+    var previous = textureSample(previous_frame, previous_frame_sampler, previous_uv).rgb * settings.decay;
+    if any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0)) {
+        previous = vec3<f32>(0.0);
+    }
+
+    return vec4<f32>(max(frame.rgb, previous), frame.a);
+}
@@ -9,51 +9,34 @@ const PI = 3.14159265359;
 const ALPASS_DFT = vec2<f32>(0.0, 4.0);
 const ALPASS_WAVEFORM = vec2<f32>(0.0, 6.0);
 
-const AUDIOLINK_WIDTH = 128;
-const AUDIOLINK_HEIGHT = 64;
-
 const AUDIOLINK_EXPBINS = 24;
 const AUDIOLINK_EXPOCT = 10;
 const AUDIOLINK_ETOTALBINS = (AUDIOLINK_EXPBINS * AUDIOLINK_EXPOCT);
 
-const START_HUE = 240.0;
-const END_HUE = 360.0;
-
 const SAMPLES_USED = 512.0;
 
 @group(#{MATERIAL_BIND_GROUP}) @binding(0) var audiolink_texture: texture_2d<f32>;
 @group(#{MATERIAL_BIND_GROUP}) @binding(1) var audiolink_sampler: sampler;
-
-fn oklch_to_oklab(c: vec3<f32>) -> vec3<f32> {
-    return vec3<f32>(
-        c.x,
-        c.y * cos(c.z * PI / 180),
-        c.y * sin(c.z * PI / 180)
-    );
-}
-
-fn oklab_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
-    let L = c.x;
-    let a = c.y;
-    let b = c.z;
-
-    let l_ = L + 0.3963377774 * a + 0.2158037573 * b;
-    let m_ = L - 0.1055613458 * a - 0.0638541728 * b;
-    let s_ = L - 0.0894841775 * a - 1.2914855480 * b;
-
-    let l = l_ * l_ * l_;
-    let m = m_ * m_ * m_;
-    let s = s_ * s_ * s_;
-
-    return vec3<f32>(
-        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
-        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
-        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
-    );
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var mask_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var mask_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var<uniform> settings: LogoBackgroundSettings;
+@group(#{MATERIAL_BIND_GROUP}) @binding(5) var palette_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(6) var palette_sampler: sampler;
+
+struct LogoBackgroundSettings {
+    spread: f32,
+    fill: f32,
+    expand: f32,
+    outline_width: f32,
+    glow_width: f32,
+    glow_strength: f32,
+    opacity: f32,
 }
 
 fn audiolink_sample_multiline(xycoord: vec2<f32>) -> vec4<f32> {
-    return textureSample(audiolink_texture, audiolink_sampler, vec2<f32>((xycoord.x % AUDIOLINK_WIDTH) / AUDIOLINK_WIDTH, (xycoord.y + xycoord.x / AUDIOLINK_WIDTH) / AUDIOLINK_HEIGHT));
+    let size = vec2<f32>(textureDimensions(audiolink_texture));
+
+    return textureSample(audiolink_texture, audiolink_sampler, vec2<f32>((xycoord.x % size.x) / size.x, (xycoord.y + xycoord.x / size.x) / size.y));
 }
 
 fn audiolink_sample_lerp_multiline(xy: vec2<f32>) -> vec4<f32> {
@@ -66,5 +49,25 @@ fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
 
     let sample_value: f32 = pow(audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>(f32(distance * SAMPLES_USED / 2), 0.0)).r + 1.0, 2.0) / 2.0;
 
-    return vec4<f32>(oklab_to_linear_srgb(oklch_to_oklab(vec3<f32>(mix(0.4101, 0.7101, sample_value), 0.1301, mix(START_HUE, END_HUE, distance)))), 1.0);
+    // The waveform brightens the palette color along the diagonal
+    let palette_color = textureSample(palette_texture, palette_sampler, vec2<f32>(clamp(distance * 0.5, 0.0, 1.0), 0.5)).rgb;
+    let color = palette_color * mix(0.6, 1.4, clamp(sample_value, 0.0, 1.0));
+
+    // Signed distance to the expanded logo edge in logo heights, negative inside
+    let shape_distance = (textureSample(mask_texture, mask_sampler, in.uv).r - 0.5) * 2.0 * settings.spread - settings.expand;
+    let edge = fwidth(shape_distance);
+
+    let fill = settings.fill * (1.0 - smoothstep(-edge, edge, shape_distance));
+
+    var outline = 0.0;
+    if settings.outline_width > 0.0 {
+        outline = 1.0 - smoothstep(-edge, edge, abs(shape_distance) - settings.outline_width * 0.5);
+    }
+
+    var glow = 0.0;
+    if settings.glow_width > 0.0 {
+        glow = settings.glow_strength * sample_value * exp(-max(shape_distance, 0.0) / settings.glow_width);
+    }
+
+    return vec4<f32>(color, clamp(max(max(fill, outline), glow), 0.0, 1.0) * settings.opacity);
 }
@@ -0,0 +1,21 @@
+#import bevy_pbr::forward_io::VertexOutput
+
+struct LogoSettings {
+    opacity: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var<uniform> settings: LogoSettings;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var logo_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var logo_sampler: sampler;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+#ifdef VERTEX_COLORS
+    // SVG meshes carry their fill colors per vertex
+    let color = in.color;
+#else
+    let color = textureSample(logo_texture, logo_sampler, in.uv);
+#endif
+
+    return vec4<f32>(color.rgb, color.a * settings.opacity);
+}
@@ -0,0 +1,60 @@
+#import bevy_pbr::{
+    mesh_functions::{get_world_from_local, mesh_position_local_to_world},
+    mesh_view_bindings::view,
+}
+
+// Same layout as in particles.wgsl
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+    palette_position: f32,
+    size: f32,
+    padding: vec2<f32>,
+}
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    // The quad's corner in xy and the particle's index in z
+    @location(0) position: vec3<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) palette_position: f32,
+    @location(2) fade: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var<storage, read> particles: array<Particle>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var palette_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var palette_sampler: sampler;
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let particle = particles[u32(vertex.position.z)];
+    let world_from_local = get_world_from_local(vertex.instance_index);
+
+    // Billboards facing the camera, sized in the mesh's local units. Dead particles have no size and
+    // collapse to a point
+    let center = view.view_from_world * mesh_position_local_to_world(world_from_local, vec4<f32>(particle.position, 1.0));
+    let size = select(0.0, particle.size * length(world_from_local[0].xyz), particle.lifetime > 0.0);
+
+    var out: VertexOutput;
+    out.position = view.clip_from_view * (center + vec4<f32>(vertex.position.xy * size, 0.0, 0.0));
+    out.corner = vertex.position.xy;
+    out.palette_position = particle.palette_position;
+    out.fade = 1.0 - clamp(particle.age / max(particle.lifetime, 1e-4), 0.0, 1.0);
+
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = 1.0 - smoothstep(0.0, 1.0, length(in.corner));
+    let color = textureSample(palette_texture, palette_sampler, vec2<f32>(in.palette_position, 0.5)).rgb;
+
+    // Added to what is behind, so the color carries the coverage
+    return vec4<f32>(color * falloff * in.fade, falloff * in.fade);
+}
@@ -0,0 +1,125 @@
+const TWO_PI = 6.28318530718;
+
+const ALPASS_DFT = vec2<u32>(0u, 4u);
+
+const WORKGROUP_SIZE = #{WORKGROUP_SIZE};
+const DFT_BINS = #{DFT_BINS};
+
+// Must stay 48 bytes, PARTICLE_SIZE in particles.rs
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+    palette_position: f32,
+    size: f32,
+    padding: vec2<f32>,
+}
+
+struct ParticleUniforms {
+    delta_time: f32,
+    seed: f32,
+    emission: f32,
+    burst: f32,
+    speed: f32,
+    drag: f32,
+    gravity: f32,
+    lifetime: f32,
+    size: f32,
+}
+
+@group(0) @binding(0) var<storage, read> input: array<Particle>;
+@group(0) @binding(1) var<storage, read_write> output: array<Particle>;
+@group(0) @binding(2) var audiolink_texture: texture_2d<f32>;
+@group(0) @binding(3) var<uniform> uniforms: ParticleUniforms;
+
+fn hash(value: u32) -> u32 {
+    var state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+    return f32(*seed) / 4294967295.0;
+}
+
+fn dft_magnitude(bin: u32) -> f32 {
+    let width = textureDimensions(audiolink_texture).x;
+    let texel = ALPASS_DFT + vec2<u32>(bin % width, bin / width);
+
+    return textureLoad(audiolink_texture, texel, 0).y;
+}
+
+fn dead() -> Particle {
+    return Particle(vec3<f32>(0.0), 0.0, vec3<f32>(0.0), 0.0, 0.0, 0.0, vec2<f32>(0.0));
+}
+
+// Particles are born on a ring, each heading out along the angle of a DFT bin, at a speed set by
+// that bin's magnitude and colored by its place in the spectrum
+fn spawn(seed: ptr<function, u32>, burst: bool) -> Particle {
+    let bin = min(u32(random(seed) * f32(DFT_BINS)), DFT_BINS - 1u);
+    let angle = f32(bin) / f32(DFT_BINS) * TWO_PI + random(seed) * TWO_PI / f32(DFT_BINS);
+    let direction = vec3<f32>(cos(angle), sin(angle), (random(seed) - 0.5) * 0.5);
+
+    var speed = uniforms.speed * (0.2 + dft_magnitude(bin) * 2.0) * (0.75 + random(seed) * 0.5);
+    if burst {
+        speed *= 2.0;
+    }
+
+    var particle: Particle;
+    particle.position = direction * 0.05;
+    particle.age = 0.0;
+    particle.velocity = direction * speed;
+    particle.lifetime = uniforms.lifetime * (0.5 + random(seed));
+    particle.palette_position = f32(bin) / f32(DFT_BINS);
+    particle.size = uniforms.size * (0.5 + random(seed));
+    particle.padding = vec2<f32>(0.0);
+
+    return particle;
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, 1, 1)
+fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    if index >= arrayLength(&output) {
+        return;
+    }
+
+    output[index] = dead();
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE, 1, 1)
+fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let index = invocation_id.x;
+    if index >= arrayLength(&output) {
+        return;
+    }
+
+    var seed = hash(index ^ hash(bitcast<u32>(uniforms.seed)));
+    var particle = input[index];
+
+    if particle.lifetime <= 0.0 {
+        let roll = random(&seed);
+        if roll < uniforms.burst {
+            particle = spawn(&seed, true);
+        } else if roll < uniforms.burst + uniforms.emission {
+            particle = spawn(&seed, false);
+        } else {
+            output[index] = particle;
+            return;
+        }
+    }
+
+    let delta_time = uniforms.delta_time;
+    particle.velocity *= exp(-uniforms.drag * delta_time);
+    particle.velocity.y -= uniforms.gravity * delta_time;
+    particle.position += particle.velocity * delta_time;
+    particle.age += delta_time;
+
+    if particle.age >= particle.lifetime {
+        particle = dead();
+    }
+
+    output[index] = particle;
+}
@@ -0,0 +1,178 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+const PI = 3.14159265359;
+const TWO_PI = 6.28318530718;
+
+const EFFECT_COPY = 0u;
+const EFFECT_FEEDBACK = 1u;
+const EFFECT_BLOOM = 2u;
+const EFFECT_CHROMATIC_ABERRATION = 3u;
+const EFFECT_KALEIDOSCOPE = 4u;
+const EFFECT_MIRROR = 5u;
+const EFFECT_RGB_SPLIT = 6u;
+const EFFECT_PIXELATE = 7u;
+const EFFECT_STROBE = 8u;
+const EFFECT_VIGNETTE = 9u;
+
+const BLOOM_RINGS = 3;
+const BLOOM_TAPS = 12;
+const BLOOM_RADIUS = 24.0;
+
+struct PostSettings {
+    resolution: vec2<f32>,
+    effect: u32,
+    amount: f32,
+    parameter: f32,
+    time: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var input_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var input_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var previous_frame: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var previous_frame_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var<uniform> settings: PostSettings;
+
+fn sample_input(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(input_texture, input_sampler, uv, 0.0);
+}
+
+fn feedback(uv: vec2<f32>) -> vec4<f32> {
+    let current = sample_input(uv);
+    let previous_uv = (uv - 0.5) / settings.parameter + 0.5;
+    let previous = textureSampleLevel(previous_frame, previous_frame_sampler, previous_uv, 0.0);
+
+    return vec4<f32>(max(current.rgb, previous.rgb * settings.amount), current.a);
+}
+
+fn bloom(uv: vec2<f32>) -> vec4<f32> {
+    let current = sample_input(uv);
+    let pixel = 1.0 / settings.resolution;
+
+    var glow = vec3<f32>(0.0);
+    for (var ring = 1; ring <= BLOOM_RINGS; ring++) {
+        let radius = BLOOM_RADIUS * f32(ring) / f32(BLOOM_RINGS);
+
+        for (var tap = 0; tap < BLOOM_TAPS; tap++) {
+            let angle = TWO_PI * (f32(tap) + 0.5 * f32(ring)) / f32(BLOOM_TAPS);
+            let color = sample_input(uv + vec2<f32>(cos(angle), sin(angle)) * radius * pixel).rgb;
+
+            glow += max(color - vec3<f32>(settings.parameter), vec3<f32>(0.0)) / f32(ring);
+        }
+    }
+
+    return vec4<f32>(current.rgb + glow * settings.amount / f32(BLOOM_TAPS), current.a);
+}
+
+// Offsets grow towards the edges, `parameter` shaping how quickly
+fn chromatic_aberration(uv: vec2<f32>) -> vec4<f32> {
+    let from_center = uv - 0.5;
+    let offset = from_center * settings.amount * pow(max(length(from_center) * 2.0, 1e-4), settings.parameter);
+
+    return vec4<f32>(
+        sample_input(uv + offset).r,
+        sample_input(uv).g,
+        sample_input(uv - offset).b,
+        sample_input(uv).a,
+    );
+}
+
+fn kaleidoscope(uv: vec2<f32>) -> vec4<f32> {
+    let aspect = settings.resolution.x / settings.resolution.y;
+    let position = (uv - 0.5) * vec2<f32>(aspect, 1.0);
+
+    let segment = TWO_PI / max(round(settings.parameter), 1.0);
+    let angle = abs((atan2(position.y, position.x) % segment + segment) % segment - segment * 0.5);
+    let folded = vec2<f32>(cos(angle), sin(angle)) * length(position) / vec2<f32>(aspect, 1.0) + 0.5;
+
+    return mix(sample_input(uv), sample_input(folded), settings.amount);
+}
+
+// Axis 0 mirrors the left half, 1 the top half and 2 both
+fn mirror(uv: vec2<f32>) -> vec4<f32> {
+    let axis = u32(round(settings.parameter));
+    var mirrored = uv;
+
+    if axis != 1u {
+        mirrored.x = 0.5 - abs(uv.x - 0.5);
+    }
+    if axis != 0u {
+        mirrored.y = 0.5 - abs(uv.y - 0.5);
+    }
+
+    return mix(sample_input(uv), sample_input(mirrored), settings.amount);
+}
+
+fn rgb_split(uv: vec2<f32>) -> vec4<f32> {
+    let angle = settings.parameter * PI / 180.0;
+    let offset = vec2<f32>(cos(angle), sin(angle)) * settings.amount;
+
+    return vec4<f32>(
+        sample_input(uv + offset).r,
+        sample_input(uv).g,
+        sample_input(uv - offset).b,
+        sample_input(uv).a,
+    );
+}
+
+fn pixelate(uv: vec2<f32>) -> vec4<f32> {
+    let cell = max(1.0, settings.parameter * settings.amount);
+    let snapped = (floor(uv * settings.resolution / cell) + 0.5) * cell / settings.resolution;
+
+    return sample_input(snapped);
+}
+
+// A rate of zero flashes for as long as the amount is up, which suits beat modulation
+fn strobe(uv: vec2<f32>) -> vec4<f32> {
+    let current = sample_input(uv);
+
+    var flash = 1.0;
+    if settings.parameter > 0.0 {
+        flash = step(0.5, fract(settings.time * settings.parameter));
+    }
+
+    return vec4<f32>(mix(current.rgb, vec3<f32>(1.0), settings.amount * flash), current.a);
+}
+
+fn vignette(uv: vec2<f32>) -> vec4<f32> {
+    let current = sample_input(uv);
+    let distance = length(uv - 0.5) * sqrt(2.0);
+    let shade = smoothstep(1.0, 1.0 - settings.parameter, distance);
+
+    return vec4<f32>(current.rgb * mix(1.0, shade, settings.amount), current.a);
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    switch settings.effect {
+        case EFFECT_FEEDBACK: {
+            return feedback(in.uv);
+        }
+        case EFFECT_BLOOM: {
+            return bloom(in.uv);
+        }
+        case EFFECT_CHROMATIC_ABERRATION: {
+            return chromatic_aberration(in.uv);
+        }
+        case EFFECT_KALEIDOSCOPE: {
+            return kaleidoscope(in.uv);
+        }
+        case EFFECT_MIRROR: {
+            return mirror(in.uv);
+        }
+        case EFFECT_RGB_SPLIT: {
+            return rgb_split(in.uv);
+        }
+        case EFFECT_PIXELATE: {
+            return pixelate(in.uv);
+        }
+        case EFFECT_STROBE: {
+            return strobe(in.uv);
+        }
+        case EFFECT_VIGNETTE: {
+            return vignette(in.uv);
+        }
+        case EFFECT_COPY, default: {
+            return sample_input(in.uv);
+        }
+    }
+}
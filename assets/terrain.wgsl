@@ -0,0 +1,91 @@
+#import bevy_pbr::{
+    mesh_functions::{get_world_from_local, mesh_normal_local_to_world, mesh_position_local_to_clip},
+}
+
+const ALPASS_WAVEFORM = vec2<i32>(0, 6);
+const TERRAIN_COLUMNS = 256;
+
+struct TerrainSettings {
+    height: f32,
+    cursor: u32,
+}
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // The sample across, and how far back in the history
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_normal: vec3<f32>,
+    @location(1) magnitude: f32,
+    @location(2) age: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var audiolink_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var audiolink_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var palette_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var palette_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var history_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(5) var history_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(6) var<uniform> settings: TerrainSettings;
+
+fn waveform_sample(sample: i32) -> f32 {
+    let width = i32(textureDimensions(audiolink_texture).x);
+
+    return textureLoad(audiolink_texture, ALPASS_WAVEFORM + vec2<i32>(sample % width, sample / width), 0).x;
+}
+
+// The front row follows the live waveform, older rows scroll back through the history written with
+// each readback
+fn displacement(sample: i32, row: i32) -> f32 {
+    let sample_clamped = clamp(sample, 0, TERRAIN_COLUMNS - 1);
+    if row <= 0 {
+        return waveform_sample(sample_clamped);
+    }
+
+    let rows = i32(textureDimensions(history_texture).y);
+    let history_row = (i32(settings.cursor) - row + rows) % rows;
+
+    return textureLoad(history_texture, vec2<i32>(sample_clamped, history_row), 0).r;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let rows = i32(textureDimensions(history_texture).y);
+    let sample = i32(round(vertex.uv.x * f32(TERRAIN_COLUMNS - 1)));
+    let row = i32(round(vertex.uv.y * f32(rows - 1)));
+
+    let height = displacement(sample, row) * settings.height;
+
+    // Normals from the neighbouring heights, in the mesh's unit grid
+    let step = vec2<f32>(2.0 / f32(TERRAIN_COLUMNS - 1), 2.0 / f32(rows - 1));
+    let slope_x = (displacement(sample + 1, row) - displacement(sample - 1, row)) * settings.height / (2.0 * step.x);
+    let slope_z = (displacement(sample, row - 1) - displacement(sample, row + 1)) * settings.height / (2.0 * step.y);
+    let normal = normalize(vec3<f32>(-slope_x, 1.0, -slope_z));
+
+    let world_from_local = get_world_from_local(vertex.instance_index);
+
+    var out: VertexOutput;
+    out.position = mesh_position_local_to_clip(world_from_local, vec4<f32>(vertex.position + vec3<f32>(0.0, height, 0.0), 1.0));
+    out.world_normal = mesh_normal_local_to_world(normal, vertex.instance_index);
+    // The waveform swings both ways, so the palette follows how far it is from the middle
+    out.magnitude = clamp(abs(displacement(sample, row)), 0.0, 1.0);
+    out.age = vertex.uv.y;
+
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(palette_texture, palette_sampler, vec2<f32>(in.magnitude, 0.5)).rgb;
+    let light = 0.3 + 0.7 * max(dot(normalize(in.world_normal), normalize(vec3<f32>(-0.4, 1.0, 0.5))), 0.0);
+
+    // Older rows fade into the distance
+    let fade = 1.0 - in.age;
+
+    return vec4<f32>(color * light * fade, 1.0);
+}
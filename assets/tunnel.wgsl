@@ -0,0 +1,88 @@
+#import bevy_pbr::{
+    mesh_functions::get_world_from_local,
+    mesh_view_bindings::view,
+}
+
+const ALPASS_DFT = vec2<i32>(0, 4);
+const DFT_BINS = 240.0;
+const TWO_PI = 6.28318530718;
+
+// Stripes around and along the tube
+const STRIPES = 12.0;
+
+struct TunnelSettings {
+    travel: f32,
+    roll: f32,
+    curve: f32,
+    bulge: f32,
+    length: f32,
+}
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // Around the tube, and along it from the camera
+    @location(2) uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) magnitude: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var audiolink_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var audiolink_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var palette_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var palette_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var<uniform> settings: TunnelSettings;
+
+fn dft_magnitude(bin: i32) -> f32 {
+    let width = i32(textureDimensions(audiolink_texture).x);
+
+    return textureLoad(audiolink_texture, ALPASS_DFT + vec2<i32>(bin % width, bin / width), 0).y;
+}
+
+// Where the tunnel's center line is at a distance down it, relative to the camera's position
+fn path(distance: f32) -> vec2<f32> {
+    let z = distance + settings.travel;
+    let center = vec2<f32>(sin(z * 0.7), cos(z * 0.45)) * settings.curve;
+    let here = vec2<f32>(sin(settings.travel * 0.7), cos(settings.travel * 0.45)) * settings.curve;
+
+    return center - here;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    // Rather than moving the shared layer camera, the tunnel flows past it: the rings stay put while
+    // the pattern scrolls and the center line bends towards where the camera is heading
+    let distance = vertex.uv.y * settings.length;
+
+    // The spectrum wraps around the tube, mirrored so both halves meet without a seam
+    let around = abs(fract(vertex.uv.x + settings.roll / TWO_PI) * 2.0 - 1.0);
+    let magnitude = dft_magnitude(i32(around * (DFT_BINS - 1.0)));
+
+    // The walls swell with the spectrum further down, leaving the mouth around the camera still
+    let radius = 1.0 + magnitude * settings.bulge * vertex.uv.y;
+    let angle = vertex.uv.x * TWO_PI + settings.roll;
+    let ring = vec2<f32>(cos(angle), sin(angle)) * radius + path(distance);
+
+    let world_from_local = get_world_from_local(vertex.instance_index);
+    let world_position = world_from_local * vec4<f32>(ring, vertex.position.z, 1.0);
+
+    var out: VertexOutput;
+    out.position = view.clip_from_world * world_position;
+    out.uv = vec2<f32>(vertex.uv.x, distance + settings.travel);
+    out.magnitude = magnitude;
+
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let stripes = 0.5 + 0.5 * cos(in.uv.y * TWO_PI) * cos(in.uv.x * TWO_PI * STRIPES);
+    let color = textureSample(palette_texture, palette_sampler, vec2<f32>(fract(in.uv.y * 0.1), 0.5)).rgb;
+
+    return vec4<f32>(color * (0.2 + stripes * (0.5 + in.magnitude)), 1.0);
+}
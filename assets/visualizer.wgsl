@@ -1,45 +1,39 @@
 #import bevy_pbr::{
-    mesh_view_bindings::globals,
+    mesh_view_bindings::{globals, view},
     forward_io::VertexOutput,
 }
 
 const ALPASS_DFT = vec2<f32>(0.0, 4.0);
 const ALPASS_WAVEFORM = vec2<f32>(0.0, 6.0);
 
-const AUDIOLINK_WIDTH = 128;
-const AUDIOLINK_HEIGHT = 64;
-
 const AUDIOLINK_EXPBINS = 24;
 const AUDIOLINK_EXPOCT = 10;
 const AUDIOLINK_ETOTALBINS = (AUDIOLINK_EXPBINS * AUDIOLINK_EXPOCT);
 
-const SAMPLES_USED = 2048.0;
+const PI = 3.14159265359;
+
+struct VisualizerSettings {
+    samples_used: f32,
+    zoom_strength: f32,
+    zoom_scale: f32,
+    contrast: f32,
+    gamma: f32,
+    palette_mix: f32,
+    feedback: f32,
+}
 
 @group(#{MATERIAL_BIND_GROUP}) @binding(0) var audiolink_texture: texture_2d<f32>;
 @group(#{MATERIAL_BIND_GROUP}) @binding(1) var audiolink_sampler: sampler;
-
-fn oklab_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
-    let L = c.x;
-    let a = c.y;
-    let b = c.z;
-
-    let l_ = L + 0.3963377774 * a + 0.2158037573 * b;
-    let m_ = L - 0.1055613458 * a - 0.0638541728 * b;
-    let s_ = L - 0.0894841775 * a - 1.2914855480 * b;
-
-    let l = l_ * l_ * l_;
-    let m = m_ * m_ * m_;
-    let s = s_ * s_ * s_;
-
-    return vec3<f32>(
-        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
-        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
-        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
-    );
-}
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var<uniform> settings: VisualizerSettings;
+@group(#{MATERIAL_BIND_GROUP}) @binding(3) var palette_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(4) var palette_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(5) var previous_frame: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(6) var previous_frame_sampler: sampler;
 
 fn audiolink_sample_multiline(xycoord: vec2<f32>) -> vec4<f32> {
-    return textureSample(audiolink_texture, audiolink_sampler, vec2<f32>((xycoord.x % AUDIOLINK_WIDTH) / AUDIOLINK_WIDTH, (xycoord.y + xycoord.x / AUDIOLINK_WIDTH) / AUDIOLINK_HEIGHT));
+    let size = vec2<f32>(textureDimensions(audiolink_texture));
+
+    return textureSample(audiolink_texture, audiolink_sampler, vec2<f32>((xycoord.x % size.x) / size.x, (xycoord.y + xycoord.x / size.x) / size.y));
 }
 
 fn audiolink_sample_lerp_multiline(xy: vec2<f32>) -> vec4<f32> {
@@ -54,56 +48,72 @@ fn get_audio_power(uv: vec2<f32>) -> vec3<f32> {
     }
 
     lf_power /= AUDIOLINK_ETOTALBINS / 3.0;
-    lf_power = clamp(lf_power * 5, 0.0, 1.0);
+    lf_power = clamp(lf_power * settings.zoom_strength, 0.0, 1.0);
+
+    let samples_used = settings.samples_used;
 
     let tuv: vec2<f32> = uv;
-    let tuvp: vec2<f32> = mix(uv * 0.1 + 0.45, uv, lf_power);
+    let tuvp: vec2<f32> = mix(uv * settings.zoom_scale + (1.0 - settings.zoom_scale) * 0.5, uv, lf_power);
 
     let col: vec3<f32> = vec3<f32>(
         (((
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * SAMPLES_USED) % SAMPLES_USED, 0)).rrr
-            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * SAMPLES_USED) % SAMPLES_USED, 0)).ggg
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * samples_used) % samples_used, 0)).rrr
+            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * samples_used) % samples_used, 0)).ggg
         )
         -
         (
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.y * SAMPLES_USED) % SAMPLES_USED, 0)).rrr
-            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.y * SAMPLES_USED) % SAMPLES_USED, 0)).ggg
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.y * samples_used) % samples_used, 0)).rrr
+            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.y * samples_used) % samples_used, 0)).ggg
         )) +
         ((
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * SAMPLES_USED + tuv.y * SAMPLES_USED) % SAMPLES_USED, 0)).bbb
-            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * SAMPLES_USED + tuv.y * SAMPLES_USED) % SAMPLES_USED, 0)).aaa
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * samples_used + tuv.y * samples_used) % samples_used, 0)).bbb
+            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * samples_used + tuv.y * samples_used) % samples_used, 0)).aaa
         )
         -
         (
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * SAMPLES_USED - tuv.y * SAMPLES_USED + SAMPLES_USED) % SAMPLES_USED, 0)).bbb
-            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * SAMPLES_USED - tuv.y * SAMPLES_USED + SAMPLES_USED) % SAMPLES_USED, 0)).aaa
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * samples_used - tuv.y * samples_used + samples_used) % samples_used, 0)).bbb
+            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuv.x * samples_used - tuv.y * samples_used + samples_used) % samples_used, 0)).aaa
         ))) * 0.5 +
         (((
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * SAMPLES_USED) % SAMPLES_USED, 0)).rrr
-            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * SAMPLES_USED) % SAMPLES_USED, 0)).ggg
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * samples_used) % samples_used, 0)).rrr
+            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * samples_used) % samples_used, 0)).ggg
         )
         -
         (
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.y * SAMPLES_USED) % SAMPLES_USED, 0)).rrr
-            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.y * SAMPLES_USED) % SAMPLES_USED, 0)).ggg
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.y * samples_used) % samples_used, 0)).rrr
+            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.y * samples_used) % samples_used, 0)).ggg
         )) +
         ((
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * SAMPLES_USED + tuvp.y * SAMPLES_USED) % SAMPLES_USED, 0)).bbb
-            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * SAMPLES_USED + tuvp.y * SAMPLES_USED) % SAMPLES_USED, 0)).aaa
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * samples_used + tuvp.y * samples_used) % samples_used, 0)).bbb
+            - audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * samples_used + tuvp.y * samples_used) % samples_used, 0)).aaa
         )
         -
         (
-            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * SAMPLES_USED - tuvp.y * SAMPLES_USED + SAMPLES_USED) % SAMPLES_USED, 0)).bbb
-            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * SAMPLES_USED - tuvp.y * SAMPLES_USED + SAMPLES_USED) % SAMPLES_USED, 0)).aaa
+            audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * samples_used - tuvp.y * samples_used + samples_used) % samples_used, 0)).bbb
+            + audiolink_sample_lerp_multiline(ALPASS_WAVEFORM + vec2<f32>((tuvp.x * samples_used - tuvp.y * samples_used + samples_used) % samples_used, 0)).aaa
         ))) * 0.5
     );
 
-    return pow(clamp(col, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0)), vec3<f32>(2.0, 2.0, 2.0));
+    let contrasted = pow(clamp(col, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(settings.contrast));
+
+    return pow(contrasted, vec3<f32>(1.0 / settings.gamma));
+}
+
+fn apply_palette(power: vec3<f32>) -> vec3<f32> {
+    let intensity = clamp(dot(power, vec3<f32>(0.2126, 0.7152, 0.0722)), 0.0, 1.0);
+    let mapped = textureSample(palette_texture, palette_sampler, vec2<f32>(intensity, 0.5)).rgb * intensity;
+
+    return mix(power, mapped, settings.palette_mix);
 }
 
 @fragment
 fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
     let power: vec3<f32> = get_audio_power(in.uv);
 
-    return vec4<f32>(power, 1.0);
+    // The previous frame covers the whole canvas, so look it up by where the fragment falls in the
+    // view, whatever size the camera renders at
+    let screen_uv = (in.position.xy - view.viewport.xy) / view.viewport.zw;
+    let previous = textureSample(previous_frame, previous_frame_sampler, screen_uv).rgb;
+
+    return vec4<f32>(max(apply_palette(power), previous * settings.feedback), 1.0);
 }
@@ -0,0 +1,44 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+struct WarpSettings {
+    region: vec4<f32>,
+    blend_edges: vec4<f32>,
+    blend_power: f32,
+    blend_gamma: f32,
+}
+
+@group(#{MATERIAL_BIND_GROUP}) @binding(0) var canvas_texture: texture_2d<f32>;
+@group(#{MATERIAL_BIND_GROUP}) @binding(1) var canvas_sampler: sampler;
+@group(#{MATERIAL_BIND_GROUP}) @binding(2) var<uniform> settings: WarpSettings;
+
+// Symmetric ramp so that two overlapping outputs always sum to one
+fn blend_ramp(distance: f32, width: f32) -> f32 {
+    if width <= 0.0 {
+        return 1.0;
+    }
+
+    let t = clamp(distance / width, 0.0, 1.0);
+
+    if t < 0.5 {
+        return 0.5 * pow(2.0 * t, settings.blend_power);
+    }
+
+    return 1.0 - 0.5 * pow(2.0 * (1.0 - t), settings.blend_power);
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let canvas_uv = mix(settings.region.xy, settings.region.zw, in.uv);
+
+    let color = textureSample(canvas_texture, canvas_sampler, canvas_uv);
+
+    let blend = blend_ramp(in.uv.x, settings.blend_edges.x)
+        * blend_ramp(1.0 - in.uv.x, settings.blend_edges.y)
+        * blend_ramp(in.uv.y, settings.blend_edges.z)
+        * blend_ramp(1.0 - in.uv.y, settings.blend_edges.w);
+
+    // The surface encodes to sRGB, so compensate for the projector's own response curve
+    let light = pow(blend, 2.2 / settings.blend_gamma);
+
+    return vec4<f32>(color.rgb * light, color.a);
+}
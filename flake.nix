@@ -42,6 +42,7 @@
             vulkan-loader
             vulkan-tools
             pipewire
+            ffmpeg
             xorg.libX11
             xorg.libXcursor
             xorg.libXi
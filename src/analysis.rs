@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audiolink::{AudiolinkReadback, DFT_BINS},
+    tempo::{Beat, BeatGrid},
+};
+
+pub const DFT_FIRST_ROW: u32 = 4;
+
+// Band crossovers as a fraction of the DFT bins, matching the AudioLink defaults
+pub const BAND_CROSSOVERS: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+pub const BEAT_THRESHOLD: f32 = 1.4;
+pub const BEAT_MIN_INTERVAL: f32 = 0.25;
+pub const BEAT_PULSE_DECAY: f32 = 6.0;
+pub const BASS_AVERAGE_DECAY: f32 = 1.5;
+
+// Beat intervals outside this tempo range are ignored by the BPM estimate
+pub const DEFAULT_BPM: f32 = 120.0;
+pub const MIN_BPM: f32 = 60.0;
+pub const MAX_BPM: f32 = 200.0;
+pub const BPM_SMOOTHING: f32 = 0.2;
+
+pub struct AnalysisPlugin;
+
+#[derive(Resource, Debug)]
+pub struct AudioAnalysis {
+    pub bands: [f32; 4],
+    pub level: f32,
+    pub beat: bool,
+    pub beat_pulse: f32,
+    pub beats: u64,
+    // The beat grid's tempo, which follows `detected_bpm` unless tapped or set
+    pub bpm: f32,
+    pub detected_bpm: f32,
+    // How far into the current beat, held just short of 1 until a detected beat arrives
+    pub beat_phase: f32,
+    bass_average: f32,
+    since_onset: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Bass,
+    LowMid,
+    HighMid,
+    Treble,
+    Level,
+    Beat,
+}
+
+impl Default for AudioAnalysis {
+    fn default() -> Self {
+        Self {
+            bands: [0.0; 4],
+            level: 0.0,
+            beat: false,
+            beat_pulse: 0.0,
+            beats: 0,
+            bpm: DEFAULT_BPM,
+            detected_bpm: DEFAULT_BPM,
+            beat_phase: 0.0,
+            bass_average: 0.0,
+            since_onset: 0.0,
+        }
+    }
+}
+
+impl AudioAnalysis {
+    pub fn get(&self, signal: Signal) -> f32 {
+        match signal {
+            Signal::Bass => self.bands[0],
+            Signal::LowMid => self.bands[1],
+            Signal::HighMid => self.bands[2],
+            Signal::Treble => self.bands[3],
+            Signal::Level => self.level,
+            Signal::Beat => self.beat_pulse,
+        }
+        .clamp(0.0, 1.0)
+    }
+}
+
+impl Plugin for AnalysisPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioAnalysis>()
+            .add_systems(Update, update.after(crate::audiolink::update_readback));
+    }
+}
+
+pub fn update(
+    mut analysis: ResMut<AudioAnalysis>,
+    mut beat_grid: ResMut<BeatGrid>,
+    mut beats: MessageWriter<Beat>,
+    audiolink_readback: Res<AudiolinkReadback>,
+    time: Res<Time>,
+) {
+    let delta_time = time.delta_secs();
+    let width = audiolink_readback.width.max(1);
+
+    let bins: Vec<f32> = (0..DFT_BINS)
+        .filter_map(|bin| audiolink_readback.get(bin % width, DFT_FIRST_ROW + bin / width))
+        .map(|texel| texel.y)
+        .collect();
+
+    if bins.len() != DFT_BINS as usize {
+        return;
+    }
+
+    for (band, crossovers) in BAND_CROSSOVERS.windows(2).enumerate() {
+        let first = (crossovers[0] * DFT_BINS as f32) as usize;
+        let last = (crossovers[1] * DFT_BINS as f32) as usize;
+
+        analysis.bands[band] = bins[first..last].iter().sum::<f32>() / (last - first) as f32;
+    }
+    analysis.level = bins.iter().sum::<f32>() / bins.len() as f32;
+
+    let bass = analysis.bands[0];
+    analysis.bass_average +=
+        (bass - analysis.bass_average) * (BASS_AVERAGE_DECAY * delta_time).min(1.0);
+    analysis.since_onset += delta_time;
+
+    let onset = bass > analysis.bass_average * BEAT_THRESHOLD
+        && bass > f32::EPSILON
+        && analysis.since_onset >= BEAT_MIN_INTERVAL;
+
+    if onset {
+        let interval = analysis.since_onset;
+        if (60.0 / MAX_BPM..=60.0 / MIN_BPM).contains(&interval) {
+            analysis.detected_bpm += (60.0 / interval - analysis.detected_bpm) * BPM_SMOOTHING;
+        }
+        analysis.since_onset = 0.0;
+    }
+
+    // Onsets only become beats through the grid, which may be running from taps instead
+    analysis.beat = beat_grid.advance(onset, analysis.detected_bpm, delta_time);
+    analysis.bpm = beat_grid.bpm;
+    analysis.beat_phase = beat_grid.phase();
+
+    if analysis.beat {
+        analysis.beats = beat_grid.last_beat as u64;
+        analysis.beat_pulse = 1.0;
+        beats.write(Beat {
+            index: analysis.beats,
+            bpm: analysis.bpm,
+        });
+    } else {
+        analysis.beat_pulse *= (-BEAT_PULSE_DECAY * delta_time).exp();
+    }
+}
@@ -5,6 +5,7 @@ use bevy::{
     render::{
         Render, RenderApp, RenderStartup, RenderSystems,
         extract_resource::{ExtractResource, ExtractResourcePlugin},
+        gpu_readback::{Readback, ReadbackComplete},
         graph::CameraDriverLabel,
         render_asset::RenderAssets,
         render_graph::{self, RenderGraph, RenderLabel},
@@ -15,22 +16,40 @@ use bevy::{
         renderer::{RenderContext, RenderDevice, RenderQueue},
         texture::GpuImage,
     },
-    shader::PipelineCacheError,
+    shader::{PipelineCacheError, ShaderDefVal},
 };
 use colored_text::Colorize;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 
-use crate::pipewire::PipewireInput;
+use crate::{parameters::Parameters, pipewire::PipewireInput};
 
 pub const SHADER_ASSET_PATH: &str = "audiolink.wgsl";
 
-pub const SAMPLE_HISTORY: usize = 4096;
+pub const DEFAULT_SAMPLE_HISTORY: usize = 4096;
 
-pub const AUDIOLINK_WIDTH: u32 = 128;
-pub const AUDIOLINK_HEIGHT: u32 = 64;
+pub const DEFAULT_AUDIOLINK_WIDTH: u32 = 128;
+pub const DEFAULT_AUDIOLINK_HEIGHT: u32 = 64;
 
 pub const WORKGROUP_SIZE: u32 = 8;
 
+pub const DFT_WINDOW_SAMPLES: usize = 3069;
+pub const DFT_BINS: u32 = 240;
+pub const USED_ROWS: u32 = 24;
+pub const WAVEFORM_FIRST_ROW: u32 = 6;
+// LFOs and envelopes from `generators`, one texel each
+pub const GENERATOR_ROW: u32 = 22;
+// The beat grid in the first texel: phase, BPM, beat count and beat pulse
+pub const TEMPO_ROW: u32 = 23;
+
+const READBACK_ROW_ALIGNMENT: u32 = 16;
+
+pub struct AudiolinkRegion {
+    pub name: &'static str,
+    pub first_row: u32,
+    pub last_row: u32,
+}
+
 #[derive(Component)]
 pub struct Audiolink {
     pub cursor_move: bool,
@@ -46,7 +65,18 @@ pub struct Audiolink {
     pub right_half_rate_buffer: Vec<f32>,
 }
 
-pub struct AudiolinkComputePlugin;
+pub struct AudiolinkComputePlugin {
+    pub settings: AudiolinkSettings,
+}
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize, ExtractResource)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudiolinkSettings {
+    pub sample_history: usize,
+    pub width: u32,
+    pub height: u32,
+    pub uniforms: AudiolinkUniforms,
+}
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
 pub struct AudiolinkLabel;
@@ -57,18 +87,41 @@ pub struct AudiolinkImages {
     texture_b: Handle<Image>,
 }
 
-#[derive(Clone, Resource)]
+#[derive(Clone, Resource, ExtractResource)]
 pub struct AudiolinkDataTexture(pub Handle<Image>);
 
+#[derive(Resource, Default)]
+pub struct AudiolinkReadback {
+    pub width: u32,
+    pub row_stride: u32,
+    pub texels: Vec<Vec4>,
+}
+
+#[derive(Component)]
+pub struct AudiolinkReadbackTarget;
+
+#[derive(Resource, Clone, ExtractResource)]
+pub struct AudiolinkAudioData(Vec<[f32; 4]>);
+
+// Always one entry per texel of the row, so the buffer is never empty
 #[derive(Resource, Clone, ExtractResource)]
-pub struct AudiolinkAudioData([[f32; 4]; SAMPLE_HISTORY]);
+pub struct AudiolinkGeneratorData(pub Vec<[f32; 4]>);
+
+#[derive(Resource, Clone, Debug, Default, ExtractResource, ShaderType)]
+pub struct AudiolinkTempo {
+    pub phase: f32,
+    pub bpm: f32,
+    pub beats: f32,
+    pub pulse: f32,
+}
 
-#[derive(Resource, Clone, ExtractResource, ShaderType)]
+#[derive(Resource, Clone, Debug, ExtractResource, ShaderType, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct AudiolinkUniforms {
-    gain: f32,
-    bass: f32,
-    trebble: f32,
-    fade_length: f32,
+    pub gain: f32,
+    pub bass: f32,
+    pub trebble: f32,
+    pub fade_length: f32,
 }
 
 #[derive(Resource)]
@@ -91,6 +144,65 @@ pub struct AudiolinkNode {
     state: AudiolinkState,
 }
 
+impl Default for AudiolinkSettings {
+    fn default() -> Self {
+        Self {
+            sample_history: DEFAULT_SAMPLE_HISTORY,
+            width: DEFAULT_AUDIOLINK_WIDTH,
+            height: DEFAULT_AUDIOLINK_HEIGHT,
+            uniforms: AudiolinkUniforms::default(),
+        }
+    }
+}
+
+impl AudiolinkSettings {
+    pub fn regions(&self) -> [AudiolinkRegion; 6] {
+        [
+            AudiolinkRegion {
+                name: "Bands (reserved)",
+                first_row: 0,
+                last_row: 3,
+            },
+            AudiolinkRegion {
+                name: "DFT",
+                first_row: 4,
+                last_row: WAVEFORM_FIRST_ROW - 1,
+            },
+            AudiolinkRegion {
+                name: "Waveform",
+                first_row: WAVEFORM_FIRST_ROW,
+                last_row: GENERATOR_ROW - 1,
+            },
+            AudiolinkRegion {
+                name: "Generators",
+                first_row: GENERATOR_ROW,
+                last_row: GENERATOR_ROW,
+            },
+            AudiolinkRegion {
+                name: "Tempo",
+                first_row: TEMPO_ROW,
+                last_row: TEMPO_ROW,
+            },
+            AudiolinkRegion {
+                name: "Unused",
+                first_row: USED_ROWS,
+                last_row: self.height - 1,
+            },
+        ]
+    }
+}
+
+impl Default for AudiolinkUniforms {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            bass: 1.0,
+            trebble: 1.0,
+            fade_length: 0.8,
+        }
+    }
+}
+
 impl Default for AudiolinkNode {
     fn default() -> Self {
         Self {
@@ -101,16 +213,29 @@ impl Default for AudiolinkNode {
 
 impl Plugin for AudiolinkComputePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((
-            ExtractResourcePlugin::<AudiolinkImages>::default(),
-            ExtractResourcePlugin::<AudiolinkAudioData>::default(),
-            ExtractResourcePlugin::<AudiolinkUniforms>::default(),
-        ))
-        .add_systems(Startup, setup)
-        .add_systems(Update, update);
+        app.insert_resource(self.settings.clone())
+            .add_plugins((
+                ExtractResourcePlugin::<AudiolinkImages>::default(),
+                ExtractResourcePlugin::<AudiolinkAudioData>::default(),
+                ExtractResourcePlugin::<AudiolinkGeneratorData>::default(),
+                ExtractResourcePlugin::<AudiolinkUniforms>::default(),
+                ExtractResourcePlugin::<AudiolinkTempo>::default(),
+            ))
+            .init_resource::<AudiolinkReadback>()
+            .init_resource::<AudiolinkTempo>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    apply_parameters,
+                    update.after(apply_parameters),
+                    update_readback.after(update),
+                ),
+            );
 
         let audiolink_render_app = app.sub_app_mut(RenderApp);
         audiolink_render_app
+            .insert_resource(self.settings.clone())
             .add_systems(RenderStartup, init_audiolink_pipeline)
             .add_systems(
                 Render,
@@ -130,6 +255,7 @@ fn init_audiolink_pipeline(
     asset_server: Res<AssetServer>,
     pipeline_cache: Res<PipelineCache>,
     render_device: Res<RenderDevice>,
+    audiolink_settings: Res<AudiolinkSettings>,
 ) {
     let texture_bind_group_layout = render_device.create_bind_group_layout(
         "AudiolinkImages",
@@ -138,17 +264,25 @@ fn init_audiolink_pipeline(
             (
                 texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
                 texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
-                storage_buffer_read_only::<[[f32; 4]; SAMPLE_HISTORY]>(false),
+                storage_buffer_read_only::<Vec<[f32; 4]>>(false),
                 uniform_buffer::<AudiolinkUniforms>(false),
+                storage_buffer_read_only::<Vec<[f32; 4]>>(false),
+                uniform_buffer::<AudiolinkTempo>(false),
             ),
         ),
     );
 
     let shader = asset_server.load(SHADER_ASSET_PATH);
 
+    let shader_defs = vec![ShaderDefVal::Int(
+        "AUDIOLINK_WIDTH".into(),
+        audiolink_settings.width as i32,
+    )];
+
     let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
         layout: vec![texture_bind_group_layout.clone()],
         shader: shader.clone(),
+        shader_defs: shader_defs.clone(),
         entry_point: Some(Cow::from("init")),
         ..default()
     });
@@ -156,6 +290,7 @@ fn init_audiolink_pipeline(
     let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
         layout: vec![texture_bind_group_layout.clone()],
         shader,
+        shader_defs,
         entry_point: Some(Cow::from("update")),
         ..default()
     });
@@ -174,14 +309,16 @@ fn prepare_bind_group(
     gpu_images: Res<RenderAssets<GpuImage>>,
     audiolink_images: Res<AudiolinkImages>,
     audiolink_audio_data: Res<AudiolinkAudioData>,
+    audiolink_generator_data: Res<AudiolinkGeneratorData>,
     audiolink_uniforms: Res<AudiolinkUniforms>,
+    audiolink_tempo: Res<AudiolinkTempo>,
     render_device: Res<RenderDevice>,
     queue: Res<RenderQueue>,
 ) {
     let view_a = gpu_images.get(&audiolink_images.texture_a).unwrap();
     let view_b = gpu_images.get(&audiolink_images.texture_b).unwrap();
 
-    let mut audio_buffer = StorageBuffer::from(audiolink_audio_data.0);
+    let mut audio_buffer = StorageBuffer::from(audiolink_audio_data.0.clone());
 
     audio_buffer.write_buffer(&render_device, &queue);
 
@@ -189,6 +326,14 @@ fn prepare_bind_group(
 
     uniform_buffer.write_buffer(&render_device, &queue);
 
+    let mut generator_buffer = StorageBuffer::from(audiolink_generator_data.0.clone());
+
+    generator_buffer.write_buffer(&render_device, &queue);
+
+    let mut tempo_buffer = UniformBuffer::from(audiolink_tempo.into_inner());
+
+    tempo_buffer.write_buffer(&render_device, &queue);
+
     let bind_group_a_to_b = render_device.create_bind_group(
         None,
         &pipeline.texture_bind_group_layout,
@@ -197,6 +342,8 @@ fn prepare_bind_group(
             &view_b.texture_view,
             &audio_buffer,
             &uniform_buffer,
+            &generator_buffer,
+            &tempo_buffer,
         )),
     );
     let bind_group_b_to_a = render_device.create_bind_group(
@@ -207,6 +354,8 @@ fn prepare_bind_group(
             &view_a.texture_view,
             &audio_buffer,
             &uniform_buffer,
+            &generator_buffer,
+            &tempo_buffer,
         )),
     );
 
@@ -216,15 +365,22 @@ fn prepare_bind_group(
     ]));
 }
 
-pub fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+pub fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut parameters: ResMut<Parameters>,
+    audiolink_settings: Res<AudiolinkSettings>,
+) {
     let mut image = Image::new_target_texture(
-        AUDIOLINK_WIDTH,
-        AUDIOLINK_HEIGHT,
+        audiolink_settings.width,
+        audiolink_settings.height,
         TextureFormat::Rgba32Float,
     );
     image.asset_usage = RenderAssetUsages::RENDER_WORLD;
-    image.texture_descriptor.usage =
-        TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING;
+    image.texture_descriptor.usage = TextureUsages::COPY_DST
+        | TextureUsages::COPY_SRC
+        | TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING;
     image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
         label: Some("audiolink data texture image sampler".to_owned()),
         address_mode_u: ImageAddressMode::Repeat,
@@ -244,18 +400,46 @@ pub fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
 
     commands.insert_resource(AudiolinkDataTexture(image_a.clone()));
 
+    commands
+        .spawn((Readback::texture(image_a.clone()), AudiolinkReadbackTarget))
+        .observe(store_readback);
+
     commands.insert_resource(AudiolinkImages {
         texture_a: image_a,
         texture_b: image_b,
     });
 
-    commands.insert_resource(AudiolinkAudioData([[0.0; 4]; SAMPLE_HISTORY]));
+    commands.insert_resource(AudiolinkAudioData(vec![
+        [0.0; 4];
+        audiolink_settings.sample_history
+    ]));
+    commands.insert_resource(AudiolinkGeneratorData(vec![
+        [0.0; 4];
+        audiolink_settings.width
+            as usize
+    ]));
+
+    let uniforms = &audiolink_settings.uniforms;
+    parameters.register("audiolink.gain", uniforms.gain, 0.0, 4.0, 0.05);
+    parameters.register("audiolink.bass", uniforms.bass, 0.0, 4.0, 0.05);
+    parameters.register("audiolink.trebble", uniforms.trebble, 0.0, 4.0, 0.05);
+    parameters.register(
+        "audiolink.fade_length",
+        uniforms.fade_length,
+        0.0,
+        1.0,
+        0.05,
+    );
+
+    commands.insert_resource(uniforms.clone());
 
-    commands.insert_resource(AudiolinkUniforms {
-        gain: 1.0,
-        bass: 1.0,
-        trebble: 1.0,
-        fade_length: 0.8,
+    commands.insert_resource(AudiolinkReadback {
+        width: audiolink_settings.width,
+        // Readback rows are padded to the 256 byte copy alignment
+        row_stride: audiolink_settings
+            .width
+            .next_multiple_of(READBACK_ROW_ALIGNMENT),
+        texels: Vec::new(),
     });
 
     commands.spawn(Audiolink {
@@ -264,13 +448,13 @@ pub fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
         left_smoothed_max: 0.0,
 
         left_on_alternate_sample: false,
-        left_full_rate_buffer: vec![0.0; SAMPLE_HISTORY],
-        left_half_rate_buffer: vec![0.0; SAMPLE_HISTORY],
+        left_full_rate_buffer: vec![0.0; audiolink_settings.sample_history],
+        left_half_rate_buffer: vec![0.0; audiolink_settings.sample_history],
 
         right_smoothed_max: 0.0,
         right_on_alternate_sample: false,
-        right_full_rate_buffer: vec![0.0; SAMPLE_HISTORY],
-        right_half_rate_buffer: vec![0.0; SAMPLE_HISTORY],
+        right_full_rate_buffer: vec![0.0; audiolink_settings.sample_history],
+        right_half_rate_buffer: vec![0.0; audiolink_settings.sample_history],
     });
 }
 
@@ -282,6 +466,7 @@ pub fn update(
     time: Res<Time>,
     pipewire_input: NonSend<PipewireInput>,
     images: Res<AudiolinkImages>,
+    audiolink_settings: Res<AudiolinkSettings>,
 ) {
     if audiolink_data_texture.0 == images.texture_a {
         audiolink_data_texture.0 = images.texture_b.clone();
@@ -344,10 +529,10 @@ pub fn update(
     let mut left_max: f32 = 0.0;
     let mut right_max: f32 = 0.0;
 
-    let mut new_audiolink_data_audio_data: [[f32; 4]; SAMPLE_HISTORY] = [[0.0; 4]; SAMPLE_HISTORY];
+    let mut new_audiolink_data_audio_data = vec![[0.0; 4]; audiolink_settings.sample_history];
 
     #[allow(clippy::needless_range_loop)]
-    for i in 0..SAMPLE_HISTORY {
+    for i in 0..audiolink_settings.sample_history {
         let mut left_full_sample = 0.0;
         let mut right_full_sample = 0.0;
 
@@ -398,6 +583,59 @@ pub fn update(
     audiolink.cursor_move = true;
 }
 
+pub fn apply_parameters(
+    mut audiolink_uniforms: ResMut<AudiolinkUniforms>,
+    parameters: Res<Parameters>,
+) {
+    if !parameters.is_changed() {
+        return;
+    }
+
+    let uniforms = audiolink_uniforms.as_mut();
+    for (name, value) in [
+        ("audiolink.gain", &mut uniforms.gain),
+        ("audiolink.bass", &mut uniforms.bass),
+        ("audiolink.trebble", &mut uniforms.trebble),
+        ("audiolink.fade_length", &mut uniforms.fade_length),
+    ] {
+        if let Some(parameter) = parameters.get(name) {
+            *value = parameter;
+        }
+    }
+}
+
+pub fn update_readback(
+    mut readback: Single<&mut Readback, With<AudiolinkReadbackTarget>>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+) {
+    **readback = Readback::texture(audiolink_data_texture.0.clone());
+}
+
+fn store_readback(
+    readback: On<ReadbackComplete>,
+    mut audiolink_readback: ResMut<AudiolinkReadback>,
+) {
+    audiolink_readback.texels = readback
+        .data
+        .chunks_exact(16)
+        .map(|texel| {
+            Vec4::from_array(std::array::from_fn(|channel| {
+                f32::from_le_bytes(texel[channel * 4..channel * 4 + 4].try_into().unwrap())
+            }))
+        })
+        .collect();
+}
+
+impl AudiolinkReadback {
+    pub fn get(&self, x: u32, y: u32) -> Option<Vec4> {
+        if x >= self.width {
+            return None;
+        }
+
+        self.texels.get((y * self.row_stride + x) as usize).copied()
+    }
+}
+
 fn print_vu(name: &str, max: f32, smoothed_max: &mut f32, delta_time: f32) {
     *smoothed_max = max.max(*smoothed_max - 0.3 * delta_time);
 
@@ -489,6 +727,7 @@ impl render_graph::Node for AudiolinkNode {
         world: &World,
     ) -> Result<(), render_graph::NodeRunError> {
         let bind_groups = &world.resource::<AudiolinkImageBindGroups>().0;
+        let audiolink_settings = world.resource::<AudiolinkSettings>();
         let pipeline_cache = world.resource::<PipelineCache>();
         let pipeline = world.resource::<AudiolinkPipeline>();
 
@@ -506,8 +745,8 @@ impl render_graph::Node for AudiolinkNode {
                 pass.set_bind_group(0, &bind_groups[0], &[]);
                 pass.set_pipeline(init_pipeline);
                 pass.dispatch_workgroups(
-                    AUDIOLINK_WIDTH / WORKGROUP_SIZE,
-                    AUDIOLINK_HEIGHT / WORKGROUP_SIZE,
+                    audiolink_settings.width / WORKGROUP_SIZE,
+                    audiolink_settings.height / WORKGROUP_SIZE,
                     1,
                 );
             }
@@ -519,8 +758,8 @@ impl render_graph::Node for AudiolinkNode {
                 pass.set_bind_group(0, &bind_groups[index], &[]);
                 pass.set_pipeline(update_pipeline);
                 pass.dispatch_workgroups(
-                    AUDIOLINK_WIDTH / WORKGROUP_SIZE,
-                    AUDIOLINK_HEIGHT / WORKGROUP_SIZE,
+                    audiolink_settings.width / WORKGROUP_SIZE,
+                    audiolink_settings.height / WORKGROUP_SIZE,
                     1,
                 );
             }
@@ -0,0 +1,957 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fmt, fs,
+    path::{Component, Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audiolink::{AudiolinkSettings, DFT_BINS, DFT_WINDOW_SAMPLES, USED_ROWS, WORKGROUP_SIZE},
+    control::ControlConfig,
+    generator::{EnvelopeTrigger, GeneratorsConfig},
+    geometry::GeometryConfig,
+    layer::{LayerConfig, LayerSource},
+    link::LinkConfig,
+    logo::{LogoConfig, LogoEntry},
+    media::MediaConfig,
+    midi::{MidiConfig, MidiControl, MidiMapping},
+    model::ModelConfig,
+    modulation::{ModulationConfig, ModulationRoute},
+    output::{OutputsConfig, Warp},
+    palette::PaletteConfig,
+    particles::ParticlesConfig,
+    pipewire::PipewireConfig,
+    post::{PostConfig, PostEffect},
+    preset::{self, PresetConfig},
+    remote::RemoteConfig,
+    scene::AVAILABLE_SCENES,
+    tempo::TempoConfig,
+    text::TextConfig,
+    visualizer::VisualizerConfig,
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "vj.ron";
+// Saved next to the config, vj.ron keeping its state in vj.state.ron
+pub const STATE_EXTENSION: &str = "state.ron";
+
+// Files read outside the asset server, such as palettes and clips, resolve relative to this
+pub const ASSET_DIRECTORY: &str = "assets";
+
+// Paths that arrive at runtime must stay inside the assets
+pub fn is_asset_path(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+const USAGE: &str = "Usage: vj-visualiser [OPTIONS]
+
+Options:
+  --config <PATH>          Configuration file (default: vj.ron)
+  --input <NODE>           PipeWire node to capture from
+  --fullscreen             Make the first output borderless fullscreen
+  --monitor <INDEX>        Monitor used by --fullscreen (default: 0)
+  --canvas <WIDTHxHEIGHT>  Fixed canvas size shared by all outputs
+  --logo <PATH>            Show a single logo instead of the playlist
+  --no-control             Do not open the control window
+  --help                   Print this message";
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub audio: PipewireConfig,
+    pub analysis: AudiolinkSettings,
+    pub scenes: Vec<String>,
+    pub layers: Vec<LayerConfig>,
+    pub media: BTreeMap<String, MediaConfig>,
+    pub visualizer: VisualizerConfig,
+    pub particles: ParticlesConfig,
+    pub geometry: GeometryConfig,
+    pub models: BTreeMap<String, ModelConfig>,
+    pub logo: LogoConfig,
+    pub text: TextConfig,
+    pub palette: PaletteConfig,
+    pub post: PostConfig,
+    pub outputs: OutputsConfig,
+    pub control: ControlConfig,
+    pub remote: RemoteConfig,
+    pub keys: KeyBindings,
+    pub parameters: BTreeMap<String, f32>,
+    pub tempo: TempoConfig,
+    pub link: LinkConfig,
+    pub midi: MidiConfig,
+    pub generators: GeneratorsConfig,
+    pub modulation: ModulationConfig,
+    pub preset: PresetConfig,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct ConfigPath(pub PathBuf);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub cue: Vec<KeyCode>,
+    pub take: KeyCode,
+    pub warp_edit: KeyCode,
+    pub warp_next_output: KeyCode,
+    pub warp_toggle_mesh: KeyCode,
+    pub warp_reset: KeyCode,
+    pub debug_overlay: KeyCode,
+    pub debug_channel: KeyCode,
+    pub debug_scale_up: KeyCode,
+    pub debug_scale_down: KeyCode,
+    pub logo_select: Vec<KeyCode>,
+    pub logo_next: KeyCode,
+    pub palette_next: KeyCode,
+    pub post_select: KeyCode,
+    pub post_move_earlier: KeyCode,
+    pub post_move_later: KeyCode,
+    pub clip_next: KeyCode,
+    pub tempo_tap: KeyCode,
+    pub tempo_resync: KeyCode,
+    pub tempo_nudge_earlier: KeyCode,
+    pub tempo_nudge_later: KeyCode,
+    pub tempo_mode: KeyCode,
+    pub midi_learn: KeyCode,
+    pub parameter_previous: KeyCode,
+    pub parameter_next: KeyCode,
+    pub parameter_increase: KeyCode,
+    pub parameter_decrease: KeyCode,
+    pub parameter_reset: KeyCode,
+    pub preset_recall: Vec<KeyCode>,
+    // Held while pressing a preset_recall key to save to that slot instead
+    pub preset_save: KeyCode,
+}
+
+#[derive(Default)]
+pub struct Cli {
+    pub config_path: Option<PathBuf>,
+    pub input: Option<String>,
+    pub fullscreen: bool,
+    pub monitor: Option<usize>,
+    pub canvas_size: Option<UVec2>,
+    pub logo: Option<String>,
+    pub no_control: bool,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Cli(String),
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, ron::error::SpannedError),
+    Invalid { key: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Cli(message) => write!(f, "{message}\n\n{USAGE}"),
+            ConfigError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ConfigError::Parse(path, err) => write!(f, "{}:{err}", path.display()),
+            ConfigError::Invalid { key, message } => write!(f, "{key}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            audio: PipewireConfig::default(),
+            analysis: AudiolinkSettings::default(),
+            scenes: AVAILABLE_SCENES.map(str::to_owned).to_vec(),
+            layers: LayerConfig::defaults(),
+            media: BTreeMap::new(),
+            visualizer: VisualizerConfig::default(),
+            particles: ParticlesConfig::default(),
+            geometry: GeometryConfig::default(),
+            models: BTreeMap::new(),
+            logo: LogoConfig::default(),
+            text: TextConfig::default(),
+            palette: PaletteConfig::default(),
+            post: PostConfig::default(),
+            outputs: OutputsConfig::default(),
+            control: ControlConfig::default(),
+            remote: RemoteConfig::default(),
+            keys: KeyBindings::default(),
+            parameters: BTreeMap::new(),
+            tempo: TempoConfig::default(),
+            link: LinkConfig::default(),
+            midi: MidiConfig::default(),
+            generators: GeneratorsConfig::default(),
+            modulation: ModulationConfig::default(),
+            preset: PresetConfig::default(),
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            cue: vec![
+                KeyCode::Digit1,
+                KeyCode::Digit2,
+                KeyCode::Digit3,
+                KeyCode::Digit4,
+                KeyCode::Digit5,
+                KeyCode::Digit6,
+                KeyCode::Digit7,
+                KeyCode::Digit8,
+                KeyCode::Digit9,
+            ],
+            take: KeyCode::Enter,
+            warp_edit: KeyCode::KeyW,
+            warp_next_output: KeyCode::Tab,
+            warp_toggle_mesh: KeyCode::KeyM,
+            warp_reset: KeyCode::Backspace,
+            debug_overlay: KeyCode::F1,
+            debug_channel: KeyCode::KeyC,
+            debug_scale_up: KeyCode::Equal,
+            debug_scale_down: KeyCode::Minus,
+            logo_select: vec![
+                KeyCode::F5,
+                KeyCode::F6,
+                KeyCode::F7,
+                KeyCode::F8,
+                KeyCode::F9,
+                KeyCode::F10,
+                KeyCode::F11,
+                KeyCode::F12,
+            ],
+            logo_next: KeyCode::KeyL,
+            palette_next: KeyCode::KeyP,
+            post_select: KeyCode::KeyO,
+            post_move_earlier: KeyCode::BracketLeft,
+            post_move_later: KeyCode::BracketRight,
+            clip_next: KeyCode::KeyN,
+            tempo_tap: KeyCode::Space,
+            tempo_resync: KeyCode::KeyR,
+            tempo_nudge_earlier: KeyCode::Comma,
+            tempo_nudge_later: KeyCode::Period,
+            tempo_mode: KeyCode::KeyT,
+            midi_learn: KeyCode::KeyI,
+            parameter_previous: KeyCode::PageUp,
+            parameter_next: KeyCode::PageDown,
+            parameter_increase: KeyCode::ArrowUp,
+            parameter_decrease: KeyCode::ArrowDown,
+            parameter_reset: KeyCode::Delete,
+            preset_recall: vec![
+                KeyCode::Numpad1,
+                KeyCode::Numpad2,
+                KeyCode::Numpad3,
+                KeyCode::Numpad4,
+                KeyCode::Numpad5,
+                KeyCode::Numpad6,
+                KeyCode::Numpad7,
+                KeyCode::Numpad8,
+                KeyCode::Numpad9,
+            ],
+            preset_save: KeyCode::ControlLeft,
+        }
+    }
+}
+
+impl KeyBindings {
+    fn named(&self) -> Vec<(String, KeyCode)> {
+        let mut named: Vec<(String, KeyCode)> = self
+            .cue
+            .iter()
+            .enumerate()
+            .map(|(index, key)| (format!("keys.cue[{index}]"), *key))
+            .collect();
+
+        named.extend(
+            self.logo_select
+                .iter()
+                .enumerate()
+                .map(|(index, key)| (format!("keys.logo_select[{index}]"), *key)),
+        );
+
+        named.extend(
+            self.preset_recall
+                .iter()
+                .enumerate()
+                .map(|(index, key)| (format!("keys.preset_recall[{index}]"), *key)),
+        );
+
+        named.extend([
+            ("keys.take".to_owned(), self.take),
+            ("keys.warp_edit".to_owned(), self.warp_edit),
+            ("keys.warp_next_output".to_owned(), self.warp_next_output),
+            ("keys.warp_toggle_mesh".to_owned(), self.warp_toggle_mesh),
+            ("keys.warp_reset".to_owned(), self.warp_reset),
+            ("keys.debug_overlay".to_owned(), self.debug_overlay),
+            ("keys.debug_channel".to_owned(), self.debug_channel),
+            ("keys.debug_scale_up".to_owned(), self.debug_scale_up),
+            ("keys.debug_scale_down".to_owned(), self.debug_scale_down),
+            ("keys.logo_next".to_owned(), self.logo_next),
+            ("keys.palette_next".to_owned(), self.palette_next),
+            ("keys.post_select".to_owned(), self.post_select),
+            ("keys.post_move_earlier".to_owned(), self.post_move_earlier),
+            ("keys.post_move_later".to_owned(), self.post_move_later),
+            ("keys.clip_next".to_owned(), self.clip_next),
+            ("keys.tempo_tap".to_owned(), self.tempo_tap),
+            ("keys.tempo_resync".to_owned(), self.tempo_resync),
+            (
+                "keys.tempo_nudge_earlier".to_owned(),
+                self.tempo_nudge_earlier,
+            ),
+            ("keys.tempo_nudge_later".to_owned(), self.tempo_nudge_later),
+            ("keys.tempo_mode".to_owned(), self.tempo_mode),
+            ("keys.midi_learn".to_owned(), self.midi_learn),
+            (
+                "keys.parameter_previous".to_owned(),
+                self.parameter_previous,
+            ),
+            ("keys.parameter_next".to_owned(), self.parameter_next),
+            (
+                "keys.parameter_increase".to_owned(),
+                self.parameter_increase,
+            ),
+            (
+                "keys.parameter_decrease".to_owned(),
+                self.parameter_decrease,
+            ),
+            ("keys.parameter_reset".to_owned(), self.parameter_reset),
+            ("keys.preset_save".to_owned(), self.preset_save),
+        ]);
+
+        named
+    }
+}
+
+impl Cli {
+    pub fn parse() -> Result<Cli, ConfigError> {
+        let mut cli = Cli::default();
+        let mut arguments = env::args().skip(1);
+
+        while let Some(argument) = arguments.next() {
+            let mut value = |name: &str| {
+                arguments
+                    .next()
+                    .ok_or_else(|| ConfigError::Cli(format!("{name} expects a value")))
+            };
+
+            match argument.as_str() {
+                "--config" => cli.config_path = Some(PathBuf::from(value("--config")?)),
+                "--input" => cli.input = Some(value("--input")?),
+                "--fullscreen" => cli.fullscreen = true,
+                "--monitor" => {
+                    let monitor = value("--monitor")?;
+                    cli.monitor = Some(monitor.parse().map_err(|_| {
+                        ConfigError::Cli(format!("--monitor expects an index, got {monitor}"))
+                    })?);
+                }
+                "--canvas" => {
+                    let canvas = value("--canvas")?;
+                    cli.canvas_size = Some(parse_size(&canvas).ok_or_else(|| {
+                        ConfigError::Cli(format!("--canvas expects WIDTHxHEIGHT, got {canvas}"))
+                    })?);
+                }
+                "--logo" => cli.logo = Some(value("--logo")?),
+                "--no-control" => cli.no_control = true,
+                "--help" | "-h" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => return Err(ConfigError::Cli(format!("Unknown argument {argument}"))),
+            }
+        }
+
+        Ok(cli)
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(input) = &self.input {
+            config.audio.input = Some(input.clone());
+        }
+
+        if (self.fullscreen || self.monitor.is_some())
+            && let Some(output) = config.outputs.outputs.first_mut()
+        {
+            output.fullscreen_monitor = Some(self.monitor.unwrap_or(0));
+        }
+
+        if let Some(canvas_size) = self.canvas_size {
+            config.outputs.canvas_size = Some(canvas_size);
+        }
+
+        if let Some(logo) = &self.logo {
+            config.logo.logos = vec![LogoEntry {
+                path: logo.clone(),
+                ..default()
+            }];
+        }
+
+        if self.no_control {
+            config.control.enabled = false;
+        }
+    }
+}
+
+fn parse_size(size: &str) -> Option<UVec2> {
+    let (width, height) = size.split_once('x')?;
+
+    Some(UVec2::new(width.parse().ok()?, height.parse().ok()?))
+}
+
+fn invalid(key: impl Into<String>, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.into(),
+        message: message.into(),
+    }
+}
+
+// What is learned or edited while running lives in its own file rather than the hand-written
+// config, so saving it never strips the config's comments or layout. Anything saved here replaces
+// the config's own value at startup
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SavedState {
+    pub modulation_routes: Option<Vec<ModulationRoute>>,
+    pub midi_mappings: Option<Vec<MidiMapping>>,
+    // Keyed by output index
+    pub warps: BTreeMap<usize, Warp>,
+}
+
+impl SavedState {
+    pub fn path(config_path: &Path) -> PathBuf {
+        config_path.with_extension(STATE_EXTENSION)
+    }
+
+    pub fn load(config_path: &Path) -> Result<SavedState, ConfigError> {
+        let path = SavedState::path(config_path);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(SavedState::default());
+            }
+            Err(err) => return Err(ConfigError::Io(path, err)),
+        };
+
+        ron::from_str(&contents).map_err(|err| ConfigError::Parse(path, err))
+    }
+
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(routes) = &self.modulation_routes {
+            config.modulation.routes = routes.clone();
+        }
+
+        if let Some(mappings) = &self.midi_mappings {
+            config.midi.mappings = mappings.clone();
+        }
+
+        for (index, warp) in &self.warps {
+            if let Some(output) = config.outputs.outputs.get_mut(*index) {
+                output.warp = warp.clone();
+            }
+        }
+    }
+
+    pub fn update_file(config_path: &Path, update: impl FnOnce(&mut SavedState)) {
+        let path = SavedState::path(config_path);
+        let mut state = match SavedState::load(config_path) {
+            Ok(state) => state,
+            Err(err) => {
+                warn!("Not saving to {}: {err}", path.display());
+                return;
+            }
+        };
+
+        update(&mut state);
+
+        let contents = match ron::ser::to_string_pretty(&state, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Could not serialize state: {err}");
+                return;
+            }
+        };
+
+        if let Err(err) = fs::write(&path, contents + "\n") {
+            warn!("Could not write {}: {err}", path.display());
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path, required: bool) -> Result<Config, ConfigError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default());
+            }
+            Err(err) => return Err(ConfigError::Io(path.to_owned(), err)),
+        };
+
+        ron::from_str(&contents).map_err(|err| ConfigError::Parse(path.to_owned(), err))
+    }
+
+    pub fn from_cli() -> Result<(Config, PathBuf), ConfigError> {
+        let cli = Cli::parse()?;
+
+        let path = cli
+            .config_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        let mut config = Config::load(&path, cli.config_path.is_some())?;
+        SavedState::load(&path)?.apply(&mut config);
+        cli.apply(&mut config);
+        config.validate()?;
+
+        Ok((config, path))
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.audio.channels == 0 {
+            return Err(invalid("audio.channels", "must be at least 1"));
+        }
+
+        let analysis = &self.analysis;
+        if analysis.sample_history < DFT_WINDOW_SAMPLES {
+            return Err(invalid(
+                "analysis.sample_history",
+                format!("must hold the {DFT_WINDOW_SAMPLES} sample DFT window"),
+            ));
+        }
+        if analysis.width % WORKGROUP_SIZE != 0 || analysis.width * 2 < DFT_BINS {
+            return Err(invalid(
+                "analysis.width",
+                format!(
+                    "must be a multiple of {WORKGROUP_SIZE} and fit {DFT_BINS} DFT bins in two rows"
+                ),
+            ));
+        }
+        if analysis.height % WORKGROUP_SIZE != 0 || analysis.height < USED_ROWS {
+            return Err(invalid(
+                "analysis.height",
+                format!("must be a multiple of {WORKGROUP_SIZE} and at least {USED_ROWS}"),
+            ));
+        }
+        if !(0.0..=1.0).contains(&analysis.uniforms.fade_length) {
+            return Err(invalid(
+                "analysis.uniforms.fade_length",
+                "must be between 0 and 1",
+            ));
+        }
+
+        if self.scenes.is_empty() {
+            return Err(invalid("scenes", "at least one scene is required"));
+        }
+        let mut scene_names = HashSet::new();
+        for (index, scene) in self.scenes.iter().enumerate() {
+            if !AVAILABLE_SCENES.contains(&scene.as_str()) {
+                return Err(invalid(
+                    format!("scenes[{index}]"),
+                    format!(
+                        "unknown scene {scene}, expected one of {}",
+                        AVAILABLE_SCENES.join(", ")
+                    ),
+                ));
+            }
+            if !scene_names.insert(scene) {
+                return Err(invalid(
+                    format!("scenes[{index}]"),
+                    format!("{scene} is listed twice"),
+                ));
+            }
+        }
+
+        if self.layers.is_empty() {
+            return Err(invalid("layers", "at least one layer is required"));
+        }
+        for (index, layer) in self.layers.iter().enumerate() {
+            let key = format!("layers[{index}]");
+
+            if layer.name.is_empty() {
+                return Err(invalid(format!("{key}.name"), "must not be empty"));
+            }
+            if self.layers[..index]
+                .iter()
+                .any(|other| other.name == layer.name)
+            {
+                return Err(invalid(
+                    format!("{key}.name"),
+                    format!("{} is used twice", layer.name),
+                ));
+            }
+            if let LayerSource::Scene(scene) = &layer.source
+                && !self.scenes.contains(scene)
+            {
+                return Err(invalid(
+                    format!("{key}.source"),
+                    format!("{scene} is not listed in scenes"),
+                ));
+            }
+            if let LayerSource::Media(media) = &layer.source
+                && !self.media.contains_key(media)
+            {
+                return Err(invalid(
+                    format!("{key}.source"),
+                    format!("{media} is not defined in media"),
+                ));
+            }
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                return Err(invalid(format!("{key}.opacity"), "must be between 0 and 1"));
+            }
+            if !(0.0..=1.0).contains(&layer.key_threshold) {
+                return Err(invalid(
+                    format!("{key}.key_threshold"),
+                    "must be between 0 and 1",
+                ));
+            }
+            if !(layer.key_softness > 0.0 && layer.key_softness.is_finite()) {
+                return Err(invalid(format!("{key}.key_softness"), "must be positive"));
+            }
+        }
+
+        for (name, media) in &self.media {
+            let key = format!("media.{name}");
+
+            if media.clips.is_empty() {
+                return Err(invalid(
+                    format!("{key}.clips"),
+                    "at least one clip is required",
+                ));
+            }
+            if media.max_width == 0 {
+                return Err(invalid(format!("{key}.max_width"), "must be positive"));
+            }
+            if media.max_frames == 0 {
+                return Err(invalid(format!("{key}.max_frames"), "must be positive"));
+            }
+            for (index, clip) in media.clips.iter().enumerate() {
+                let key = format!("{key}.clips[{index}]");
+
+                if clip.path.is_empty() {
+                    return Err(invalid(format!("{key}.path"), "must not be empty"));
+                }
+                if !(clip.fps > 0.0 && clip.fps.is_finite()) {
+                    return Err(invalid(format!("{key}.fps"), "must be positive"));
+                }
+                if let Some(beats) = clip.beats
+                    && !(beats > 0.0 && beats.is_finite())
+                {
+                    return Err(invalid(format!("{key}.beats"), "must be positive"));
+                }
+            }
+        }
+
+        if self.logo.logos.is_empty() {
+            return Err(invalid("logo.logos", "at least one logo is required"));
+        }
+        for (index, entry) in self.logo.logos.iter().enumerate() {
+            let key = format!("logo.logos[{index}]");
+
+            if entry.path.is_empty() {
+                return Err(invalid(format!("{key}.path"), "must not be empty"));
+            }
+            if !(entry.duration > 0.0 && entry.duration.is_finite()) {
+                return Err(invalid(format!("{key}.duration"), "must be positive"));
+            }
+            if let Some(position) = entry.position
+                && (!(0.0..=1.0).contains(&position.x) || !(0.0..=1.0).contains(&position.y))
+            {
+                return Err(invalid(format!("{key}.position"), "must be within 0..1"));
+            }
+            if let Some(height) = entry.height
+                && !(height > 0.0 && height.is_finite())
+            {
+                return Err(invalid(format!("{key}.height"), "must be positive"));
+            }
+        }
+        if !(self.logo.crossfade >= 0.0 && self.logo.crossfade.is_finite()) {
+            return Err(invalid("logo.crossfade", "must not be negative"));
+        }
+        if !(0.0..=1.0).contains(&self.logo.position.x)
+            || !(0.0..=1.0).contains(&self.logo.position.y)
+        {
+            return Err(invalid("logo.position", "must be within 0..1"));
+        }
+        if !(self.logo.height > 0.0 && self.logo.height.is_finite()) {
+            return Err(invalid("logo.height", "must be positive"));
+        }
+        if !(0.0..=1.0).contains(&self.logo.opacity) {
+            return Err(invalid("logo.opacity", "must be between 0 and 1"));
+        }
+        if !(self.logo.depth > 0.0 && self.logo.depth.is_finite()) {
+            return Err(invalid("logo.depth", "must be positive"));
+        }
+        let background = &self.logo.background;
+        if !(background.padding > 0.0 && background.padding.is_finite()) {
+            return Err(invalid("logo.background.padding", "must be positive"));
+        }
+        if !(0.0..=1.0).contains(&background.fill) {
+            return Err(invalid("logo.background.fill", "must be between 0 and 1"));
+        }
+        for (key, width) in [
+            ("logo.background.expand", background.expand),
+            ("logo.background.outline_width", background.outline_width),
+            ("logo.background.glow_width", background.glow_width),
+        ] {
+            if !(0.0..=background.padding).contains(&width) {
+                return Err(invalid(
+                    key,
+                    "must be between 0 and logo.background.padding",
+                ));
+            }
+        }
+        if let Some(pulse) = &self.logo.pulse
+            && !(0.0..=1.0).contains(&pulse.opacity)
+        {
+            return Err(invalid("logo.pulse.opacity", "must be between 0 and 1"));
+        }
+
+        if self.particles.count == 0 {
+            return Err(invalid("particles.count", "must be at least 1"));
+        }
+        if !(self.particles.scale > 0.0 && self.particles.scale.is_finite()) {
+            return Err(invalid("particles.scale", "must be positive"));
+        }
+
+        for (key, placement) in [
+            ("geometry.bars.scale", &self.geometry.bars),
+            ("geometry.terrain.scale", &self.geometry.terrain),
+            ("geometry.tunnel.scale", &self.geometry.tunnel),
+        ] {
+            if !(placement.scale > 0.0 && placement.scale.is_finite()) {
+                return Err(invalid(key, "must be positive"));
+            }
+        }
+
+        for (name, model) in &self.models {
+            let key = format!("models.{name}");
+
+            if model.path.is_empty() {
+                return Err(invalid(format!("{key}.path"), "must not be empty"));
+            }
+            if !(model.placement.scale > 0.0 && model.placement.scale.is_finite()) {
+                return Err(invalid(
+                    format!("{key}.placement.scale"),
+                    "must be positive",
+                ));
+            }
+        }
+
+        let text = &self.text;
+        if !(text.font_size > 0.0 && text.font_size.is_finite()) {
+            return Err(invalid("text.font_size", "must be positive"));
+        }
+        for (key, position) in [
+            ("text.title_position", text.title_position),
+            ("text.lyrics_position", text.lyrics_position),
+        ] {
+            if !(0.0..=1.0).contains(&position.x) || !(0.0..=1.0).contains(&position.y) {
+                return Err(invalid(key, "must be within 0..1"));
+            }
+        }
+        if !(0.0..=1.0).contains(&text.ticker_position) {
+            return Err(invalid("text.ticker_position", "must be between 0 and 1"));
+        }
+
+        if !(self.palette.crossfade >= 0.0 && self.palette.crossfade.is_finite()) {
+            return Err(invalid("palette.crossfade", "must not be negative"));
+        }
+
+        for (index, effect) in self.post.chain.iter().enumerate() {
+            if self.post.chain[..index].contains(effect) {
+                return Err(invalid(
+                    format!("post.chain[{index}]"),
+                    format!("{} is listed twice", effect.name()),
+                ));
+            }
+        }
+        for (index, modulation) in self.post.modulation.iter().enumerate() {
+            if !self.post.chain.contains(&modulation.effect) {
+                return Err(invalid(
+                    format!("post.modulation[{index}].effect"),
+                    format!("{} is not in post.chain", modulation.effect.name()),
+                ));
+            }
+        }
+
+        if self.outputs.outputs.is_empty() {
+            return Err(invalid(
+                "outputs.outputs",
+                "at least one output is required",
+            ));
+        }
+        if let Some(canvas_size) = self.outputs.canvas_size
+            && (canvas_size.x == 0 || canvas_size.y == 0)
+        {
+            return Err(invalid("outputs.canvas_size", "must not be zero"));
+        }
+        for (index, output) in self.outputs.outputs.iter().enumerate() {
+            let key = format!("outputs.outputs[{index}]");
+
+            if !output.region.min.is_finite()
+                || !output.region.max.is_finite()
+                || output.region.min.cmplt(Vec2::ZERO).any()
+                || output.region.max.cmpgt(Vec2::ONE).any()
+                || output.region.is_empty()
+            {
+                return Err(invalid(
+                    format!("{key}.region"),
+                    "must be a non-empty rectangle within 0..1",
+                ));
+            }
+
+            for (edge, width) in [
+                ("left", output.blend.left),
+                ("right", output.blend.right),
+                ("top", output.blend.top),
+                ("bottom", output.blend.bottom),
+            ] {
+                if !(0.0..=0.5).contains(&width) {
+                    return Err(invalid(
+                        format!("{key}.blend.{edge}"),
+                        "must be between 0 and 0.5",
+                    ));
+                }
+            }
+            if let Some(scene) = &output.scene
+                && !self.scenes.contains(scene)
+            {
+                return Err(invalid(
+                    format!("{key}.scene"),
+                    format!("{scene} is not listed in scenes"),
+                ));
+            }
+
+            if !(output.blend.power > 0.0 && output.blend.power.is_finite()) {
+                return Err(invalid(format!("{key}.blend.power"), "must be positive"));
+            }
+            if !(output.blend.gamma > 0.0 && output.blend.gamma.is_finite()) {
+                return Err(invalid(format!("{key}.blend.gamma"), "must be positive"));
+            }
+
+            if let Some(mesh) = &output.warp.mesh
+                && (mesh.columns < 2
+                    || mesh.rows < 2
+                    || mesh.points.len() != mesh.columns * mesh.rows)
+            {
+                return Err(invalid(
+                    format!("{key}.warp.mesh"),
+                    "needs at least 2x2 points and exactly columns * rows points",
+                ));
+            }
+        }
+
+        let envelope_keys = self
+            .generators
+            .envelopes
+            .iter()
+            .filter_map(|(name, envelope)| match envelope.trigger {
+                EnvelopeTrigger::Key(key) => {
+                    Some((format!("generators.envelopes.{name}.trigger"), key))
+                }
+                _ => None,
+            });
+
+        let mut bound_keys: Vec<(String, KeyCode)> = Vec::new();
+        for (name, key) in self.keys.named().into_iter().chain(envelope_keys) {
+            if let Some((other_name, _)) = bound_keys.iter().find(|(_, other)| *other == key) {
+                return Err(invalid(
+                    name,
+                    format!("{key:?} is already bound to {other_name}"),
+                ));
+            }
+            bound_keys.push((name, key));
+        }
+
+        if !(0.0..=1.0).contains(&self.tempo.blend) {
+            return Err(invalid("tempo.blend", "must be between 0 and 1"));
+        }
+        if !(self.tempo.nudge > 0.0 && self.tempo.nudge.is_finite()) {
+            return Err(invalid("tempo.nudge", "must be positive"));
+        }
+        if !(self.link.quantum > 0.0 && self.link.quantum.is_finite()) {
+            return Err(invalid("link.quantum", "must be positive"));
+        }
+
+        if !(self.midi.quantum > 0.0 && self.midi.quantum.is_finite()) {
+            return Err(invalid("midi.quantum", "must be positive"));
+        }
+        for (index, mapping) in self.midi.mappings.iter().enumerate() {
+            let (channel, number) = match mapping.control {
+                MidiControl::Controller {
+                    channel,
+                    controller,
+                } => (channel, controller),
+                MidiControl::Note { channel, note } => (channel, note),
+            };
+            if channel > 15 || number > 127 {
+                return Err(invalid(
+                    format!("midi.mappings[{index}].control"),
+                    "channels go up to 15 and controllers and notes up to 127",
+                ));
+            }
+        }
+
+        let generators = &self.generators;
+        if generators.lfos.len() + generators.envelopes.len() > self.analysis.width as usize {
+            return Err(invalid(
+                "generators",
+                "more LFOs and envelopes than texels in a row of the audiolink texture",
+            ));
+        }
+        for name in generators.lfos.keys() {
+            if generators.envelopes.contains_key(name) {
+                return Err(invalid(
+                    format!("generators.envelopes.{name}"),
+                    "is also the name of an LFO",
+                ));
+            }
+        }
+        for (name, lfo) in &generators.lfos {
+            if !(lfo.beats > 0.0 && lfo.beats.is_finite()) {
+                return Err(invalid(
+                    format!("generators.lfos.{name}.beats"),
+                    "must be positive",
+                ));
+            }
+        }
+        for (name, envelope) in &generators.envelopes {
+            let key = format!("generators.envelopes.{name}");
+
+            for (field, seconds) in [
+                ("attack", envelope.attack),
+                ("decay", envelope.decay),
+                ("release", envelope.release),
+                ("hold", envelope.hold),
+            ] {
+                if !(seconds >= 0.0 && seconds.is_finite()) {
+                    return Err(invalid(format!("{key}.{field}"), "must not be negative"));
+                }
+            }
+            if !(0.0..=1.0).contains(&envelope.sustain) {
+                return Err(invalid(format!("{key}.sustain"), "must be between 0 and 1"));
+            }
+        }
+
+        for (index, route) in self.modulation.routes.iter().enumerate() {
+            route.validate().map_err(|(field, message)| {
+                invalid(format!("modulation.routes[{index}].{field}"), message)
+            })?;
+        }
+
+        if !(self.preset.morph_beats >= 0.0 && self.preset.morph_beats.is_finite()) {
+            return Err(invalid("preset.morph_beats", "must not be negative"));
+        }
+        for (index, name) in self.preset.slots.iter().enumerate() {
+            if !preset::is_valid_name(name) {
+                return Err(invalid(
+                    format!("preset.slots[{index}]"),
+                    "must be a file name without slashes or a leading dot",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
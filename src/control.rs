@@ -0,0 +1,426 @@
+use bevy::{camera::RenderTarget, prelude::*, window::WindowRef};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audiolink::{Audiolink, AudiolinkDataTexture, AudiolinkSettings},
+    config::Config,
+    output::{Canvas, OUTPUT_CAMERA_ORDER},
+    parameters::Parameters,
+    scene::{PreviewCanvas, Scenes},
+};
+
+pub const CONTROL_WINDOW_TITLE: &str = "VJ Control";
+
+pub const VIEW_WIDTH: f32 = 480.0;
+pub const AUDIOLINK_VIEW_SCALE: f32 = 4.0;
+pub const METER_HEIGHT: f32 = 256.0;
+
+const PANEL_COLOR: Color = Color::srgb(0.1, 0.1, 0.1);
+const BUTTON_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+const PROGRAM_COLOR: Color = Color::srgb(0.7, 0.1, 0.1);
+const PREVIEW_COLOR: Color = Color::srgb(0.1, 0.6, 0.1);
+const SELECTED_COLOR: Color = Color::srgb(0.2, 0.3, 0.6);
+
+pub struct ControlPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControlConfig {
+    pub enabled: bool,
+    pub resolution: UVec2,
+}
+
+#[derive(Component)]
+pub struct AudiolinkView;
+
+#[derive(Component)]
+pub struct Meter(pub Channel);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Left,
+    Right,
+}
+
+#[derive(Component)]
+pub struct ParameterButton {
+    pub name: String,
+    pub steps: f32,
+}
+
+#[derive(Component)]
+pub struct ParameterRow(pub usize);
+
+#[derive(Component)]
+pub struct ParameterValue(pub String);
+
+#[derive(Component)]
+pub struct SceneButton(pub usize);
+
+#[derive(Component)]
+pub struct TakeButton;
+
+impl Default for ControlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            resolution: UVec2::new(1280, 800),
+        }
+    }
+}
+
+impl Plugin for ControlPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            setup
+                .after(crate::output::setup)
+                .after(crate::scene::setup)
+                .after(crate::audiolink::setup)
+                .after(crate::visualizer::setup)
+                .after(crate::post::setup),
+        )
+        .add_systems(
+            Update,
+            (
+                update_audiolink_view.after(crate::audiolink::update),
+                update_meters.after(crate::audiolink::update),
+                press_parameter_buttons,
+                update_parameter_values
+                    .after(press_parameter_buttons)
+                    .after(crate::parameters::control_parameters),
+                press_scene_buttons,
+                update_scene_buttons.after(press_scene_buttons),
+            ),
+        );
+    }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    canvas: Res<Canvas>,
+    preview_canvas: Res<PreviewCanvas>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+    audiolink_settings: Res<AudiolinkSettings>,
+    scenes: Res<Scenes>,
+    parameters: Res<Parameters>,
+    config: Res<Config>,
+) {
+    if !config.control.enabled {
+        return;
+    }
+
+    let window = commands
+        .spawn(Window {
+            title: CONTROL_WINDOW_TITLE.to_owned(),
+            resolution: config.control.resolution.into(),
+            ..default()
+        })
+        .id();
+
+    let camera = commands
+        .spawn((
+            Camera2d,
+            Camera {
+                order: OUTPUT_CAMERA_ORDER + 1,
+                target: RenderTarget::Window(WindowRef::Entity(window)),
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+        ))
+        .id();
+
+    let view_height = VIEW_WIDTH * canvas.size.y as f32 / canvas.size.x.max(1) as f32;
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            UiTargetCamera(camera),
+        ))
+        .with_children(|root| {
+            root.spawn(Node {
+                column_gap: Val::Px(8.0),
+                ..default()
+            })
+            .with_children(|views| {
+                spawn_view(views, "Program", canvas.image.clone(), view_height);
+                spawn_view(views, "Preview", preview_canvas.image.clone(), view_height);
+            });
+
+            root.spawn(Node {
+                column_gap: Val::Px(8.0),
+                ..default()
+            })
+            .with_children(|row| {
+                row.spawn(panel()).with_children(|panel| {
+                    panel.spawn(label("Audiolink"));
+                    panel.spawn((
+                        ImageNode::new(audiolink_data_texture.0.clone()),
+                        Node {
+                            width: Val::Px(audiolink_settings.width as f32 * AUDIOLINK_VIEW_SCALE),
+                            height: Val::Px(
+                                audiolink_settings.height as f32 * AUDIOLINK_VIEW_SCALE,
+                            ),
+                            ..default()
+                        },
+                        AudiolinkView,
+                    ));
+                });
+
+                row.spawn(panel()).with_children(|panel| {
+                    panel.spawn(label("Levels"));
+                    panel
+                        .spawn(Node {
+                            column_gap: Val::Px(4.0),
+                            ..default()
+                        })
+                        .with_children(|meters| {
+                            spawn_meter(meters, Channel::Left);
+                            spawn_meter(meters, Channel::Right);
+                        });
+                });
+
+                row.spawn(panel()).with_children(|panel| {
+                    panel.spawn(label("Parameters"));
+                    for (index, (name, _)) in parameters.iter().enumerate() {
+                        spawn_parameter(panel, index, name);
+                    }
+                });
+            });
+
+            root.spawn(Node {
+                column_gap: Val::Px(8.0),
+                ..default()
+            })
+            .with_children(|row| {
+                for (index, name) in scenes.names.iter().enumerate() {
+                    row.spawn((button(), SceneButton(index)))
+                        .with_child(label(&format!("{} {name}", index + 1)));
+                }
+
+                row.spawn((button(), TakeButton)).with_child(label("Take"));
+            });
+        });
+}
+
+fn panel() -> impl Bundle {
+    (
+        Node {
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(8.0)),
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        BackgroundColor(PANEL_COLOR),
+    )
+}
+
+fn button() -> impl Bundle {
+    (
+        Button,
+        Node {
+            padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(BUTTON_COLOR),
+    )
+}
+
+fn label(text: &str) -> impl Bundle {
+    (
+        Text::new(text),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+    )
+}
+
+fn spawn_view(parent: &mut ChildSpawnerCommands, name: &str, image: Handle<Image>, height: f32) {
+    parent.spawn(panel()).with_children(|panel| {
+        panel.spawn(label(name));
+        panel.spawn((
+            ImageNode::new(image),
+            Node {
+                width: Val::Px(VIEW_WIDTH),
+                height: Val::Px(height),
+                ..default()
+            },
+        ));
+    });
+}
+
+fn spawn_meter(parent: &mut ChildSpawnerCommands, channel: Channel) {
+    parent
+        .spawn((
+            Node {
+                width: Val::Px(24.0),
+                height: Val::Px(METER_HEIGHT),
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.05, 0.05, 0.05)),
+        ))
+        .with_child((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(0.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgb(0.0, 1.0, 0.0)),
+            Meter(channel),
+        ));
+}
+
+fn spawn_parameter(parent: &mut ChildSpawnerCommands, index: usize, name: &str) {
+    parent
+        .spawn((
+            Node {
+                column_gap: Val::Px(4.0),
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            BackgroundColor(Color::NONE),
+            ParameterRow(index),
+        ))
+        .with_children(|row| {
+            row.spawn((
+                button(),
+                ParameterButton {
+                    name: name.to_owned(),
+                    steps: -1.0,
+                },
+            ))
+            .with_child(label("-"));
+            row.spawn((
+                button(),
+                ParameterButton {
+                    name: name.to_owned(),
+                    steps: 1.0,
+                },
+            ))
+            .with_child(label("+"));
+            row.spawn((label(""), ParameterValue(name.to_owned())));
+        });
+}
+
+pub fn update_audiolink_view(
+    mut views: Query<&mut ImageNode, With<AudiolinkView>>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+) {
+    for mut view in views.iter_mut() {
+        view.image = audiolink_data_texture.0.clone();
+    }
+}
+
+pub fn update_meters(
+    audiolink: Single<&Audiolink>,
+    mut meters: Query<(&Meter, &mut Node, &mut BackgroundColor)>,
+) {
+    for (meter, mut node, mut background_color) in meters.iter_mut() {
+        let level = match meter.0 {
+            Channel::Left => audiolink.left_smoothed_max,
+            Channel::Right => audiolink.right_smoothed_max,
+        };
+
+        node.height = Val::Percent(level.clamp(0.0, 1.0) * 100.0);
+        background_color.0 = if level >= 1.0 {
+            Color::srgb(1.0, 0.0, 0.0)
+        } else if level >= 0.8 {
+            Color::srgb(1.0, 1.0, 0.0)
+        } else {
+            Color::srgb(0.0, 1.0, 0.0)
+        };
+    }
+}
+
+pub fn press_parameter_buttons(
+    buttons: Query<(&Interaction, &ParameterButton), Changed<Interaction>>,
+    mut parameters: ResMut<Parameters>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            parameters.nudge(&button.name, button.steps);
+        }
+    }
+}
+
+pub fn update_parameter_values(
+    mut values: Query<(&ParameterValue, &mut Text)>,
+    mut rows: Query<(&ParameterRow, &mut BackgroundColor)>,
+    parameters: Res<Parameters>,
+) {
+    if !parameters.is_changed() {
+        return;
+    }
+
+    for (value, mut text) in values.iter_mut() {
+        if let Some(parameter) = parameters.parameter(&value.0) {
+            text.0 = if parameter.modulation != 0.0 {
+                format!(
+                    "{}: {:.2} ~ {:.2}",
+                    value.0,
+                    parameter.value,
+                    parameter.modulated()
+                )
+            } else {
+                format!("{}: {:.2}", value.0, parameter.value)
+            };
+        }
+    }
+
+    for (row, mut background_color) in rows.iter_mut() {
+        background_color.0 = if row.0 == parameters.selected {
+            SELECTED_COLOR
+        } else {
+            Color::NONE
+        };
+    }
+}
+
+pub fn press_scene_buttons(
+    scene_buttons: Query<(&Interaction, &SceneButton), Changed<Interaction>>,
+    take_buttons: Query<&Interaction, (Changed<Interaction>, With<TakeButton>)>,
+    mut scenes: ResMut<Scenes>,
+) {
+    for (interaction, button) in scene_buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            scenes.cue(button.0);
+        }
+    }
+
+    for interaction in take_buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            scenes.take();
+        }
+    }
+}
+
+pub fn update_scene_buttons(
+    mut buttons: Query<(&SceneButton, &mut BackgroundColor)>,
+    scenes: Res<Scenes>,
+) {
+    if !scenes.is_changed() {
+        return;
+    }
+
+    for (button, mut background_color) in buttons.iter_mut() {
+        background_color.0 = if button.0 == scenes.program {
+            PROGRAM_COLOR
+        } else if button.0 == scenes.preview {
+            PREVIEW_COLOR
+        } else {
+            BUTTON_COLOR
+        };
+    }
+}
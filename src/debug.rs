@@ -0,0 +1,369 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderType},
+    shader::ShaderRef,
+    ui::RelativeCursorPosition,
+};
+
+use crate::{
+    audiolink::{AudiolinkDataTexture, AudiolinkReadback, AudiolinkSettings},
+    config::Config,
+    output::OutputCamera,
+};
+
+const SHADER_ASSET_PATH: &str = "audiolink_debug.wgsl";
+
+pub const DEBUG_VIEW_SCALE: f32 = 6.0;
+
+pub struct DebugPlugin;
+
+#[derive(Resource)]
+pub struct AudiolinkDebug {
+    pub visible: bool,
+    pub channel: DebugChannel,
+    pub scale: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugChannel {
+    All,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+#[derive(Component)]
+pub struct AudiolinkDebugOverlay;
+
+#[derive(Component)]
+pub struct AudiolinkDebugView {
+    pub material_handle: Handle<AudiolinkDebugMaterial>,
+}
+
+#[derive(Component)]
+pub struct AudiolinkDebugStatus;
+
+#[derive(Component)]
+pub struct AudiolinkDebugTooltip;
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct AudiolinkDebugMaterial {
+    #[texture(0, sample_type = "float", filterable = false)]
+    audiolink_texture: Handle<Image>,
+    #[uniform(1)]
+    settings: AudiolinkDebugSettings,
+}
+
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct AudiolinkDebugSettings {
+    channel: u32,
+    scale: f32,
+}
+
+impl UiMaterial for AudiolinkDebugMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+impl Default for AudiolinkDebug {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            channel: DebugChannel::All,
+            scale: 1.0,
+        }
+    }
+}
+
+impl DebugChannel {
+    pub fn next(&self) -> DebugChannel {
+        match self {
+            DebugChannel::All => DebugChannel::Red,
+            DebugChannel::Red => DebugChannel::Green,
+            DebugChannel::Green => DebugChannel::Blue,
+            DebugChannel::Blue => DebugChannel::Alpha,
+            DebugChannel::Alpha => DebugChannel::All,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugChannel::All => "rgba",
+            DebugChannel::Red => "r",
+            DebugChannel::Green => "g",
+            DebugChannel::Blue => "b",
+            DebugChannel::Alpha => "a",
+        }
+    }
+
+    fn index(&self) -> u32 {
+        match self {
+            DebugChannel::All => 0,
+            DebugChannel::Red => 1,
+            DebugChannel::Green => 2,
+            DebugChannel::Blue => 3,
+            DebugChannel::Alpha => 4,
+        }
+    }
+}
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(UiMaterialPlugin::<AudiolinkDebugMaterial>::default())
+            .init_resource::<AudiolinkDebug>()
+            .add_systems(
+                Startup,
+                setup
+                    .after(crate::output::setup)
+                    .after(crate::audiolink::setup),
+            )
+            .add_systems(
+                Update,
+                (
+                    control_debug,
+                    update_debug_view
+                        .after(control_debug)
+                        .after(crate::audiolink::update),
+                    update_debug_tooltip,
+                ),
+            );
+    }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<AudiolinkDebugMaterial>>,
+    audiolink_debug: Res<AudiolinkDebug>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+    audiolink_settings: Res<AudiolinkSettings>,
+    output_cameras: Query<(Entity, &OutputCamera)>,
+) {
+    let Some((camera, _)) = output_cameras.iter().find(|(_, camera)| camera.index == 0) else {
+        return;
+    };
+
+    let material_handle = materials.add(AudiolinkDebugMaterial {
+        audiolink_texture: audiolink_data_texture.0.clone(),
+        settings: AudiolinkDebugSettings {
+            channel: audiolink_debug.channel.index(),
+            scale: audiolink_debug.scale,
+        },
+    });
+
+    let view_width = audiolink_settings.width as f32 * DEBUG_VIEW_SCALE;
+    let view_height = audiolink_settings.height as f32 * DEBUG_VIEW_SCALE;
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(16.0),
+                top: Val::Px(16.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            Visibility::Hidden,
+            UiTargetCamera(camera),
+            AudiolinkDebugOverlay,
+        ))
+        .with_children(|overlay| {
+            overlay.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                AudiolinkDebugStatus,
+            ));
+
+            overlay
+                .spawn(Node {
+                    column_gap: Val::Px(8.0),
+                    ..default()
+                })
+                .with_children(|row| {
+                    row.spawn((
+                        MaterialNode(material_handle.clone()),
+                        Node {
+                            width: Val::Px(view_width),
+                            height: Val::Px(view_height),
+                            ..default()
+                        },
+                        RelativeCursorPosition::default(),
+                        AudiolinkDebugView { material_handle },
+                    ))
+                    .with_child((
+                        Node {
+                            position_type: PositionType::Absolute,
+                            padding: UiRect::all(Val::Px(4.0)),
+                            ..default()
+                        },
+                        BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.9)),
+                        Text::new(""),
+                        TextFont {
+                            font_size: 12.0,
+                            ..default()
+                        },
+                        Visibility::Hidden,
+                        AudiolinkDebugTooltip,
+                    ));
+
+                    row.spawn(Node {
+                        width: Val::Px(160.0),
+                        height: Val::Px(view_height),
+                        ..default()
+                    })
+                    .with_children(|labels| {
+                        for region in audiolink_settings.regions() {
+                            labels.spawn((
+                                Node {
+                                    position_type: PositionType::Absolute,
+                                    top: Val::Px(region.first_row as f32 * DEBUG_VIEW_SCALE),
+                                    height: Val::Px(
+                                        (region.last_row + 1 - region.first_row) as f32
+                                            * DEBUG_VIEW_SCALE,
+                                    ),
+                                    border: UiRect::left(Val::Px(2.0)),
+                                    padding: UiRect::left(Val::Px(4.0)),
+                                    ..default()
+                                },
+                                BorderColor::all(Color::WHITE),
+                                Text::new(format!(
+                                    "{} ({}-{})",
+                                    region.name, region.first_row, region.last_row
+                                )),
+                                TextFont {
+                                    font_size: 12.0,
+                                    ..default()
+                                },
+                            ));
+                        }
+                    });
+                });
+        });
+}
+
+pub fn control_debug(
+    mut audiolink_debug: ResMut<AudiolinkDebug>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    if keyboard.just_pressed(config.keys.debug_overlay) {
+        audiolink_debug.visible = !audiolink_debug.visible;
+    }
+
+    if !audiolink_debug.visible {
+        return;
+    }
+
+    if keyboard.just_pressed(config.keys.debug_channel) {
+        audiolink_debug.channel = audiolink_debug.channel.next();
+    }
+    if keyboard.just_pressed(config.keys.debug_scale_up) {
+        audiolink_debug.scale *= 2.0;
+    }
+    if keyboard.just_pressed(config.keys.debug_scale_down) {
+        audiolink_debug.scale *= 0.5;
+    }
+}
+
+pub fn update_debug_view(
+    mut overlays: Query<&mut Visibility, With<AudiolinkDebugOverlay>>,
+    mut statuses: Query<&mut Text, With<AudiolinkDebugStatus>>,
+    views: Query<&AudiolinkDebugView>,
+    mut materials: ResMut<Assets<AudiolinkDebugMaterial>>,
+    audiolink_debug: Res<AudiolinkDebug>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+    audiolink_settings: Res<AudiolinkSettings>,
+    config: Res<Config>,
+) {
+    if audiolink_debug.is_changed() {
+        for mut visibility in overlays.iter_mut() {
+            *visibility = if audiolink_debug.visible {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+
+        for mut status in statuses.iter_mut() {
+            status.0 = format!(
+                "Audiolink {}x{}  channel: {} [{:?}]  scale: x{} [{:?}/{:?}]",
+                audiolink_settings.width,
+                audiolink_settings.height,
+                audiolink_debug.channel.name(),
+                config.keys.debug_channel,
+                audiolink_debug.scale,
+                config.keys.debug_scale_down,
+                config.keys.debug_scale_up
+            );
+        }
+    }
+
+    if !audiolink_debug.visible {
+        return;
+    }
+
+    for view in views.iter() {
+        if let Some(material) = materials.get_mut(view.material_handle.id()) {
+            material.audiolink_texture = audiolink_data_texture.0.clone();
+            material.settings = AudiolinkDebugSettings {
+                channel: audiolink_debug.channel.index(),
+                scale: audiolink_debug.scale,
+            };
+        }
+    }
+}
+
+pub fn update_debug_tooltip(
+    views: Query<&RelativeCursorPosition, With<AudiolinkDebugView>>,
+    mut tooltips: Query<(&mut Node, &mut Text, &mut Visibility), With<AudiolinkDebugTooltip>>,
+    audiolink_debug: Res<AudiolinkDebug>,
+    audiolink_readback: Res<AudiolinkReadback>,
+    audiolink_settings: Res<AudiolinkSettings>,
+) {
+    let Ok((mut node, mut text, mut visibility)) = tooltips.single_mut() else {
+        return;
+    };
+
+    let hovered = views
+        .iter()
+        .find(|cursor| cursor.cursor_over())
+        .and_then(|cursor| cursor.normalized)
+        .map(|normalized| normalized + 0.5);
+
+    let Some(uv) = hovered.filter(|_| audiolink_debug.visible) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    let width = audiolink_settings.width;
+    let height = audiolink_settings.height;
+
+    let x = ((uv.x * width as f32) as u32).min(width - 1);
+    let y = ((uv.y * height as f32) as u32).min(height - 1);
+
+    let region = audiolink_settings
+        .regions()
+        .into_iter()
+        .find(|region| (region.first_row..=region.last_row).contains(&y))
+        .map(|region| region.name)
+        .unwrap_or("Unknown");
+
+    text.0 = match audiolink_readback.get(x, y) {
+        Some(texel) => format!(
+            "({x}, {y}) {region}\nr {:.6}\ng {:.6}\nb {:.6}\na {:.6}",
+            texel.x, texel.y, texel.z, texel.w
+        ),
+        None => format!("({x}, {y}) {region}\nwaiting for readback"),
+    };
+
+    node.left = Val::Px(uv.x * width as f32 * DEBUG_VIEW_SCALE + 12.0);
+    node.top = Val::Px(uv.y * height as f32 * DEBUG_VIEW_SCALE + 12.0);
+    *visibility = Visibility::Inherited;
+}
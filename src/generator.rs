@@ -0,0 +1,266 @@
+use std::{collections::BTreeMap, f32::consts::TAU};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::AudioAnalysis, audiolink::AudiolinkGeneratorData, config::Config, tempo::BeatGrid,
+};
+
+pub struct GeneratorPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LfoShape {
+    Sine,
+    Saw,
+    Square,
+    // A new random value held for each period
+    SampleAndHold,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum EnvelopeTrigger {
+    Beat,
+    // Every this many beats, counted from the first
+    Beats(u64),
+    // Held open for as long as the key is down
+    Key(KeyCode),
+}
+
+// Each generator takes a texel in the audiolink texture's generator row, LFOs first and then
+// envelopes, each in name order
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneratorsConfig {
+    pub lfos: BTreeMap<String, LfoConfig>,
+    pub envelopes: BTreeMap<String, EnvelopeConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LfoConfig {
+    pub shape: LfoShape,
+    // Period in beats at the current tempo
+    pub beats: f32,
+    // Offset into the period, from 0 to 1
+    pub phase: f32,
+}
+
+// Attack, decay and release in seconds, sustain as a level
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvelopeConfig {
+    pub trigger: EnvelopeTrigger,
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+    // How long beat triggers hold the envelope open before releasing
+    pub hold: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnvelopeStage {
+    #[default]
+    Idle,
+    Attack,
+    Decay,
+    Release,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GeneratorState {
+    pub value: f32,
+    // Through the LFO's period from 0 to 1, or seconds since the envelope was triggered
+    pub phase: f32,
+    pub gate: bool,
+    pub stage: EnvelopeStage,
+    pub since_trigger: f32,
+}
+
+#[derive(Resource)]
+pub struct Generators {
+    pub names: Vec<String>,
+    pub states: Vec<GeneratorState>,
+}
+
+impl Default for LfoConfig {
+    fn default() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            beats: 4.0,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Default for EnvelopeConfig {
+    fn default() -> Self {
+        Self {
+            trigger: EnvelopeTrigger::Beat,
+            attack: 0.01,
+            decay: 0.2,
+            sustain: 0.5,
+            release: 0.3,
+            hold: 0.1,
+        }
+    }
+}
+
+impl LfoConfig {
+    // The phase through the period and the value at a position on the beat grid
+    pub fn sample(&self, beat_position: f64) -> (f32, f32) {
+        let position = beat_position / self.beats.max(f32::EPSILON) as f64 + self.phase as f64;
+        let phase = position.rem_euclid(1.0) as f32;
+
+        (phase, self.shape.sample(phase, position.floor() as u64))
+    }
+}
+
+impl LfoShape {
+    fn sample(&self, phase: f32, period: u64) -> f32 {
+        match self {
+            LfoShape::Sine => 0.5 - 0.5 * (phase * TAU).cos(),
+            LfoShape::Saw => phase,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            LfoShape::SampleAndHold => random(period),
+        }
+    }
+}
+
+// Repeatable noise from 0 to 1, so sample and hold LFOs with the same period agree
+fn random(seed: u64) -> f32 {
+    let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    state ^= state >> 31;
+    state = state.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    state ^= state >> 29;
+
+    (state >> 40) as f32 / (1u64 << 24) as f32
+}
+
+impl EnvelopeConfig {
+    // A trigger restarts the attack, and the envelope releases once the gate closes
+    fn advance(&self, state: &mut GeneratorState, trigger: bool, gate: bool, delta_time: f32) {
+        if trigger {
+            state.stage = EnvelopeStage::Attack;
+            state.since_trigger = 0.0;
+        } else {
+            state.since_trigger += delta_time;
+        }
+        if !gate && matches!(state.stage, EnvelopeStage::Attack | EnvelopeStage::Decay) {
+            state.stage = EnvelopeStage::Release;
+        }
+        state.gate = gate;
+
+        let rate = |seconds: f32| delta_time / seconds.max(f32::EPSILON);
+
+        match state.stage {
+            EnvelopeStage::Idle => state.value = 0.0,
+            EnvelopeStage::Attack => {
+                state.value += rate(self.attack);
+                if state.value >= 1.0 {
+                    state.value = 1.0;
+                    state.stage = EnvelopeStage::Decay;
+                }
+            }
+            // Decay settles on the sustain level until the gate closes
+            EnvelopeStage::Decay => {
+                state.value =
+                    (state.value - rate(self.decay) * (1.0 - self.sustain)).max(self.sustain);
+            }
+            EnvelopeStage::Release => {
+                state.value -= rate(self.release);
+                if state.value <= 0.0 {
+                    state.value = 0.0;
+                    state.stage = EnvelopeStage::Idle;
+                }
+            }
+        }
+
+        state.phase = state.since_trigger;
+    }
+}
+
+impl Generators {
+    pub fn new(config: &GeneratorsConfig) -> Generators {
+        let names: Vec<String> = config
+            .lfos
+            .keys()
+            .chain(config.envelopes.keys())
+            .cloned()
+            .collect();
+
+        Generators {
+            states: vec![GeneratorState::default(); names.len()],
+            names,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        let index = self.names.iter().position(|other| other == name)?;
+        self.states.get(index).map(|state| state.value)
+    }
+}
+
+impl Plugin for GeneratorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update.after(crate::analysis::update));
+    }
+}
+
+pub fn update(
+    mut generators: ResMut<Generators>,
+    mut generator_data: ResMut<AudiolinkGeneratorData>,
+    analysis: Res<AudioAnalysis>,
+    beat_grid: Res<BeatGrid>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let delta_time = time.delta_secs();
+    let beat_position = beat_grid.free_position();
+    let config = &config.generators;
+    let lfo_count = config.lfos.len();
+
+    for (state, lfo) in generators.states.iter_mut().zip(config.lfos.values()) {
+        (state.phase, state.value) = lfo.sample(beat_position);
+    }
+
+    for (state, envelope) in generators
+        .states
+        .iter_mut()
+        .skip(lfo_count)
+        .zip(config.envelopes.values())
+    {
+        let (trigger, gate) = match envelope.trigger {
+            EnvelopeTrigger::Beat => (
+                analysis.beat,
+                analysis.beat || state.since_trigger < envelope.hold,
+            ),
+            EnvelopeTrigger::Beats(beats) => {
+                let trigger = analysis.beat && analysis.beats % beats.max(1) == 0;
+                (trigger, trigger || state.since_trigger < envelope.hold)
+            }
+            EnvelopeTrigger::Key(key) => (keyboard.just_pressed(key), keyboard.pressed(key)),
+        };
+
+        envelope.advance(state, trigger, gate, delta_time);
+    }
+
+    // One texel each in the generator row: the value, the LFO's phase or seconds since the envelope
+    // was triggered, whether the envelope's gate is open, and zero
+    for (texel, state) in generator_data.0.iter_mut().zip(generators.states.iter()) {
+        *texel = [
+            state.value,
+            state.phase,
+            if state.gate { 1.0 } else { 0.0 },
+            0.0,
+        ];
+    }
+}
@@ -0,0 +1,676 @@
+use std::f32::consts::TAU;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    camera::visibility::NoFrustumCulling,
+    mesh::{Indices, MeshVertexBufferLayoutRef, PrimitiveTopology},
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::*,
+    render::render_resource::{
+        AsBindGroup, Extent3d, RenderPipelineDescriptor, ShaderType, SpecializedMeshPipelineError,
+        TextureDimension, TextureFormat,
+    },
+    shader::ShaderRef,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::AudioAnalysis,
+    audiolink::{AudiolinkDataTexture, AudiolinkReadback, DFT_BINS, WAVEFORM_FIRST_ROW},
+    config::Config,
+    palette::PaletteTexture,
+    parameters::Parameters,
+    scene::Scenes,
+};
+
+pub const BARS_SHADER_ASSET_PATH: &str = "bars.wgsl";
+pub const TERRAIN_SHADER_ASSET_PATH: &str = "terrain.wgsl";
+pub const TUNNEL_SHADER_ASSET_PATH: &str = "tunnel.wgsl";
+
+pub const BARS_SCENE: &str = "bars";
+pub const TERRAIN_SCENE: &str = "terrain";
+pub const TUNNEL_SCENE: &str = "tunnel";
+
+// Rows of waveform history kept for the terrain, one per readback, each of the newest samples
+pub const TERRAIN_ROWS: u32 = 128;
+pub const TERRAIN_COLUMNS: u32 = 256;
+
+pub const TUNNEL_RINGS: u32 = 96;
+pub const TUNNEL_SEGMENTS: u32 = 48;
+
+pub const ROLL_SMOOTHING: f32 = 4.0;
+
+pub struct GeometryPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeometryConfig {
+    pub bars: GeometryPlacement,
+    pub terrain: GeometryPlacement,
+    pub tunnel: GeometryPlacement,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeometryPlacement {
+    pub translation: Vec3,
+    // Degrees around the x axis, tilting the scene towards the camera
+    pub tilt: f32,
+    pub scale: f32,
+}
+
+#[derive(Component)]
+pub struct Bars {
+    pub material_handle: Handle<BarsMaterial>,
+}
+
+#[derive(Component)]
+pub struct Terrain {
+    pub material_handle: Handle<TerrainMaterial>,
+}
+
+#[derive(Component)]
+pub struct Tunnel {
+    pub material_handle: Handle<TunnelMaterial>,
+}
+
+// A ring buffer of waveform rows, `cursor` being the newest
+#[derive(Resource)]
+pub struct WaveformHistory {
+    pub image: Handle<Image>,
+    pub cursor: u32,
+}
+
+// How far the camera has flown down the tunnel, and its roll
+#[derive(Resource, Default)]
+pub struct TunnelMotion {
+    pub travel: f32,
+    pub roll: f32,
+    pub target_roll: f32,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct BarsMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    audiolink_texture: Option<Handle<Image>>,
+    #[texture(2)]
+    #[sampler(3)]
+    palette_texture: Option<Handle<Image>>,
+    #[uniform(4)]
+    settings: BarsSettings,
+}
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+pub struct BarsSettings {
+    height: f32,
+    floor: f32,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct TerrainMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    audiolink_texture: Option<Handle<Image>>,
+    #[texture(2)]
+    #[sampler(3)]
+    palette_texture: Option<Handle<Image>>,
+    #[texture(4, sample_type = "float", filterable = false)]
+    #[sampler(5, sampler_type = "non_filtering")]
+    history_texture: Option<Handle<Image>>,
+    #[uniform(6)]
+    settings: TerrainSettings,
+}
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+pub struct TerrainSettings {
+    height: f32,
+    cursor: u32,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct TunnelMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    audiolink_texture: Option<Handle<Image>>,
+    #[texture(2)]
+    #[sampler(3)]
+    palette_texture: Option<Handle<Image>>,
+    #[uniform(4)]
+    settings: TunnelSettings,
+}
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+pub struct TunnelSettings {
+    travel: f32,
+    roll: f32,
+    curve: f32,
+    bulge: f32,
+    length: f32,
+}
+
+impl Default for GeometryConfig {
+    fn default() -> Self {
+        Self {
+            bars: GeometryPlacement {
+                translation: Vec3::new(0.0, -150.0, -900.0),
+                tilt: 20.0,
+                scale: 350.0,
+            },
+            terrain: GeometryPlacement {
+                translation: Vec3::new(0.0, -250.0, -700.0),
+                tilt: 0.0,
+                scale: 600.0,
+            },
+            tunnel: GeometryPlacement {
+                translation: Vec3::ZERO,
+                tilt: 0.0,
+                scale: 150.0,
+            },
+        }
+    }
+}
+
+impl Default for GeometryPlacement {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::new(0.0, 0.0, -900.0),
+            tilt: 0.0,
+            scale: 300.0,
+        }
+    }
+}
+
+impl GeometryPlacement {
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.translation,
+            rotation: Quat::from_rotation_x(self.tilt.to_radians()),
+            scale: Vec3::splat(self.scale),
+        }
+    }
+}
+
+// The generated meshes are viewed from any side, the tunnel from the inside
+fn double_sided(descriptor: &mut RenderPipelineDescriptor) {
+    descriptor.primitive.cull_mode = None;
+}
+
+impl Material for BarsMaterial {
+    fn vertex_shader() -> ShaderRef {
+        BARS_SHADER_ASSET_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        BARS_SHADER_ASSET_PATH.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        double_sided(descriptor);
+        Ok(())
+    }
+}
+
+impl Material for TerrainMaterial {
+    fn vertex_shader() -> ShaderRef {
+        TERRAIN_SHADER_ASSET_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        TERRAIN_SHADER_ASSET_PATH.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        double_sided(descriptor);
+        Ok(())
+    }
+}
+
+impl Material for TunnelMaterial {
+    fn vertex_shader() -> ShaderRef {
+        TUNNEL_SHADER_ASSET_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        TUNNEL_SHADER_ASSET_PATH.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        double_sided(descriptor);
+        Ok(())
+    }
+}
+
+impl Plugin for GeometryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            MaterialPlugin::<BarsMaterial>::default(),
+            MaterialPlugin::<TerrainMaterial>::default(),
+            MaterialPlugin::<TunnelMaterial>::default(),
+        ))
+        .init_resource::<TunnelMotion>()
+        .add_systems(
+            Startup,
+            setup
+                .after(crate::audiolink::setup)
+                .after(crate::palette::setup)
+                .before(crate::control::setup),
+        )
+        .add_systems(
+            Update,
+            (
+                update_waveform_history.after(crate::audiolink::update_readback),
+                update_tunnel_motion.after(crate::analysis::update),
+                update
+                    .after(crate::audiolink::update)
+                    .after(update_waveform_history)
+                    .after(update_tunnel_motion),
+            ),
+        );
+    }
+}
+
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn new() -> MeshBuilder {
+        MeshBuilder {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> u32 {
+        self.positions.push(position.to_array());
+        self.normals.push(normal.to_array());
+        self.uvs.push(uv.to_array());
+        self.positions.len() as u32 - 1
+    }
+
+    fn quad(&mut self, corners: [u32; 4]) {
+        let [a, b, c, d] = corners;
+        self.indices.extend([a, b, c, a, c, d]);
+    }
+
+    fn build(self) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+        .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+// One box per DFT bin around a unit ring, one unit tall. The uv holds the bin and whether the vertex
+// is on top, which the vertex shader raises by the bin's magnitude
+fn bars_mesh() -> Mesh {
+    let mut builder = MeshBuilder::new();
+    let half_width = TAU / DFT_BINS as f32 * 0.4;
+    let half_depth = 0.04;
+
+    for bin in 0..DFT_BINS {
+        let u = (bin as f32 + 0.5) / DFT_BINS as f32;
+        let rotation = Quat::from_rotation_y(-u * TAU);
+        let center = rotation * Vec3::Z;
+
+        let corner = |x: f32, y: f32, z: f32| center + rotation * Vec3::new(x, y, z);
+        let faces = [
+            (Vec3::Z, [(-1.0, 1.0), (1.0, 1.0)]),
+            (Vec3::NEG_Z, [(1.0, -1.0), (-1.0, -1.0)]),
+            (Vec3::X, [(1.0, 1.0), (1.0, -1.0)]),
+            (Vec3::NEG_X, [(-1.0, -1.0), (-1.0, 1.0)]),
+        ];
+
+        for (normal, [(x0, z0), (x1, z1)]) in faces {
+            let normal = rotation * normal;
+            let bottom_0 = builder.vertex(
+                corner(x0 * half_width, 0.0, z0 * half_depth),
+                normal,
+                Vec2::new(u, 0.0),
+            );
+            let bottom_1 = builder.vertex(
+                corner(x1 * half_width, 0.0, z1 * half_depth),
+                normal,
+                Vec2::new(u, 0.0),
+            );
+            let top_1 = builder.vertex(
+                corner(x1 * half_width, 1.0, z1 * half_depth),
+                normal,
+                Vec2::new(u, 1.0),
+            );
+            let top_0 = builder.vertex(
+                corner(x0 * half_width, 1.0, z0 * half_depth),
+                normal,
+                Vec2::new(u, 1.0),
+            );
+            builder.quad([bottom_0, bottom_1, top_1, top_0]);
+        }
+
+        let normal = Vec3::Y;
+        let top = [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)].map(|(x, z)| {
+            builder.vertex(
+                corner(x * half_width, 1.0, z * half_depth),
+                normal,
+                Vec2::new(u, 1.0),
+            )
+        });
+        builder.quad(top);
+    }
+
+    builder.build()
+}
+
+// A unit grid over x and z, one column per DFT bin and one row per frame of history, the newest at
+// the front edge
+fn terrain_mesh() -> Mesh {
+    let mut builder = MeshBuilder::new();
+
+    for row in 0..TERRAIN_ROWS {
+        let v = row as f32 / (TERRAIN_ROWS - 1) as f32;
+        for column in 0..TERRAIN_COLUMNS {
+            let u = column as f32 / (TERRAIN_COLUMNS - 1) as f32;
+            builder.vertex(
+                Vec3::new(u * 2.0 - 1.0, 0.0, 1.0 - v * 2.0),
+                Vec3::Y,
+                Vec2::new(u, v),
+            );
+        }
+    }
+
+    for row in 0..TERRAIN_ROWS - 1 {
+        for column in 0..TERRAIN_COLUMNS - 1 {
+            let index = row * TERRAIN_COLUMNS + column;
+            builder.quad([
+                index,
+                index + 1,
+                index + TERRAIN_COLUMNS + 1,
+                index + TERRAIN_COLUMNS,
+            ]);
+        }
+    }
+
+    builder.build()
+}
+
+// An open tube of unit radius along -z, one unit long, seen from the inside
+fn tunnel_mesh() -> Mesh {
+    let mut builder = MeshBuilder::new();
+
+    for ring in 0..=TUNNEL_RINGS {
+        let v = ring as f32 / TUNNEL_RINGS as f32;
+        for segment in 0..=TUNNEL_SEGMENTS {
+            let u = segment as f32 / TUNNEL_SEGMENTS as f32;
+            let (sin, cos) = (u * TAU).sin_cos();
+            builder.vertex(
+                Vec3::new(cos, sin, -v),
+                Vec3::new(-cos, -sin, 0.0),
+                Vec2::new(u, v),
+            );
+        }
+    }
+
+    let stride = TUNNEL_SEGMENTS + 1;
+    for ring in 0..TUNNEL_RINGS {
+        for segment in 0..TUNNEL_SEGMENTS {
+            let index = ring * stride + segment;
+            builder.quad([index, index + stride, index + stride + 1, index + 1]);
+        }
+    }
+
+    builder.build()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut bars_materials: ResMut<Assets<BarsMaterial>>,
+    mut terrain_materials: ResMut<Assets<TerrainMaterial>>,
+    mut tunnel_materials: ResMut<Assets<TunnelMaterial>>,
+    mut parameters: ResMut<Parameters>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+    palette_texture: Res<PaletteTexture>,
+    scenes: Res<Scenes>,
+    config: Res<Config>,
+) {
+    let audiolink_texture = Some(audiolink_data_texture.0.clone());
+    let palette_texture = Some(palette_texture.0.clone());
+    let geometry = &config.geometry;
+
+    if scenes.index_of(BARS_SCENE).is_some() {
+        parameters.register("bars.height", 1.0, 0.0, 4.0, 0.05);
+        parameters.register("bars.floor", 0.02, 0.0, 0.5, 0.01);
+        parameters.register("bars.spin", 6.0, -90.0, 90.0, 1.0);
+
+        let material_handle = bars_materials.add(BarsMaterial {
+            audiolink_texture: audiolink_texture.clone(),
+            palette_texture: palette_texture.clone(),
+            settings: BarsSettings::default(),
+        });
+
+        commands.spawn((
+            Mesh3d(meshes.add(bars_mesh())),
+            MeshMaterial3d(material_handle.clone()),
+            geometry.bars.transform(),
+            NoFrustumCulling,
+            Bars { material_handle },
+            scenes.render_layers(BARS_SCENE),
+        ));
+    }
+
+    if scenes.index_of(TERRAIN_SCENE).is_some() {
+        parameters.register("terrain.height", 0.4, 0.0, 2.0, 0.05);
+
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: TERRAIN_COLUMNS,
+                height: TERRAIN_ROWS,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &0.0_f32.to_le_bytes(),
+            TextureFormat::R32Float,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = bevy::image::ImageSampler::nearest();
+        let history = images.add(image);
+
+        let material_handle = terrain_materials.add(TerrainMaterial {
+            audiolink_texture: audiolink_texture.clone(),
+            palette_texture: palette_texture.clone(),
+            history_texture: Some(history.clone()),
+            settings: TerrainSettings::default(),
+        });
+
+        commands.spawn((
+            Mesh3d(meshes.add(terrain_mesh())),
+            MeshMaterial3d(material_handle.clone()),
+            geometry.terrain.transform(),
+            NoFrustumCulling,
+            Terrain { material_handle },
+            scenes.render_layers(TERRAIN_SCENE),
+        ));
+
+        commands.insert_resource(WaveformHistory {
+            image: history,
+            cursor: 0,
+        });
+    }
+
+    if scenes.index_of(TUNNEL_SCENE).is_some() {
+        parameters.register("tunnel.speed", 0.3, 0.0, 4.0, 0.05);
+        parameters.register("tunnel.beat_boost", 1.5, 0.0, 8.0, 0.1);
+        parameters.register("tunnel.beat_roll", 15.0, 0.0, 90.0, 1.0);
+        parameters.register("tunnel.curve", 0.4, 0.0, 2.0, 0.05);
+        parameters.register("tunnel.bulge", 0.5, 0.0, 2.0, 0.05);
+        parameters.register("tunnel.length", 20.0, 2.0, 60.0, 1.0);
+
+        let material_handle = tunnel_materials.add(TunnelMaterial {
+            audiolink_texture,
+            palette_texture,
+            settings: TunnelSettings::default(),
+        });
+
+        commands.spawn((
+            Mesh3d(meshes.add(tunnel_mesh())),
+            MeshMaterial3d(material_handle.clone()),
+            geometry.tunnel.transform(),
+            NoFrustumCulling,
+            Tunnel { material_handle },
+            scenes.render_layers(TUNNEL_SCENE),
+        ));
+    }
+}
+
+pub fn update_waveform_history(
+    waveform_history: Option<ResMut<WaveformHistory>>,
+    mut images: ResMut<Assets<Image>>,
+    audiolink_readback: Res<AudiolinkReadback>,
+) {
+    let Some(mut waveform_history) = waveform_history else {
+        return;
+    };
+
+    // The history only scrolls when a new readback arrives, so rows never repeat
+    if !audiolink_readback.is_changed() {
+        return;
+    }
+    let width = audiolink_readback.width.max(1);
+
+    let row: Vec<u8> = (0..TERRAIN_COLUMNS)
+        .flat_map(|sample| {
+            audiolink_readback
+                .get(sample % width, WAVEFORM_FIRST_ROW + sample / width)
+                .map(|texel| texel.x)
+                .unwrap_or_default()
+                .to_le_bytes()
+        })
+        .collect();
+
+    waveform_history.cursor = (waveform_history.cursor + 1) % TERRAIN_ROWS;
+
+    let Some(data) = images
+        .get_mut(waveform_history.image.id())
+        .and_then(|image| image.data.as_mut())
+    else {
+        return;
+    };
+
+    let start = waveform_history.cursor as usize * row.len();
+    if let Some(destination) = data.get_mut(start..start + row.len()) {
+        destination.copy_from_slice(&row);
+    }
+}
+
+pub fn update_tunnel_motion(
+    mut tunnel_motion: ResMut<TunnelMotion>,
+    parameters: Res<Parameters>,
+    analysis: Res<AudioAnalysis>,
+    time: Res<Time>,
+) {
+    let get = |name: &str| parameters.get(name).unwrap_or_default();
+    let delta_time = time.delta_secs();
+
+    // Flying speed jumps with each beat and eases back, and every beat rolls the camera a step
+    let speed = get("tunnel.speed") * (1.0 + get("tunnel.beat_boost") * analysis.beat_pulse);
+    tunnel_motion.travel += speed * delta_time;
+
+    if analysis.beat {
+        let step = get("tunnel.beat_roll").to_radians();
+        tunnel_motion.target_roll += if analysis.beats % 2 == 0 { step } else { -step };
+    }
+    let difference = tunnel_motion.target_roll - tunnel_motion.roll;
+    tunnel_motion.roll += difference * (ROLL_SMOOTHING * delta_time).min(1.0);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn update(
+    mut bars: Query<(&Bars, &mut Transform), (Without<Terrain>, Without<Tunnel>)>,
+    terrain: Query<&Terrain>,
+    mut tunnel: Query<(&Tunnel, &mut Transform), Without<Bars>>,
+    mut bars_materials: ResMut<Assets<BarsMaterial>>,
+    mut terrain_materials: ResMut<Assets<TerrainMaterial>>,
+    mut tunnel_materials: ResMut<Assets<TunnelMaterial>>,
+    waveform_history: Option<Res<WaveformHistory>>,
+    tunnel_motion: Res<TunnelMotion>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+    parameters: Res<Parameters>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let get = |name: &str| parameters.get(name).unwrap_or_default();
+    let audiolink_texture = Some(audiolink_data_texture.0.clone());
+
+    for (bars, mut transform) in bars.iter_mut() {
+        let spin = Quat::from_rotation_y((get("bars.spin") * time.elapsed_secs()).to_radians());
+        transform.rotation = config.geometry.bars.transform().rotation * spin;
+
+        if let Some(material) = bars_materials.get_mut(bars.material_handle.id()) {
+            material.audiolink_texture = audiolink_texture.clone();
+            material.settings = BarsSettings {
+                height: get("bars.height"),
+                floor: get("bars.floor"),
+            };
+        }
+    }
+
+    for terrain in terrain.iter() {
+        if let Some(material) = terrain_materials.get_mut(terrain.material_handle.id()) {
+            material.audiolink_texture = audiolink_texture.clone();
+            material.settings = TerrainSettings {
+                height: get("terrain.height"),
+                cursor: waveform_history
+                    .as_ref()
+                    .map(|waveform_history| waveform_history.cursor)
+                    .unwrap_or(0),
+            };
+        }
+    }
+
+    for (tunnel, mut transform) in tunnel.iter_mut() {
+        // The tube is stretched along z so the rings stay round however long it is
+        let placement = &config.geometry.tunnel;
+        transform.scale = Vec3::new(
+            placement.scale,
+            placement.scale,
+            placement.scale * get("tunnel.length"),
+        );
+
+        if let Some(material) = tunnel_materials.get_mut(tunnel.material_handle.id()) {
+            material.audiolink_texture = audiolink_texture.clone();
+            material.settings = TunnelSettings {
+                travel: tunnel_motion.travel,
+                roll: tunnel_motion.roll,
+                curve: get("tunnel.curve"),
+                bulge: get("tunnel.bulge"),
+                length: get("tunnel.length"),
+            };
+        }
+    }
+}
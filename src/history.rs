@@ -0,0 +1,221 @@
+use bevy::{
+    camera::{RenderTarget, visibility::RenderLayers},
+    image::BevyDefault,
+    prelude::*,
+    render::render_resource::{AsBindGroup, Extent3d, ShaderType, TextureFormat},
+    shader::ShaderRef,
+    sprite_render::{Material2d, Material2dPlugin},
+};
+
+use crate::{
+    output::{Canvas, OUTPUT_CAMERA_ORDER},
+    parameters::Parameters,
+};
+
+pub const SHADER_ASSET_PATH: &str = "history.wgsl";
+
+pub const HISTORY_RENDER_LAYER: usize = 47;
+pub const HISTORY_CAMERA_ORDER: isize = OUTPUT_CAMERA_ORDER - 1;
+// The frame rate history.zoom, history.rotation and history.decay are tuned for
+pub const REFERENCE_FRAME_RATE: f32 = 60.0;
+
+pub struct HistoryPlugin;
+
+// Two frame sized images swapped every frame, like `AudiolinkImages`: one holds the previous frame
+// while the history pass writes the next one into the other
+#[derive(Resource, Clone)]
+pub struct FrameHistoryImages {
+    texture_a: Handle<Image>,
+    texture_b: Handle<Image>,
+}
+
+// The image written on the previous frame, which materials bind as `previous_frame`
+#[derive(Resource, Clone)]
+pub struct PreviousFrame(pub Handle<Image>);
+
+#[derive(Component)]
+pub struct HistoryCamera;
+
+#[derive(Component)]
+pub struct HistoryPass {
+    pub material_handle: Handle<HistoryMaterial>,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct HistoryMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    frame_texture: Handle<Image>,
+    #[texture(2)]
+    #[sampler(3)]
+    previous_frame: Handle<Image>,
+    #[uniform(4)]
+    settings: HistorySettings,
+}
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+pub struct HistorySettings {
+    resolution: Vec2,
+    zoom: f32,
+    rotation: f32,
+    decay: f32,
+}
+
+impl FrameHistoryImages {
+    pub fn other(&self, handle: &Handle<Image>) -> Handle<Image> {
+        if *handle == self.texture_a {
+            self.texture_b.clone()
+        } else {
+            self.texture_a.clone()
+        }
+    }
+}
+
+impl HistorySettings {
+    // The parameters are per frame at REFERENCE_FRAME_RATE, and compound over however many of
+    // those frames this one lasted so trails move at the same speed at any frame rate
+    fn from_parameters(
+        parameters: &Parameters,
+        resolution: Vec2,
+        delta_time: f32,
+    ) -> HistorySettings {
+        let get = |name: &str| parameters.get(name).unwrap_or_default();
+        let frames = delta_time * REFERENCE_FRAME_RATE;
+
+        HistorySettings {
+            resolution,
+            zoom: get("history.zoom").powf(frames),
+            rotation: get("history.rotation").to_radians() * frames,
+            decay: get("history.decay").powf(frames),
+        }
+    }
+}
+
+impl Material2d for HistoryMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<HistoryMaterial>::default())
+            .add_systems(
+                Startup,
+                setup
+                    .after(crate::output::setup)
+                    .before(crate::control::setup),
+            )
+            .add_systems(
+                Update,
+                (
+                    resize_history.after(crate::output::resize_outputs),
+                    update.after(resize_history),
+                ),
+            );
+    }
+}
+
+// After post-processing, the history pass blends the final canvas with the previous frame, zoomed,
+// rotated and decayed, so trails accumulate across frames
+pub fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<HistoryMaterial>>,
+    mut parameters: ResMut<Parameters>,
+    canvas: Res<Canvas>,
+) {
+    parameters.register("history.zoom", 1.0, 0.9, 1.1, 0.005);
+    parameters.register("history.rotation", 0.0, -10.0, 10.0, 0.25);
+    parameters.register("history.decay", 0.0, 0.0, 0.99, 0.01);
+
+    let frame_image =
+        || Image::new_target_texture(canvas.size.x, canvas.size.y, TextureFormat::bevy_default());
+    let image_a = images.add(frame_image());
+    let image_b = images.add(frame_image());
+
+    let material_handle = materials.add(HistoryMaterial {
+        frame_texture: canvas.image.clone(),
+        previous_frame: image_a.clone(),
+        settings: HistorySettings::from_parameters(
+            &parameters,
+            canvas.size.as_vec2(),
+            1.0 / REFERENCE_FRAME_RATE,
+        ),
+    });
+
+    commands.spawn((
+        Camera2d,
+        Camera {
+            order: HISTORY_CAMERA_ORDER,
+            target: RenderTarget::Image(image_b.clone().into()),
+            ..default()
+        },
+        Msaa::Off,
+        RenderLayers::layer(HISTORY_RENDER_LAYER),
+        HistoryCamera,
+    ));
+
+    commands.spawn((
+        Mesh2d(meshes.add(Rectangle::new(1.0, 1.0))),
+        MeshMaterial2d(material_handle.clone()),
+        Transform::from_scale(canvas.size.as_vec2().extend(1.0)),
+        RenderLayers::layer(HISTORY_RENDER_LAYER),
+        HistoryPass { material_handle },
+    ));
+
+    commands.insert_resource(PreviousFrame(image_a.clone()));
+    commands.insert_resource(FrameHistoryImages {
+        texture_a: image_a,
+        texture_b: image_b,
+    });
+}
+
+pub fn resize_history(
+    canvas: Res<Canvas>,
+    frame_history_images: Res<FrameHistoryImages>,
+    mut images: ResMut<Assets<Image>>,
+    mut history_pass: Single<&mut Transform, With<HistoryPass>>,
+) {
+    if !canvas.is_changed() || canvas.is_added() {
+        return;
+    }
+
+    for handle in [
+        &frame_history_images.texture_a,
+        &frame_history_images.texture_b,
+    ] {
+        if let Some(image) = images.get_mut(handle.id()) {
+            image.resize(Extent3d {
+                width: canvas.size.x,
+                height: canvas.size.y,
+                depth_or_array_layers: 1,
+            });
+        }
+    }
+
+    history_pass.scale = canvas.size.as_vec2().extend(1.0);
+}
+
+pub fn update(
+    mut previous_frame: ResMut<PreviousFrame>,
+    mut history_camera: Single<&mut Camera, With<HistoryCamera>>,
+    history_pass: Single<&HistoryPass>,
+    frame_history_images: Res<FrameHistoryImages>,
+    mut materials: ResMut<Assets<HistoryMaterial>>,
+    parameters: Res<Parameters>,
+    canvas: Res<Canvas>,
+    time: Res<Time>,
+) {
+    // What the history pass wrote last frame becomes the previous frame, and the other image the target
+    previous_frame.0 = frame_history_images.other(&previous_frame.0);
+    history_camera.target =
+        RenderTarget::Image(frame_history_images.other(&previous_frame.0).into());
+
+    if let Some(material) = materials.get_mut(history_pass.material_handle.id()) {
+        material.previous_frame = previous_frame.0.clone();
+        material.settings =
+            HistorySettings::from_parameters(&parameters, canvas.size.as_vec2(), time.delta_secs());
+    }
+}
@@ -0,0 +1,405 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    camera::{RenderTarget, visibility::RenderLayers},
+    image::BevyDefault,
+    prelude::*,
+    render::render_resource::{AsBindGroup, Extent3d, ShaderType, TextureDimension, TextureFormat},
+    shader::ShaderRef,
+    sprite_render::{Material2d, Material2dPlugin},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{AudioAnalysis, Signal},
+    config::Config,
+    output::Canvas,
+    parameters::Parameters,
+    scene::{OVERLAY_RENDER_LAYER, SCENE_RENDER_LAYER_BASE, Scenes},
+    text::TEXT_RENDER_LAYER,
+};
+
+pub const SHADER_ASSET_PATH: &str = "composite.wgsl";
+
+pub const COMPOSITE_RENDER_LAYER_BASE: usize = 64;
+
+// Layers render first, then the compositor blends them into the canvas' scene image before post-processing
+pub const LAYER_CAMERA_ORDER: isize = -100;
+pub const COMPOSITE_CAMERA_ORDER_BASE: isize = -99;
+
+pub struct LayerPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum LayerSource {
+    // Whatever scene is currently on program
+    Program,
+    Scene(String),
+    Logo,
+    // A clip bank from `media`, whose decoded frames are composited directly
+    Media(String),
+    // The title, ticker and lyrics from `text`
+    Text,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Add,
+    Screen,
+    Multiply,
+    Difference,
+    LumaKey,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayerConfig {
+    pub name: String,
+    pub source: LayerSource,
+    pub blend: BlendMode,
+    pub opacity: f32,
+    pub key_threshold: f32,
+    pub key_softness: f32,
+    pub modulation: Option<LayerModulation>,
+}
+
+// Adds `depth * signal` to the layer's opacity, a negative depth ducking it instead
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LayerModulation {
+    pub signal: Signal,
+    pub depth: f32,
+}
+
+#[derive(Component)]
+pub struct LayerCamera {
+    pub index: usize,
+}
+
+#[derive(Component)]
+pub struct CompositePass {
+    pub index: usize,
+    pub material_handle: Handle<CompositeMaterial>,
+}
+
+#[derive(Resource)]
+pub struct LayerImages {
+    pub layers: Vec<Handle<Image>>,
+    pub composite: [Handle<Image>; 2],
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct CompositeMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    base_texture: Handle<Image>,
+    #[texture(2)]
+    #[sampler(3)]
+    layer_texture: Handle<Image>,
+    #[uniform(4)]
+    settings: CompositeSettings,
+}
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+pub struct CompositeSettings {
+    blend: u32,
+    has_base: u32,
+    opacity: f32,
+    key_threshold: f32,
+    key_softness: f32,
+}
+
+impl Default for LayerConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            source: LayerSource::Program,
+            blend: BlendMode::Normal,
+            opacity: 1.0,
+            key_threshold: 0.1,
+            key_softness: 0.1,
+            modulation: None,
+        }
+    }
+}
+
+impl LayerConfig {
+    pub fn defaults() -> Vec<LayerConfig> {
+        vec![
+            LayerConfig {
+                name: "scene".to_owned(),
+                source: LayerSource::Program,
+                ..default()
+            },
+            LayerConfig {
+                name: "logo".to_owned(),
+                source: LayerSource::Logo,
+                ..default()
+            },
+        ]
+    }
+
+    fn opacity_parameter(&self) -> String {
+        format!("layers.{}.opacity", self.name)
+    }
+
+    fn render_layers(&self, scenes: &Scenes) -> RenderLayers {
+        match &self.source {
+            LayerSource::Program => RenderLayers::layer(SCENE_RENDER_LAYER_BASE + scenes.program),
+            LayerSource::Scene(name) => scenes.render_layers(name),
+            LayerSource::Logo => RenderLayers::layer(OVERLAY_RENDER_LAYER),
+            LayerSource::Media(_) => RenderLayers::none(),
+            LayerSource::Text => RenderLayers::layer(TEXT_RENDER_LAYER),
+        }
+    }
+
+    pub fn has_camera(&self) -> bool {
+        !matches!(self.source, LayerSource::Media(_))
+    }
+}
+
+impl BlendMode {
+    // Matches the BLEND_* constants in composite.wgsl
+    fn shader_index(&self) -> u32 {
+        match self {
+            BlendMode::Normal => 0,
+            BlendMode::Add => 1,
+            BlendMode::Screen => 2,
+            BlendMode::Multiply => 3,
+            BlendMode::Difference => 4,
+            BlendMode::LumaKey => 5,
+        }
+    }
+}
+
+impl Material2d for CompositeMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+impl Plugin for LayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<CompositeMaterial>::default())
+            .add_systems(
+                Startup,
+                setup
+                    .after(crate::scene::setup)
+                    .before(crate::control::setup),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_layer_sources.after(crate::scene::control_scenes),
+                    resize_layer_images.after(crate::output::resize_outputs),
+                    update_composite_passes
+                        .after(crate::analysis::update)
+                        .after(crate::media::update_media),
+                ),
+            );
+    }
+}
+
+fn target_image(size: UVec2) -> Image {
+    Image::new_target_texture(size.x, size.y, TextureFormat::bevy_default())
+}
+
+// Every layer has its own camera and image, blended in order by a chain of composite passes that
+// ping-pong between two images, the last one writing the canvas' scene image
+#[allow(clippy::too_many_arguments)]
+pub fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<CompositeMaterial>>,
+    mut parameters: ResMut<Parameters>,
+    scenes: Res<Scenes>,
+    canvas: Res<Canvas>,
+    config: Res<Config>,
+) {
+    let composite_images = [
+        images.add(target_image(canvas.size)),
+        images.add(target_image(canvas.size)),
+    ];
+
+    let mut layer_images = Vec::new();
+    let layer_count = config.layers.len();
+
+    for (index, layer) in config.layers.iter().enumerate() {
+        parameters.register(&layer.opacity_parameter(), layer.opacity, 0.0, 1.0, 0.05);
+
+        // Media players replace their layer's image once frames are decoded
+        let layer_image = if layer.has_camera() {
+            images.add(target_image(canvas.size))
+        } else {
+            images.add(Image::new_fill(
+                Extent3d::default(),
+                TextureDimension::D2,
+                &[0, 0, 0, 0],
+                TextureFormat::bevy_default(),
+                RenderAssetUsages::RENDER_WORLD,
+            ))
+        };
+        layer_images.push(layer_image.clone());
+
+        if layer.has_camera() {
+            let camera = commands
+                .spawn((
+                    Camera {
+                        order: LAYER_CAMERA_ORDER,
+                        target: RenderTarget::Image(layer_image.clone().into()),
+                        clear_color: ClearColorConfig::Custom(Color::NONE),
+                        ..default()
+                    },
+                    layer.render_layers(&scenes),
+                    LayerCamera { index },
+                ))
+                .id();
+
+            // Text is drawn by the 2d pipeline, everything else is a 3d scene
+            if layer.source == LayerSource::Text {
+                commands.entity(camera).insert((Camera2d, Msaa::Off));
+            } else {
+                commands.entity(camera).insert(Camera3d::default());
+            }
+        }
+
+        let base_texture = if index == 0 {
+            layer_image.clone()
+        } else {
+            composite_images[(index - 1) % 2].clone()
+        };
+        let target = if index + 1 == layer_count {
+            canvas.scene.clone()
+        } else {
+            composite_images[index % 2].clone()
+        };
+
+        let material_handle = materials.add(CompositeMaterial {
+            base_texture,
+            layer_texture: layer_image,
+            settings: CompositeSettings::default(),
+        });
+
+        let render_layers = RenderLayers::layer(COMPOSITE_RENDER_LAYER_BASE + index);
+        commands.spawn((
+            Camera2d,
+            Camera {
+                order: COMPOSITE_CAMERA_ORDER_BASE + index as isize,
+                target: RenderTarget::Image(target.into()),
+                ..default()
+            },
+            Msaa::Off,
+            render_layers.clone(),
+        ));
+        commands.spawn((
+            Mesh2d(meshes.add(Rectangle::new(1.0, 1.0))),
+            MeshMaterial2d(material_handle.clone()),
+            Transform::from_scale(canvas.size.as_vec2().extend(1.0)),
+            render_layers,
+            CompositePass {
+                index,
+                material_handle,
+            },
+        ));
+    }
+
+    commands.insert_resource(LayerImages {
+        layers: layer_images,
+        composite: composite_images,
+    });
+}
+
+pub fn apply_layer_sources(
+    mut commands: Commands,
+    scenes: Res<Scenes>,
+    cameras: Query<(Entity, &LayerCamera)>,
+    config: Res<Config>,
+) {
+    if !scenes.is_changed() {
+        return;
+    }
+
+    for (entity, layer_camera) in cameras.iter() {
+        if let Some(layer) = config.layers.get(layer_camera.index)
+            && layer.source == LayerSource::Program
+        {
+            commands.entity(entity).insert(layer.render_layers(&scenes));
+        }
+    }
+}
+
+pub fn resize_layer_images(
+    canvas: Res<Canvas>,
+    layer_images: Res<LayerImages>,
+    mut images: ResMut<Assets<Image>>,
+    mut passes: Query<&mut Transform, With<CompositePass>>,
+    config: Res<Config>,
+) {
+    if !canvas.is_changed() || canvas.is_added() {
+        return;
+    }
+
+    let camera_images = layer_images
+        .layers
+        .iter()
+        .zip(&config.layers)
+        .filter(|(_, layer)| layer.has_camera())
+        .map(|(handle, _)| handle);
+
+    for handle in camera_images.chain(&layer_images.composite) {
+        if let Some(image) = images.get_mut(handle.id()) {
+            image.resize(Extent3d {
+                width: canvas.size.x,
+                height: canvas.size.y,
+                depth_or_array_layers: 1,
+            });
+        }
+    }
+
+    // The materials are touched every frame by update_composite_passes, which rebuilds their bind groups
+    for mut transform in passes.iter_mut() {
+        transform.scale = canvas.size.as_vec2().extend(1.0);
+    }
+}
+
+pub fn update_composite_passes(
+    passes: Query<&CompositePass>,
+    layer_images: Res<LayerImages>,
+    mut materials: ResMut<Assets<CompositeMaterial>>,
+    parameters: Res<Parameters>,
+    analysis: Res<AudioAnalysis>,
+    config: Res<Config>,
+) {
+    for pass in passes.iter() {
+        let (Some(layer), Some(material)) = (
+            config.layers.get(pass.index),
+            materials.get_mut(pass.material_handle.id()),
+        ) else {
+            continue;
+        };
+
+        let modulation = layer
+            .modulation
+            .as_ref()
+            .map(|modulation| modulation.depth * analysis.get(modulation.signal))
+            .unwrap_or_default();
+        let opacity = parameters
+            .get(&layer.opacity_parameter())
+            .unwrap_or(layer.opacity);
+
+        if let Some(layer_image) = layer_images.layers.get(pass.index) {
+            material.layer_texture = layer_image.clone();
+            if pass.index == 0 {
+                material.base_texture = layer_image.clone();
+            }
+        }
+
+        material.settings = CompositeSettings {
+            blend: layer.blend.shader_index(),
+            has_base: u32::from(pass.index > 0),
+            opacity: (opacity + modulation).clamp(0.0, 1.0),
+            key_threshold: layer.key_threshold,
+            key_softness: layer.key_softness,
+        };
+    }
+}
@@ -0,0 +1,720 @@
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, RandomState},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{
+    config::Config,
+    tempo::{BeatGrid, TempoMode},
+};
+
+// Ableton Link's discovery group, where every peer announces its session and timeline
+pub const LINK_GROUP: Ipv4Addr = Ipv4Addr::new(224, 76, 78, 75);
+pub const LINK_PORT: u16 = 20808;
+pub const LINK_BUFFER_SIZE: usize = 512;
+
+// Peers are forgotten this many seconds after their last announcement
+pub const LINK_TTL: u8 = 5;
+pub const LINK_BROADCAST_PERIOD: Duration = Duration::from_millis(250);
+
+// Measuring another session's clock takes this many samples, one ping and pong per frame
+pub const MEASUREMENT_SAMPLES: usize = 100;
+pub const MEASUREMENT_TIMEOUT: Duration = Duration::from_secs(3);
+// Sessions we chose not to join are measured again after this long
+pub const MEASUREMENT_RETRY: Duration = Duration::from_secs(30);
+// Sessions whose clocks are closer than this many microseconds are told apart by their ID
+pub const SESSION_EPSILON: i64 = 500_000;
+
+// Smaller changes to our tempo aren't published, so drift in the detected tempo doesn't flood
+// the session with timelines
+pub const PUBLISH_THRESHOLD: f32 = 0.1;
+// Beats the grid may drift from the timeline before it is republished while alone in a session
+pub const PHASE_THRESHOLD: f64 = 0.05;
+
+const DISCOVERY_HEADER: &[u8; 8] = b"_asdp_v\x01";
+const MEASUREMENT_HEADER: &[u8; 8] = b"_link_v\x01";
+
+const ALIVE: u8 = 1;
+const RESPONSE: u8 = 2;
+const BYE_BYE: u8 = 3;
+const PING: u8 = 1;
+const PONG: u8 = 2;
+
+pub type NodeId = [u8; 8];
+
+pub struct LinkPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkConfig {
+    pub enabled: bool,
+    // Whether the Auto, Manual and Blend tempo modes set the session's tempo, the Link mode only
+    // ever following it
+    pub publish: bool,
+    // Beats in a bar, which the beat grid keeps in phase with the session
+    pub quantum: f32,
+    // The interface to join the discovery group on, unspecified leaving it to the system
+    pub interface: Ipv4Addr,
+}
+
+// Link's fixed point: microseconds per beat, a beat in micro-beats and the time it fell on in
+// microseconds of the session's clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeline {
+    pub micros_per_beat: i64,
+    pub beat_origin: i64,
+    pub time_origin: i64,
+}
+
+// The session's clock is ours offset by `intercept`, shared with the thread answering pings
+pub struct GhostClock {
+    pub start: Instant,
+    pub session: NodeId,
+    pub intercept: i64,
+}
+
+pub enum LinkMessage {
+    Peer {
+        node: NodeId,
+        session: NodeId,
+        timeline: Timeline,
+        endpoint: SocketAddr,
+    },
+    ByeBye(NodeId),
+    // `host_time` is when we sent the ping and `received` when the pong arrived, in our clock
+    Pong {
+        session: NodeId,
+        ghost_time: i64,
+        host_time: i64,
+        previous_ghost_time: Option<i64>,
+        received: i64,
+    },
+}
+
+pub struct LinkPeer {
+    pub session: NodeId,
+    pub timeline: Timeline,
+    pub endpoint: SocketAddr,
+    pub seen: Instant,
+}
+
+pub struct Measurement {
+    pub session: NodeId,
+    pub endpoint: SocketAddr,
+    // Estimates of the session's clock minus ours
+    pub samples: Vec<f64>,
+    pub started: Instant,
+}
+
+#[derive(Resource)]
+pub struct LinkSession {
+    pub node: NodeId,
+    // Where peers ping us to measure our clock
+    pub endpoint: SocketAddrV4,
+    pub timeline: Timeline,
+    pub clock: Arc<Mutex<GhostClock>>,
+    pub peers: HashMap<NodeId, LinkPeer>,
+    pub measurement: Option<Measurement>,
+    pub measured: HashMap<NodeId, Instant>,
+    pub last_broadcast: Option<Instant>,
+    // Peers in our session when it was last logged
+    pub session_peers: usize,
+    receiver: Mutex<mpsc::Receiver<LinkMessage>>,
+    discovery: UdpSocket,
+    measurement_socket: UdpSocket,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            publish: false,
+            quantum: 4.0,
+            interface: Ipv4Addr::UNSPECIFIED,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn new(bpm: f32, beat_origin: i64, time_origin: i64) -> Timeline {
+        Timeline {
+            micros_per_beat: (60_000_000.0 / bpm as f64).round() as i64,
+            beat_origin,
+            time_origin,
+        }
+    }
+
+    pub fn bpm(&self) -> f32 {
+        (60_000_000.0 / self.micros_per_beat as f64) as f32
+    }
+
+    pub fn beats_at(&self, ghost_time: i64) -> f64 {
+        self.beat_origin as f64 / 1e6
+            + (ghost_time - self.time_origin) as f64 / self.micros_per_beat as f64
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        [self.micros_per_beat, self.beat_origin, self.time_origin]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect()
+    }
+
+    fn decode(value: &[u8]) -> Option<Timeline> {
+        let timeline = Timeline {
+            micros_per_beat: read_i64(value.get(0..8)?)?,
+            beat_origin: read_i64(value.get(8..16)?)?,
+            time_origin: read_i64(value.get(16..24)?)?,
+        };
+
+        (timeline.micros_per_beat > 0).then_some(timeline)
+    }
+}
+
+impl GhostClock {
+    pub fn host_time(&self) -> i64 {
+        self.start.elapsed().as_micros() as i64
+    }
+
+    pub fn ghost_time(&self) -> i64 {
+        self.host_time() + self.intercept
+    }
+}
+
+fn read_i64(value: &[u8]) -> Option<i64> {
+    Some(i64::from_be_bytes(*value.first_chunk::<8>()?))
+}
+
+// Payloads are a sequence of four character keys, each followed by its value's size and value
+fn write_entry(buffer: &mut Vec<u8>, key: &[u8; 4], value: &[u8]) {
+    buffer.extend_from_slice(key);
+    buffer.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(value);
+}
+
+fn entries(mut payload: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut entries = Vec::new();
+
+    while let Some((header, rest)) = payload.split_first_chunk::<8>() {
+        let key = [header[0], header[1], header[2], header[3]];
+        let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let Some(value) = rest.get(..size) else {
+            break;
+        };
+
+        entries.push((key, value));
+        payload = &rest[size..];
+    }
+
+    entries
+}
+
+fn find<'a>(entries: &[([u8; 4], &'a [u8])], key: &[u8; 4]) -> Option<&'a [u8]> {
+    entries
+        .iter()
+        .find(|(other, _)| other == key)
+        .map(|(_, value)| *value)
+}
+
+fn parse_announcement(packet: &[u8], source: SocketAddr) -> Option<LinkMessage> {
+    let rest = packet.strip_prefix(DISCOVERY_HEADER)?;
+    // The message type, time to live and group, then the sender's node ID
+    let (&[kind, _, _, _], rest) = rest.split_first_chunk::<4>()?;
+    let (node, payload) = rest.split_first_chunk::<8>()?;
+
+    match kind {
+        BYE_BYE => Some(LinkMessage::ByeBye(*node)),
+        ALIVE | RESPONSE => {
+            let entries = entries(payload);
+            let session = *find(&entries, b"sess")?.first_chunk::<8>()?;
+            let timeline = Timeline::decode(find(&entries, b"tmln")?)?;
+
+            let (address, port) = find(&entries, b"mep4")?.split_first_chunk::<4>()?;
+            let port = u16::from_be_bytes(*port.first_chunk::<2>()?);
+            // Peers that don't know their own address are measured where they announced from
+            let endpoint = match Ipv4Addr::from(*address) {
+                address if address.is_unspecified() => SocketAddr::new(source.ip(), port),
+                address => SocketAddr::from((address, port)),
+            };
+
+            Some(LinkMessage::Peer {
+                node: *node,
+                session,
+                timeline,
+                endpoint,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse_pong(payload: &[u8], received: i64) -> Option<LinkMessage> {
+    let entries = entries(payload);
+
+    Some(LinkMessage::Pong {
+        session: *find(&entries, b"sess")?.first_chunk::<8>()?,
+        ghost_time: read_i64(find(&entries, b"__gt")?)?,
+        host_time: read_i64(find(&entries, b"__ht")?)?,
+        previous_ghost_time: find(&entries, b"_pgt").and_then(read_i64),
+        received,
+    })
+}
+
+fn listen_discovery(socket: UdpSocket, sender: mpsc::Sender<LinkMessage>) {
+    let mut buffer = [0; LINK_BUFFER_SIZE];
+
+    loop {
+        let (length, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) => {
+                warn!("Link discovery socket closed: {err}");
+                return;
+            }
+        };
+
+        if let Some(message) = parse_announcement(&buffer[..length], source)
+            && sender.send(message).is_err()
+        {
+            return;
+        }
+    }
+}
+
+// Pings are answered here rather than in a system, so the frame rate doesn't skew peers'
+// measurements of our clock
+fn listen_measurement(
+    socket: UdpSocket,
+    clock: Arc<Mutex<GhostClock>>,
+    sender: mpsc::Sender<LinkMessage>,
+) {
+    let mut buffer = [0; LINK_BUFFER_SIZE];
+
+    loop {
+        let (length, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) => {
+                warn!("Link measurement socket closed: {err}");
+                return;
+            }
+        };
+        let Ok(clock) = clock.lock() else {
+            return;
+        };
+        let Some((&kind, payload)) = buffer[..length]
+            .strip_prefix(MEASUREMENT_HEADER)
+            .and_then(|rest| rest.split_first())
+        else {
+            continue;
+        };
+
+        match kind {
+            // Pongs carry our session and clock, then echo the ping's own payload
+            PING => {
+                let mut pong = MEASUREMENT_HEADER.to_vec();
+                pong.push(PONG);
+                write_entry(&mut pong, b"sess", &clock.session);
+                write_entry(&mut pong, b"__gt", &clock.ghost_time().to_be_bytes());
+                pong.extend_from_slice(payload);
+
+                if let Err(err) = socket.send_to(&pong, source) {
+                    debug!("Could not answer Link ping from {source}: {err}");
+                }
+            }
+            PONG => {
+                if let Some(message) = parse_pong(payload, clock.host_time())
+                    && sender.send(message).is_err()
+                {
+                    return;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn bind_discovery(interface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    // Every instance on the machine listens on the same port
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, LINK_PORT)).into())?;
+    socket.join_multicast_v4(&LINK_GROUP, &interface)?;
+    socket.set_multicast_if_v4(&interface)?;
+    socket.set_multicast_loop_v4(true)?;
+
+    Ok(socket.into())
+}
+
+// The address of whichever interface routes to the discovery group
+fn routed_address() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((LINK_GROUP, LINK_PORT)).ok()?;
+
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(address) => Some(address),
+        IpAddr::V6(_) => None,
+    }
+}
+
+// Link node IDs are random alphanumeric characters
+fn random_node_id() -> NodeId {
+    const CHARACTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+    let mut bits = RandomState::new().hash_one(std::process::id());
+    std::array::from_fn(|_| {
+        let character = CHARACTERS[(bits % CHARACTERS.len() as u64) as usize];
+        bits /= CHARACTERS.len() as u64;
+        character
+    })
+}
+
+impl LinkSession {
+    // Starts out alone in a session of our own, at the beat grid's tempo
+    pub fn start(config: &LinkConfig, bpm: f32) -> io::Result<LinkSession> {
+        let discovery = bind_discovery(config.interface)?;
+        let measurement_socket = UdpSocket::bind((config.interface, 0))?;
+
+        let address = if config.interface.is_unspecified() {
+            routed_address().unwrap_or(Ipv4Addr::LOCALHOST)
+        } else {
+            config.interface
+        };
+        let endpoint = SocketAddrV4::new(address, measurement_socket.local_addr()?.port());
+
+        let node = random_node_id();
+        let clock = Arc::new(Mutex::new(GhostClock {
+            start: Instant::now(),
+            session: node,
+            intercept: 0,
+        }));
+        let (sender, receiver) = mpsc::channel();
+
+        let socket = discovery.try_clone()?;
+        let discovery_sender = sender.clone();
+        thread::spawn(move || listen_discovery(socket, discovery_sender));
+
+        let socket = measurement_socket.try_clone()?;
+        let measurement_clock = clock.clone();
+        thread::spawn(move || listen_measurement(socket, measurement_clock, sender));
+
+        Ok(LinkSession {
+            node,
+            endpoint,
+            timeline: Timeline::new(bpm, 0, 0),
+            clock,
+            peers: HashMap::new(),
+            measurement: None,
+            measured: HashMap::new(),
+            last_broadcast: None,
+            session_peers: 0,
+            receiver: Mutex::new(receiver),
+            discovery,
+            measurement_socket,
+        })
+    }
+
+    fn announcement(&self, session: &NodeId) -> Vec<u8> {
+        let mut buffer = DISCOVERY_HEADER.to_vec();
+        buffer.extend_from_slice(&[ALIVE, LINK_TTL, 0, 0]);
+        buffer.extend_from_slice(&self.node);
+
+        write_entry(&mut buffer, b"tmln", &self.timeline.encode());
+        write_entry(&mut buffer, b"sess", session);
+
+        let mut endpoint = self.endpoint.ip().octets().to_vec();
+        endpoint.extend_from_slice(&self.endpoint.port().to_be_bytes());
+        write_entry(&mut buffer, b"mep4", &endpoint);
+
+        buffer
+    }
+
+    fn bye_bye(&self) -> Vec<u8> {
+        let mut buffer = DISCOVERY_HEADER.to_vec();
+        buffer.extend_from_slice(&[BYE_BYE, LINK_TTL, 0, 0]);
+        buffer.extend_from_slice(&self.node);
+
+        buffer
+    }
+
+    fn ping(&self, endpoint: SocketAddr, host_time: i64, previous_ghost_time: Option<i64>) {
+        let mut ping = MEASUREMENT_HEADER.to_vec();
+        ping.push(PING);
+        write_entry(&mut ping, b"__ht", &host_time.to_be_bytes());
+        if let Some(previous_ghost_time) = previous_ghost_time {
+            write_entry(&mut ping, b"_pgt", &previous_ghost_time.to_be_bytes());
+        }
+
+        if let Err(err) = self.measurement_socket.send_to(&ping, endpoint) {
+            debug!("Could not ping Link peer at {endpoint}: {err}");
+        }
+    }
+
+    fn receive_pong(
+        &mut self,
+        clock: &mut GhostClock,
+        session: NodeId,
+        ghost_time: i64,
+        host_time: i64,
+        previous_ghost_time: Option<i64>,
+        received: i64,
+    ) {
+        let Some(measurement) = &mut self.measurement else {
+            return;
+        };
+        if measurement.session != session {
+            return;
+        }
+
+        // Assuming the ping and pong took as long as each other
+        measurement
+            .samples
+            .push(ghost_time as f64 - (host_time + received) as f64 / 2.0);
+        if let Some(previous_ghost_time) = previous_ghost_time {
+            measurement
+                .samples
+                .push((ghost_time + previous_ghost_time) as f64 / 2.0 - host_time as f64);
+        }
+
+        if measurement.samples.len() < MEASUREMENT_SAMPLES {
+            let endpoint = measurement.endpoint;
+            self.ping(endpoint, clock.host_time(), Some(ghost_time));
+            return;
+        }
+
+        let mut samples = std::mem::take(&mut measurement.samples);
+        samples.sort_by(f64::total_cmp);
+        let intercept = samples[samples.len() / 2] as i64;
+        self.measurement = None;
+        self.measured.insert(session, Instant::now());
+
+        // As in Link, the session whose clock has run the longest wins, the lower ID breaking ties
+        let difference = intercept - clock.intercept;
+        if difference > SESSION_EPSILON
+            || (difference.abs() <= SESSION_EPSILON && session < clock.session)
+        {
+            clock.session = session;
+            clock.intercept = intercept;
+
+            if let Some(timeline) = self
+                .peers
+                .values()
+                .filter(|peer| peer.session == session)
+                .map(|peer| peer.timeline)
+                .max_by_key(|timeline| timeline.beat_origin)
+            {
+                self.timeline = timeline;
+            }
+            info!("Joined Link session at {:.1} BPM", self.timeline.bpm());
+            self.last_broadcast = None;
+        }
+    }
+}
+
+impl Plugin for LinkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                update
+                    .run_if(resource_exists::<LinkSession>)
+                    .after(crate::tempo::control_tempo)
+                    .before(crate::analysis::update),
+            )
+            .add_systems(Last, leave.run_if(resource_exists::<LinkSession>));
+    }
+}
+
+pub fn setup(mut commands: Commands, beat_grid: Res<BeatGrid>, config: Res<Config>) {
+    if !config.link.enabled {
+        return;
+    }
+
+    match LinkSession::start(&config.link, beat_grid.bpm) {
+        Ok(session) => {
+            info!(
+                "Joined the Ableton Link group, measured at {}",
+                session.endpoint
+            );
+            commands.insert_resource(session);
+        }
+        Err(err) => warn!("Could not start Ableton Link: {err}"),
+    }
+}
+
+pub fn update(
+    mut link: ResMut<LinkSession>,
+    mut beat_grid: ResMut<BeatGrid>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let link = link.as_mut();
+    let ghost_clock = link.clock.clone();
+    let Ok(mut clock) = ghost_clock.lock() else {
+        return;
+    };
+    let now = Instant::now();
+
+    let messages: Vec<LinkMessage> = link
+        .receiver
+        .get_mut()
+        .map(|receiver| receiver.try_iter().collect())
+        .unwrap_or_default();
+
+    for message in messages {
+        match message {
+            LinkMessage::Peer {
+                node,
+                session,
+                timeline,
+                endpoint,
+            } => {
+                if node == link.node {
+                    continue;
+                }
+                // Within a session, the timeline with the latest beat origin is the newest change
+                if session == clock.session && timeline.beat_origin > link.timeline.beat_origin {
+                    link.timeline = timeline;
+                }
+                link.peers.insert(
+                    node,
+                    LinkPeer {
+                        session,
+                        timeline,
+                        endpoint,
+                        seen: now,
+                    },
+                );
+            }
+            LinkMessage::ByeBye(node) => {
+                link.peers.remove(&node);
+            }
+            LinkMessage::Pong {
+                session,
+                ghost_time,
+                host_time,
+                previous_ghost_time,
+                received,
+            } => link.receive_pong(
+                &mut clock,
+                session,
+                ghost_time,
+                host_time,
+                previous_ghost_time,
+                received,
+            ),
+        }
+    }
+
+    let ttl = Duration::from_secs(LINK_TTL as u64);
+    link.peers.retain(|_, peer| now - peer.seen < ttl);
+
+    let session_peers = link
+        .peers
+        .values()
+        .filter(|peer| peer.session == clock.session)
+        .count();
+    if session_peers != link.session_peers {
+        info!("Ableton Link session has {session_peers} other peers");
+        link.session_peers = session_peers;
+    }
+
+    if let Some(measurement) = link
+        .measurement
+        .take_if(|measurement| now - measurement.started > MEASUREMENT_TIMEOUT)
+    {
+        link.measured.insert(measurement.session, now);
+    }
+    if link.measurement.is_none()
+        && let Some((session, endpoint)) = link
+            .peers
+            .values()
+            .find(|peer| {
+                peer.session != clock.session
+                    && link
+                        .measured
+                        .get(&peer.session)
+                        .is_none_or(|measured| now - *measured > MEASUREMENT_RETRY)
+            })
+            .map(|peer| (peer.session, peer.endpoint))
+    {
+        link.measurement = Some(Measurement {
+            session,
+            endpoint,
+            samples: Vec::new(),
+            started: now,
+        });
+        link.ping(endpoint, clock.host_time(), None);
+    }
+
+    let ghost_time = clock.ghost_time();
+
+    let quantum = config.link.quantum as f64;
+
+    if beat_grid.mode == TempoMode::Link && link.session_peers > 0 {
+        let beats = link.timeline.beats_at(ghost_time);
+        // Whole bars of offset keep the grid's own beat count while its bars line up with the
+        // session's
+        let shift = ((beat_grid.position - beats) / quantum).round() * quantum;
+
+        beat_grid.bpm = link.timeline.bpm();
+        // analysis::update steps the grid on by this frame afterwards
+        beat_grid.position = beats + shift - (time.delta_secs() * beat_grid.bpm / 60.0) as f64;
+    } else if beat_grid.mode == TempoMode::Link {
+        // Alone in the session, taps, nudges and /tempo/bpm drive the grid as in Manual and the
+        // timeline follows it, moving forward by part of a bar so its beat origin only grows
+        let beats = link.timeline.beats_at(ghost_time);
+        let grid_beats = beat_grid.position + (time.delta_secs() * beat_grid.bpm / 60.0) as f64;
+        let offset = (grid_beats - beats).rem_euclid(quantum);
+
+        if offset.min(quantum - offset) > PHASE_THRESHOLD
+            || (beat_grid.bpm - link.timeline.bpm()).abs() > PUBLISH_THRESHOLD
+        {
+            let beat_origin = (((beats + offset) * 1e6) as i64).max(link.timeline.beat_origin + 1);
+            link.timeline = Timeline::new(beat_grid.bpm, beat_origin, ghost_time);
+            link.last_broadcast = None;
+        }
+    } else if config.link.publish && (beat_grid.bpm - link.timeline.bpm()).abs() > PUBLISH_THRESHOLD
+    {
+        // The session's beat carries on from where it is, only its rate changes
+        let beat_origin =
+            ((link.timeline.beats_at(ghost_time) * 1e6) as i64).max(link.timeline.beat_origin + 1);
+        link.timeline = Timeline::new(beat_grid.bpm, beat_origin, ghost_time);
+        link.last_broadcast = None;
+    }
+
+    if link
+        .last_broadcast
+        .is_none_or(|last| now - last >= LINK_BROADCAST_PERIOD)
+    {
+        let announcement = link.announcement(&clock.session);
+        if let Err(err) = link
+            .discovery
+            .send_to(&announcement, (LINK_GROUP, LINK_PORT))
+        {
+            debug!("Could not announce to the Link group: {err}");
+        }
+        link.last_broadcast = Some(now);
+    }
+}
+
+// Saying goodbye on exit lets peers drop us at once rather than after the TTL
+pub fn leave(mut exits: MessageReader<AppExit>, link: Res<LinkSession>) {
+    if exits.read().next().is_none() {
+        return;
+    }
+
+    if let Err(err) = link
+        .discovery
+        .send_to(&link.bye_bye(), (LINK_GROUP, LINK_PORT))
+    {
+        debug!("Could not leave the Link group: {err}");
+    }
+}
@@ -1,29 +1,91 @@
-use std::f32::consts::PI;
-
 use bevy::{
     asset::{Asset, AssetServer, Assets, Handle},
+    camera::{Projection, visibility::Visibility},
     ecs::{
+        change_detection::DetectChangesMut,
         component::Component,
-        system::{Commands, Res, ResMut, Single},
+        entity::Entity,
+        hierarchy::ChildOf,
+        query::With,
+        resource::Resource,
+        system::{Commands, Query, Res, ResMut},
     },
     image::Image,
-    math::{Quat, Vec3, primitives::Plane3d},
-    mesh::{Mesh, Mesh3d},
-    pbr::{Material, MeshMaterial3d},
+    input::{ButtonInput, keyboard::KeyCode},
+    math::{Quat, Vec2, Vec3, primitives::Rectangle},
+    mesh::{Mesh, Mesh3d, MeshVertexBufferLayoutRef},
+    pbr::{Material, MaterialPipeline, MaterialPipelineKey, MeshMaterial3d},
     reflect::TypePath,
-    render::{alpha::AlphaMode, render_resource::AsBindGroup},
+    render::{
+        alpha::AlphaMode,
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, ShaderType, SpecializedMeshPipelineError,
+        },
+    },
     shader::ShaderRef,
+    time::Time,
     transform::components::Transform,
 };
-use bevy_svg::prelude::{Origin, Svg3d};
+use bevy_svg::prelude::Svg;
+use serde::{Deserialize, Serialize};
 
-use crate::audiolink::AudiolinkDataTexture;
+use crate::{
+    analysis::{AudioAnalysis, Signal},
+    audiolink::AudiolinkDataTexture,
+    config::Config,
+    layer::LayerCamera,
+    output::Canvas,
+    palette::PaletteTexture,
+    sdf::Mask,
+};
 
 const SHADER_ASSET_PATH: &str = "logo.wgsl";
+const OVERLAY_SHADER_ASSET_PATH: &str = "logo_overlay.wgsl";
+
+const BACKGROUND_OFFSET: f32 = 0.01;
+
+pub const MASK_HEIGHT: usize = 512;
+
+pub const BEAT_SYNC_TIMEOUT: f32 = 4.0;
 
 #[derive(Component)]
 pub struct Logo {
-    pub material_handle: Handle<LogoBackgroundMaterial>,
+    pub index: usize,
+    pub source: LogoSource,
+    pub material_handle: Handle<LogoMaterial>,
+    pub background_material_handle: Handle<LogoBackgroundMaterial>,
+    pub shape: Option<Entity>,
+}
+
+#[derive(Resource, Default)]
+pub struct LogoPlaylist {
+    pub current: usize,
+    // Where the current logo's weight started, and the logos fading out with theirs, so that
+    // switching mid-fade carries on from what is on screen
+    pub start: f32,
+    pub previous: Vec<(usize, f32)>,
+    pub elapsed: f32,
+    pub transition: f32,
+}
+
+#[derive(Clone, Debug)]
+pub enum LogoSource {
+    Svg(Handle<Svg>),
+    Image(Handle<Image>),
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct LogoMaterial {
+    #[uniform(0)]
+    settings: LogoSettings,
+    #[texture(1)]
+    #[sampler(2)]
+    logo_texture: Option<Handle<Image>>,
+}
+
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct LogoSettings {
+    opacity: f32,
 }
 
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
@@ -31,9 +93,204 @@ pub struct LogoBackgroundMaterial {
     #[texture(0)]
     #[sampler(1)]
     color_texture: Option<Handle<Image>>,
+    #[texture(2)]
+    #[sampler(3)]
+    mask_texture: Option<Handle<Image>>,
+    #[uniform(4)]
+    settings: LogoBackgroundSettings,
+    #[texture(5)]
+    #[sampler(6)]
+    palette_texture: Option<Handle<Image>>,
     alpha_mode: AlphaMode,
 }
 
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct LogoBackgroundSettings {
+    spread: f32,
+    fill: f32,
+    expand: f32,
+    outline_width: f32,
+    glow_width: f32,
+    glow_strength: f32,
+    opacity: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogoConfig {
+    pub logos: Vec<LogoEntry>,
+    pub crossfade: f32,
+    pub beat_sync: bool,
+    pub position: Vec2,
+    pub height: f32,
+    pub rotation: f32,
+    pub opacity: f32,
+    pub depth: f32,
+    pub background: LogoBackgroundConfig,
+    pub pulse: Option<LogoPulse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogoEntry {
+    pub path: String,
+    pub duration: f32,
+    pub position: Option<Vec2>,
+    pub height: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogoBackgroundConfig {
+    pub enabled: bool,
+    pub padding: f32,
+    pub fill: f32,
+    pub expand: f32,
+    pub outline_width: f32,
+    pub glow_width: f32,
+    pub glow_strength: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogoPulse {
+    pub signal: Signal,
+    pub scale: f32,
+    pub rotation: f32,
+    pub opacity: f32,
+}
+
+impl Default for LogoConfig {
+    fn default() -> Self {
+        Self {
+            logos: vec![LogoEntry::default()],
+            crossfade: 1.0,
+            beat_sync: true,
+            position: Vec2::new(0.5, 0.5),
+            height: 0.3,
+            rotation: 0.0,
+            opacity: 1.0,
+            depth: 700.0,
+            background: LogoBackgroundConfig::default(),
+            pulse: None,
+        }
+    }
+}
+
+impl Default for LogoEntry {
+    fn default() -> Self {
+        Self {
+            path: "logo-no-overlap.svg".to_owned(),
+            duration: 30.0,
+            position: None,
+            height: None,
+        }
+    }
+}
+
+impl Default for LogoBackgroundConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            padding: 0.15,
+            fill: 1.0,
+            expand: 0.02,
+            outline_width: 0.0,
+            glow_width: 0.05,
+            glow_strength: 0.8,
+        }
+    }
+}
+
+impl Default for LogoPulse {
+    fn default() -> Self {
+        Self {
+            signal: Signal::Bass,
+            scale: 0.1,
+            rotation: 0.0,
+            opacity: 0.0,
+        }
+    }
+}
+
+impl LogoBackgroundConfig {
+    fn settings(&self) -> LogoBackgroundSettings {
+        LogoBackgroundSettings {
+            spread: self.padding,
+            fill: self.fill,
+            expand: self.expand,
+            outline_width: self.outline_width,
+            glow_width: self.glow_width,
+            glow_strength: self.glow_strength,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl LogoEntry {
+    pub fn is_svg(&self) -> bool {
+        self.path.to_lowercase().ends_with(".svg")
+    }
+}
+
+impl LogoPlaylist {
+    pub fn show(&mut self, index: usize) {
+        if index == self.current {
+            return;
+        }
+
+        let previous: Vec<(usize, f32)> = self
+            .previous
+            .iter()
+            .map(|(previous, _)| *previous)
+            .chain([self.current])
+            .filter(|previous| *previous != index)
+            .map(|previous| (previous, self.weight(previous)))
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect();
+
+        self.start = self.weight(index);
+        self.previous = previous;
+        self.current = index;
+        self.elapsed = 0.0;
+        self.transition = 0.0;
+    }
+
+    pub fn weight(&self, index: usize) -> f32 {
+        if index == self.current {
+            self.start + (1.0 - self.start) * self.transition
+        } else {
+            self.previous
+                .iter()
+                .find(|(previous, _)| *previous == index)
+                .map(|(_, weight)| weight * (1.0 - self.transition))
+                .unwrap_or(0.0)
+        }
+    }
+}
+
+impl Material for LogoMaterial {
+    fn fragment_shader() -> ShaderRef {
+        OVERLAY_SHADER_ASSET_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // SVG meshes are flipped vertically when tessellated, so their winding is reversed
+        descriptor.primitive.cull_mode = None;
+
+        Ok(())
+    }
+}
+
 impl Material for LogoBackgroundMaterial {
     fn fragment_shader() -> ShaderRef {
         SHADER_ASSET_PATH.into()
@@ -46,48 +303,287 @@ impl Material for LogoBackgroundMaterial {
 
 pub fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<LogoBackgroundMaterial>>,
+    mut materials: ResMut<Assets<LogoMaterial>>,
+    mut background_materials: ResMut<Assets<LogoBackgroundMaterial>>,
     asset_server: Res<AssetServer>,
+    config: Res<Config>,
 ) {
-    let logo = asset_server.load("logo-no-overlap.svg");
-
-    let logo_background_material = materials.add(LogoBackgroundMaterial {
-        color_texture: None,
-        alpha_mode: AlphaMode::Opaque,
-    });
-
-    commands.spawn((
-        Svg3d(logo),
-        Origin::Center,
-        Transform {
-            translation: Vec3::new(0.0, 0.0, -700.0),
-            scale: Vec3::new(1.0, 1.0, 1.0),
-            rotation: Quat::default(),
-        },
-    ));
-    commands.spawn((
-        Mesh3d(meshes.add(Plane3d::default())),
-        MeshMaterial3d(logo_background_material.clone()),
-        Origin::Center,
-        Transform {
-            translation: Vec3::new(0.0, 0.0, -701.0),
-            scale: Vec3::new(228.0, 228.0, 228.0),
-            rotation: Quat::from_rotation_x(PI * 0.5),
-        },
-    ));
+    for (index, entry) in config.logo.logos.iter().enumerate() {
+        let source = if entry.is_svg() {
+            LogoSource::Svg(asset_server.load(entry.path.clone()))
+        } else {
+            LogoSource::Image(asset_server.load(entry.path.clone()))
+        };
+
+        let logo_material = materials.add(LogoMaterial {
+            settings: LogoSettings { opacity: 0.0 },
+            logo_texture: None,
+        });
+
+        let logo_background_material = background_materials.add(LogoBackgroundMaterial {
+            color_texture: None,
+            mask_texture: None,
+            settings: config.logo.background.settings(),
+            palette_texture: None,
+            alpha_mode: AlphaMode::Blend,
+        });
+
+        commands.spawn((
+            Transform::default(),
+            Visibility::Hidden,
+            Logo {
+                index,
+                source,
+                material_handle: logo_material,
+                background_material_handle: logo_background_material,
+                shape: None,
+            },
+        ));
+    }
+}
+
+pub fn update_playlist(
+    mut playlist: ResMut<LogoPlaylist>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    analysis: Res<AudioAnalysis>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let logos = &config.logo.logos;
+    let delta_time = time.delta_secs();
+
+    playlist.elapsed += delta_time;
+    playlist.transition = if config.logo.crossfade > 0.0 {
+        (playlist.transition + delta_time / config.logo.crossfade).min(1.0)
+    } else {
+        1.0
+    };
+    if playlist.transition >= 1.0 {
+        playlist.previous.clear();
+    }
+
+    for (index, key) in config.keys.logo_select.iter().enumerate() {
+        if index < logos.len() && keyboard.just_pressed(*key) {
+            playlist.show(index);
+        }
+    }
+    if keyboard.just_pressed(config.keys.logo_next) {
+        let next = (playlist.current + 1) % logos.len().max(1);
+        playlist.show(next);
+    }
+
+    let Some(entry) = logos.get(playlist.current) else {
+        return;
+    };
+    if logos.len() < 2 || playlist.elapsed < entry.duration {
+        return;
+    }
+
+    // Wait for a beat to switch on, unless the music has stopped giving us any
+    let overdue = playlist.elapsed - entry.duration;
+    if config.logo.beat_sync && !analysis.beat && overdue < BEAT_SYNC_TIMEOUT {
+        return;
+    }
+
+    let next = (playlist.current + 1) % logos.len();
+    playlist.show(next);
+}
 
-    commands.spawn(Logo {
-        material_handle: logo_background_material,
-    });
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_logo_shapes(
+    mut commands: Commands,
+    mut logos: Query<(Entity, &mut Logo)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<LogoMaterial>>,
+    mut background_materials: ResMut<Assets<LogoBackgroundMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    svgs: Res<Assets<Svg>>,
+    config: Res<Config>,
+) {
+    let background = &config.logo.background;
+    let padding = background.padding;
+    let pixels_per_unit = MASK_HEIGHT as f32 / (1.0 + 2.0 * padding);
+
+    for (entity, mut logo) in logos.iter_mut() {
+        if logo.shape.is_some() {
+            continue;
+        }
+
+        // Both shapes are normalized to a height of one unit and centered on the logo,
+        // the mask covers the shape plus the background padding on every side
+        let (mesh, transform, aspect, mask) = match &logo.source {
+            LogoSource::Svg(handle) => {
+                let Some(svg) = svgs.get(handle.id()) else {
+                    continue;
+                };
+                let Some(svg_mesh) = meshes.get(svg.mesh.id()) else {
+                    continue;
+                };
+                let scale = 1.0 / svg.size.y.max(f32::EPSILON);
+                let aspect = svg.size.x * scale;
+
+                let svg_padding = padding * svg.size.y;
+                let mask = if background.enabled {
+                    Mask::from_mesh(
+                        svg_mesh,
+                        Vec2::new(-svg_padding, -svg.size.y - svg_padding),
+                        Vec2::new(svg.size.x + svg_padding, svg_padding),
+                        mask_width(aspect, padding, pixels_per_unit),
+                        MASK_HEIGHT,
+                    )
+                } else {
+                    None
+                };
+
+                (
+                    svg.mesh.clone(),
+                    Transform {
+                        translation: Vec3::new(-0.5 * aspect, 0.5, 0.0),
+                        scale: Vec3::splat(scale),
+                        rotation: Quat::default(),
+                    },
+                    aspect,
+                    mask,
+                )
+            }
+            LogoSource::Image(handle) => {
+                let Some(image) = images.get(handle.id()) else {
+                    continue;
+                };
+                let aspect = image.width() as f32 / image.height().max(1) as f32;
+
+                let content_min = Vec2::splat(padding * pixels_per_unit);
+                let mask = background.enabled.then(|| {
+                    Mask::from_image_alpha(
+                        image,
+                        content_min,
+                        content_min + Vec2::new(aspect, 1.0) * pixels_per_unit,
+                        mask_width(aspect, padding, pixels_per_unit),
+                        MASK_HEIGHT,
+                    )
+                });
+
+                if let Some(material) = materials.get_mut(logo.material_handle.id()) {
+                    material.logo_texture = Some(handle.clone());
+                }
+
+                (
+                    meshes.add(Rectangle::new(aspect, 1.0)),
+                    Transform::default(),
+                    aspect,
+                    mask,
+                )
+            }
+        };
+
+        logo.shape = Some(
+            commands
+                .spawn((
+                    Mesh3d(mesh),
+                    MeshMaterial3d(logo.material_handle.clone()),
+                    transform,
+                    ChildOf(entity),
+                ))
+                .id(),
+        );
+
+        let Some(mask) = mask else {
+            continue;
+        };
+
+        let mask_texture = images.add(mask.to_signed_distance_field(padding * pixels_per_unit));
+        if let Some(material) = background_materials.get_mut(logo.background_material_handle.id()) {
+            material.mask_texture = Some(mask_texture);
+        }
+
+        commands.spawn((
+            Mesh3d(meshes.add(Rectangle::new(aspect + 2.0 * padding, 1.0 + 2.0 * padding))),
+            MeshMaterial3d(logo.background_material_handle.clone()),
+            Transform::from_xyz(0.0, 0.0, -BACKGROUND_OFFSET),
+            ChildOf(entity),
+        ));
+    }
+}
+
+fn mask_width(aspect: f32, padding: f32, pixels_per_unit: f32) -> usize {
+    (((aspect + 2.0 * padding) * pixels_per_unit).round() as usize).max(1)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn update(
-    visualizer: Single<&mut Logo>,
+    mut logos: Query<(&Logo, &mut Transform, &mut Visibility)>,
+    playlist: Res<LogoPlaylist>,
+    camera_projections: Query<&Projection, (With<LayerCamera>, With<Camera3d>)>,
     audiolink_data_texture: Res<AudiolinkDataTexture>,
-    mut materials: ResMut<Assets<LogoBackgroundMaterial>>,
+    palette_texture: Res<PaletteTexture>,
+    mut materials: ResMut<Assets<LogoMaterial>>,
+    mut background_materials: ResMut<Assets<LogoBackgroundMaterial>>,
+    analysis: Res<AudioAnalysis>,
+    canvas: Res<Canvas>,
+    config: Res<Config>,
 ) {
-    if let Some(material_reference) = materials.get_mut(visualizer.material_handle.id()) {
-        material_reference.color_texture = Some(audiolink_data_texture.0.clone());
+    // Every layer camera keeps the default projection, so any of them will do
+    let Some(Projection::Perspective(perspective)) = camera_projections.iter().next() else {
+        return;
+    };
+
+    let logo_config = &config.logo;
+    let aspect = canvas.size.x as f32 / canvas.size.y.max(1) as f32;
+
+    // Visible extents of the canvas at the logo's depth
+    let half_height = logo_config.depth * (perspective.fov * 0.5).tan();
+    let half_width = half_height * aspect;
+
+    let (pulse_scale, pulse_rotation, pulse_opacity) = match &logo_config.pulse {
+        Some(pulse) => {
+            let value = analysis.get(pulse.signal);
+
+            (
+                1.0 + pulse.scale * value,
+                pulse.rotation * value,
+                1.0 - pulse.opacity * (1.0 - value),
+            )
+        }
+        None => (1.0, 0.0, 1.0),
+    };
+
+    for (logo, mut transform, mut visibility) in logos.iter_mut() {
+        let Some(entry) = logo_config.logos.get(logo.index) else {
+            continue;
+        };
+
+        let weight = playlist.weight(logo.index);
+        let target_visibility = if weight > 0.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        visibility.set_if_neq(target_visibility);
+        if weight <= 0.0 {
+            continue;
+        }
+
+        let position = entry.position.unwrap_or(logo_config.position);
+        let height = entry.height.unwrap_or(logo_config.height);
+
+        transform.translation = Vec3::new(
+            (position.x * 2.0 - 1.0) * half_width,
+            (1.0 - position.y * 2.0) * half_height,
+            -logo_config.depth,
+        );
+        transform.scale = Vec3::splat(height * 2.0 * half_height * pulse_scale);
+        transform.rotation =
+            Quat::from_rotation_z(-(logo_config.rotation + pulse_rotation).to_radians());
+
+        if let Some(material) = materials.get_mut(logo.material_handle.id()) {
+            material.settings.opacity =
+                (logo_config.opacity * pulse_opacity * weight).clamp(0.0, 1.0);
+        }
+
+        if let Some(material) = background_materials.get_mut(logo.background_material_handle.id()) {
+            material.color_texture = Some(audiolink_data_texture.0.clone());
+            material.palette_texture = Some(palette_texture.0.clone());
+            material.settings.opacity = weight;
+        }
     }
 }
@@ -1,37 +1,139 @@
+pub mod analysis;
 pub mod audiolink;
+pub mod config;
+pub mod control;
+pub mod debug;
+pub mod generator;
+pub mod geometry;
+pub mod history;
+pub mod layer;
+pub mod link;
 pub mod logo;
+pub mod media;
+pub mod midi;
+pub mod model;
+pub mod modulation;
+pub mod output;
+pub mod palette;
+pub mod parameters;
+pub mod particles;
 pub mod pipewire;
+pub mod post;
+pub mod preset;
+pub mod remote;
+pub mod scene;
+pub mod sdf;
+pub mod tempo;
+pub mod text;
 pub mod visualizer;
+pub mod web;
 
 use bevy::prelude::*;
 
-use crate::{audiolink::AudiolinkComputePlugin, pipewire::PipewireInput};
+use crate::{
+    analysis::AnalysisPlugin,
+    audiolink::AudiolinkComputePlugin,
+    config::{Config, ConfigPath},
+    control::ControlPlugin,
+    debug::DebugPlugin,
+    generator::{GeneratorPlugin, Generators},
+    geometry::GeometryPlugin,
+    history::HistoryPlugin,
+    layer::LayerPlugin,
+    link::LinkPlugin,
+    media::MediaPlugin,
+    midi::{Midi, MidiPlugin},
+    model::ModelPlugin,
+    modulation::{Modulation, ModulationPlugin},
+    output::OutputPlugin,
+    palette::{PalettePlugin, Palettes},
+    parameters::{Parameters, ParametersPlugin},
+    particles::ParticlesPlugin,
+    pipewire::PipewireInput,
+    post::PostPlugin,
+    preset::PresetPlugin,
+    remote::RemotePlugin,
+    scene::{ScenePlugin, Scenes},
+    tempo::{BeatGrid, TempoPlugin},
+    text::TextPlugin,
+};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let pipewire_input = PipewireInput::new()?;
+    let (config, config_path) = Config::from_cli()?;
+    let palettes = palette::load_palettes(&config.palette)?;
+    let model_mappings = model::load_mappings(&config.models)?;
+    let presets = preset::load_presets(&config.preset)?;
+    let pipewire_input = PipewireInput::new(&config.audio)?;
 
     App::new()
         .add_plugins((
-            DefaultPlugins,
-            AudiolinkComputePlugin,
+            DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(config.outputs.primary_window()),
+                ..default()
+            }),
+            AudiolinkComputePlugin {
+                settings: config.analysis.clone(),
+            },
+            (AnalysisPlugin, TempoPlugin, LinkPlugin, MidiPlugin),
+            (
+                ParametersPlugin,
+                GeneratorPlugin,
+                ModulationPlugin,
+                PresetPlugin,
+            ),
+            PalettePlugin,
+            (
+                LayerPlugin,
+                MediaPlugin,
+                TextPlugin,
+                ParticlesPlugin {
+                    config: config.particles.clone(),
+                },
+                GeometryPlugin,
+                ModelPlugin {
+                    mappings: model_mappings,
+                },
+                PostPlugin,
+                HistoryPlugin,
+            ),
+            RemotePlugin,
+            OutputPlugin,
+            ScenePlugin,
+            ControlPlugin,
+            DebugPlugin,
+            MaterialPlugin::<logo::LogoMaterial>::default(),
             MaterialPlugin::<logo::LogoBackgroundMaterial>::default(),
             MaterialPlugin::<visualizer::VisualizerMaterial>::default(),
             bevy_svg::prelude::SvgPlugin,
         ))
+        .insert_resource(config.outputs.clone())
+        .insert_resource(Scenes::new(&config.scenes))
+        .insert_resource(Parameters::new(&config.parameters))
+        .insert_resource(BeatGrid::new(&config.tempo, analysis::DEFAULT_BPM))
+        .insert_resource(Generators::new(&config.generators))
+        .insert_resource(Modulation::new(&config.modulation))
+        .insert_resource(Midi::new(&config.midi))
+        .insert_resource(Palettes::new(palettes, &config.palette.preset))
+        .insert_resource(presets)
+        .insert_resource(ConfigPath(config_path))
+        .insert_resource(config)
         .insert_non_send_resource(pipewire_input)
-        .add_systems(Startup, (setup, logo::setup, visualizer::setup))
+        .init_resource::<logo::LogoPlaylist>()
+        .add_systems(Startup, (logo::setup, visualizer::setup))
         .add_systems(
             Update,
             (
-                logo::update.after(audiolink::update),
-                visualizer::update.after(audiolink::update),
+                logo::spawn_logo_shapes,
+                logo::update_playlist.after(analysis::update),
+                logo::update
+                    .after(audiolink::update)
+                    .after(logo::update_playlist),
+                visualizer::update
+                    .after(audiolink::update)
+                    .after(history::update),
             ),
         )
         .run();
 
     Ok(())
 }
-
-fn setup(mut commands: Commands) {
-    commands.spawn(Camera3d::default());
-}
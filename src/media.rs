@@ -0,0 +1,492 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Mutex, mpsc},
+    thread,
+};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{CompressedImageFormats, ImageSampler, ImageType},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::AudioAnalysis,
+    config::{ASSET_DIRECTORY, Config},
+    layer::{LayerImages, LayerSource},
+    parameters::Parameters,
+};
+
+pub const SEQUENCE_EXTENSION: &str = "png";
+
+pub struct MediaPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    Loop,
+    PingPong,
+    Once,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    pub clips: Vec<ClipConfig>,
+    // Cues wait for the next beat that is a multiple of this many beats, zero switching immediately
+    pub quantize: u64,
+    // Every clip is decoded at startup and kept in memory as RGBA, 4 bytes a pixel, so videos are
+    // scaled down to max_width and all clips cut short. A 16:9 frame 640 wide takes 0.9 MB, about
+    // 230 MB for a clip of the default 250 frames. Image sequences keep their own size
+    pub max_width: u32,
+    pub max_frames: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipConfig {
+    // A video file, or a directory of PNG frames played in name order
+    pub path: String,
+    // Frame rate of image sequences, videos use their own
+    pub fps: f32,
+    pub loop_mode: LoopMode,
+    pub speed: f32,
+    // Stretches playback so the clip lasts this many beats at the current tempo
+    pub beats: Option<f32>,
+}
+
+pub struct ClipFrames {
+    pub size: UVec2,
+    pub fps: f32,
+    pub frames: Vec<Vec<u8>>,
+}
+
+enum ClipState {
+    Loading(Mutex<mpsc::Receiver<Result<ClipFrames, String>>>),
+    Ready(ClipFrames),
+    Failed,
+}
+
+#[derive(Component)]
+pub struct MediaPlayer {
+    pub name: String,
+    pub layer: usize,
+    pub current: usize,
+    pub pending: Option<usize>,
+    clips: Vec<ClipState>,
+    position: f32,
+    direction: f32,
+    shown: Option<(usize, usize)>,
+    image: Option<Handle<Image>>,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            clips: Vec::new(),
+            quantize: 4,
+            max_width: 640,
+            max_frames: 250,
+        }
+    }
+}
+
+impl Default for ClipConfig {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            fps: 25.0,
+            loop_mode: LoopMode::Loop,
+            speed: 1.0,
+            beats: None,
+        }
+    }
+}
+
+impl MediaPlayer {
+    pub fn cue(&mut self, index: usize) {
+        if index < self.clips.len() {
+            self.pending = Some(index);
+        }
+    }
+
+    fn ready_clip(&self, index: usize) -> Option<&ClipFrames> {
+        match self.clips.get(index) {
+            Some(ClipState::Ready(frames)) if !frames.frames.is_empty() => Some(frames),
+            _ => None,
+        }
+    }
+}
+
+impl Plugin for MediaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            setup
+                .after(crate::layer::setup)
+                .before(crate::control::setup),
+        )
+        .add_systems(
+            Update,
+            (
+                receive_clips,
+                control_media,
+                update_media
+                    .after(receive_clips)
+                    .after(control_media)
+                    .after(crate::analysis::update),
+            ),
+        );
+    }
+}
+
+fn parse_frame_rate(rate: &str) -> Option<f32> {
+    match rate.split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator: f32 = denominator.trim().parse().ok()?;
+            (denominator > 0.0).then_some(numerator.trim().parse::<f32>().ok()? / denominator)
+        }
+        None => rate.trim().parse().ok(),
+    }
+}
+
+// Decodes through the ffmpeg command line tools, which must be on the PATH
+fn load_video(path: &Path, max_width: u32, max_frames: usize) -> Result<ClipFrames, String> {
+    let probe = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height,r_frame_rate",
+            "-of",
+            "csv=p=0",
+        ])
+        .arg(path)
+        .output()
+        .map_err(|err| format!("could not run ffprobe: {err}"))?;
+
+    let probe = String::from_utf8_lossy(&probe.stdout);
+    let fields: Vec<&str> = probe.trim().split(',').collect();
+    let [width, height, rate] = fields[..] else {
+        return Err(format!("ffprobe found no video stream in {probe:?}"));
+    };
+
+    let source_size = UVec2::new(
+        width.parse().map_err(|_| "invalid width")?,
+        height.parse().map_err(|_| "invalid height")?,
+    );
+    let fps = parse_frame_rate(rate).ok_or("invalid frame rate")?;
+
+    // Even dimensions keep every pixel format happy
+    let width = source_size.x.min(max_width).max(2) & !1;
+    let height =
+        ((source_size.y as u64 * width as u64 / source_size.x.max(1) as u64) as u32).max(2) & !1;
+
+    let mut child = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(path)
+        .args([
+            "-vf",
+            &format!("scale={width}:{height}"),
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+            "-",
+        ])
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("could not run ffmpeg: {err}"))?;
+
+    let mut stdout = child.stdout.take().ok_or("ffmpeg has no output")?;
+    let frame_length = (width * height * 4) as usize;
+
+    let mut frames = Vec::new();
+    while frames.len() < max_frames {
+        let mut frame = vec![0; frame_length];
+        if stdout.read_exact(&mut frame).is_err() {
+            break;
+        }
+        frames.push(frame);
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    Ok(ClipFrames {
+        size: UVec2::new(width, height),
+        fps,
+        frames,
+    })
+}
+
+fn load_sequence(path: &Path, fps: f32, max_frames: usize) -> Result<ClipFrames, String> {
+    let mut files: Vec<PathBuf> = fs::read_dir(path)
+        .map_err(|err| err.to_string())?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| {
+            file.extension()
+                .is_some_and(|extension| extension == SEQUENCE_EXTENSION)
+        })
+        .collect();
+    files.sort();
+    files.truncate(max_frames);
+
+    let mut size = None;
+    let mut frames = Vec::with_capacity(files.len());
+
+    for file in files {
+        let bytes = fs::read(&file).map_err(|err| format!("{}: {err}", file.display()))?;
+        let image = Image::from_buffer(
+            &bytes,
+            ImageType::Extension(SEQUENCE_EXTENSION),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::MAIN_WORLD,
+        )
+        .map_err(|err| format!("{}: {err}", file.display()))?;
+
+        let image = if image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb {
+            image
+        } else {
+            image
+                .convert(TextureFormat::Rgba8UnormSrgb)
+                .ok_or_else(|| format!("{}: unsupported pixel format", file.display()))?
+        };
+
+        let frame_size = image.size();
+        if *size.get_or_insert(frame_size) != frame_size {
+            warn!("Skipping {}: frames differ in size", file.display());
+            continue;
+        }
+
+        // Layers are composited as premultiplied alpha
+        let mut data = image.data.unwrap_or_default();
+        for pixel in data.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel[..3] {
+                *channel = (*channel as u32 * alpha / 255) as u8;
+            }
+        }
+        frames.push(data);
+    }
+
+    Ok(ClipFrames {
+        size: size.ok_or("no PNG frames")?,
+        fps,
+        frames,
+    })
+}
+
+fn spawn_loader(clip: &ClipConfig, media: &MediaConfig) -> ClipState {
+    let (sender, receiver) = mpsc::channel();
+
+    let path = Path::new(ASSET_DIRECTORY).join(&clip.path);
+    let fps = clip.fps;
+    let (max_width, max_frames) = (media.max_width, media.max_frames);
+
+    thread::spawn(move || {
+        let frames = if path.is_dir() {
+            load_sequence(&path, fps, max_frames)
+        } else {
+            load_video(&path, max_width, max_frames)
+        };
+
+        let _ = sender.send(frames.map_err(|err| format!("{}: {err}", path.display())));
+    });
+
+    ClipState::Loading(Mutex::new(receiver))
+}
+
+pub fn setup(mut commands: Commands, mut parameters: ResMut<Parameters>, config: Res<Config>) {
+    for (layer, layer_config) in config.layers.iter().enumerate() {
+        let LayerSource::Media(name) = &layer_config.source else {
+            continue;
+        };
+        let Some(media) = config.media.get(name) else {
+            continue;
+        };
+
+        parameters.register(&format!("media.{name}.speed"), 1.0, -4.0, 4.0, 0.1);
+
+        commands.spawn(MediaPlayer {
+            name: name.clone(),
+            layer,
+            current: 0,
+            pending: None,
+            clips: media
+                .clips
+                .iter()
+                .map(|clip| spawn_loader(clip, media))
+                .collect(),
+            position: 0.0,
+            direction: 1.0,
+            shown: None,
+            image: None,
+        });
+    }
+}
+
+pub fn receive_clips(mut players: Query<&mut MediaPlayer>) {
+    for mut player in players.iter_mut() {
+        for clip in player.clips.iter_mut() {
+            let ClipState::Loading(receiver) = clip else {
+                continue;
+            };
+
+            let received = match receiver.get_mut() {
+                Ok(receiver) => receiver.try_recv(),
+                Err(_) => Err(mpsc::TryRecvError::Disconnected),
+            };
+
+            *clip = match received {
+                Ok(Ok(frames)) => {
+                    info!("Loaded {} frames at {}", frames.frames.len(), frames.size);
+                    ClipState::Ready(frames)
+                }
+                Ok(Err(err)) => {
+                    warn!("Could not load clip {err}");
+                    ClipState::Failed
+                }
+                Err(mpsc::TryRecvError::Empty) => continue,
+                Err(mpsc::TryRecvError::Disconnected) => ClipState::Failed,
+            };
+        }
+    }
+}
+
+pub fn control_media(
+    mut players: Query<&mut MediaPlayer>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    if !keyboard.just_pressed(config.keys.clip_next) {
+        return;
+    }
+
+    for mut player in players.iter_mut() {
+        let next = (player.pending.unwrap_or(player.current) + 1) % player.clips.len().max(1);
+        player.cue(next);
+    }
+}
+
+pub fn update_media(
+    mut players: Query<&mut MediaPlayer>,
+    mut layer_images: ResMut<LayerImages>,
+    mut images: ResMut<Assets<Image>>,
+    parameters: Res<Parameters>,
+    analysis: Res<AudioAnalysis>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    for mut player in players.iter_mut() {
+        let Some(media) = config.media.get(&player.name) else {
+            continue;
+        };
+
+        if let Some(pending) = player.pending
+            && (media.quantize == 0 || (analysis.beat && analysis.beats % media.quantize == 0))
+        {
+            player.current = pending;
+            player.pending = None;
+            player.position = 0.0;
+            player.direction = 1.0;
+        }
+
+        let current = player.current;
+        let (Some(clip), Some(frames)) = (media.clips.get(current), player.ready_clip(current))
+        else {
+            continue;
+        };
+
+        let frame_count = frames.frames.len();
+        let last = (frame_count - 1) as f32;
+
+        let mut rate = clip.speed
+            * parameters
+                .get(&format!("media.{}.speed", player.name))
+                .unwrap_or(1.0);
+        if let Some(beats) = clip.beats {
+            let clip_seconds = frame_count as f32 / frames.fps;
+            rate *= clip_seconds / (beats * 60.0 / analysis.bpm.max(1.0));
+        }
+
+        let mut position =
+            player.position + time.delta_secs() * frames.fps * rate * player.direction;
+        let mut direction = player.direction;
+
+        match clip.loop_mode {
+            LoopMode::Loop => position = position.rem_euclid(frame_count as f32),
+            LoopMode::Once => position = position.clamp(0.0, last),
+            LoopMode::PingPong if last > 0.0 => {
+                if position > last {
+                    position = 2.0 * last - position;
+                    direction = -direction;
+                } else if position < 0.0 {
+                    position = -position;
+                    direction = -direction;
+                }
+                position = position.clamp(0.0, last);
+            }
+            LoopMode::PingPong => position = 0.0,
+        }
+
+        let frame = (position as usize).min(frame_count - 1);
+        let size = frames.size;
+
+        player.position = position;
+        player.direction = direction;
+
+        if player.shown == Some((current, frame)) {
+            continue;
+        }
+
+        // Frames are only copied when they change, as each is a full RGBA image
+        let Some(data) = player
+            .ready_clip(current)
+            .map(|frames| frames.frames[frame].clone())
+        else {
+            continue;
+        };
+
+        // Clips can differ in size, so switching clips uploads a new image
+        let same_clip = player
+            .shown
+            .is_some_and(|(shown_clip, _)| shown_clip == current);
+        match player
+            .image
+            .as_ref()
+            .and_then(|image| images.get_mut(image.id()))
+        {
+            Some(image) if same_clip => image.data = Some(data),
+            _ => {
+                let image = images.add(Image::new(
+                    Extent3d {
+                        width: size.x,
+                        height: size.y,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    data,
+                    TextureFormat::Rgba8UnormSrgb,
+                    RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+                ));
+
+                if let Some(layer_image) = layer_images.layers.get_mut(player.layer) {
+                    *layer_image = image.clone();
+                }
+                player.image = Some(image);
+            }
+        }
+
+        player.shown = Some((current, frame));
+    }
+}
@@ -0,0 +1,522 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use alsa::{
+    Direction,
+    seq::{
+        Addr, ClientIter, EvCtrl, EvNote, EventType, PortCap, PortIter, PortSubscribe, PortType,
+        Seq,
+    },
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{MAX_BPM, MIN_BPM},
+    config::{Config, ConfigPath, SavedState},
+    parameters::Parameters,
+    remote::RemoteCommand,
+    scene::Scenes,
+    tempo::{BeatGrid, TempoMode},
+};
+
+pub const MIDI_CLIENT_NAME: &std::ffi::CStr = c"vj-visualiser";
+pub const MIDI_PORT_NAME: &std::ffi::CStr = c"input";
+
+// MIDI clock runs at 24 ticks to the beat, and song positions count sixteenth notes
+pub const CLOCK_TICKS_PER_BEAT: i64 = 24;
+pub const CLOCK_TICKS_PER_SONG_POSITION: i64 = 6;
+// Tick intervals averaged for the tempo, and the gap after which the clock counts as stopped
+pub const CLOCK_HISTORY: usize = 48;
+pub const CLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Controllers trigger as they rise past this value
+pub const TRIGGER_THRESHOLD: u8 = 64;
+
+pub struct MidiPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MidiConfig {
+    pub enabled: bool,
+    // Sources to subscribe to at startup, matching any part of their client or port name, such as
+    // "Midi Through" or "DDJ". Others can still be connected to our port with aconnect
+    pub connect: Vec<String>,
+    // Beats in a bar, which the beat grid keeps in phase with the clock's song position
+    pub quantum: f32,
+    pub mappings: Vec<MidiMapping>,
+}
+
+// Channels from 0 to 15
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MidiControl {
+    Controller { channel: u8, controller: u8 },
+    Note { channel: u8, note: u8 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum MidiAction {
+    // Sweeps the parameter's range with the controller's value, or the note's velocity while held
+    Parameter(String),
+    // Sets an Input modulation source from 0 to 1, as /input/<name> does
+    Input(String),
+    // The rest trigger on a note on, or a controller rising past half
+    Cue(String),
+    Take,
+    // Recalls a preset, morphing over preset.morph_beats
+    Preset(String),
+    // An address as used over OSC and HTTP, without an argument, such as "/tempo/tap"
+    Remote(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MidiMapping {
+    pub control: MidiControl,
+    pub action: MidiAction,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum MidiMessage {
+    Controller {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    // Stamped as it arrives, so the frame rate doesn't jitter the tempo
+    Clock(Instant),
+    Start,
+    Continue,
+    Stop,
+    SongPosition(i64),
+}
+
+#[derive(Default)]
+pub struct MidiClock {
+    // The last tick's index since Start, -1 until the first
+    pub tick: i64,
+    pub last_tick: Option<Instant>,
+    pub intervals: VecDeque<f32>,
+    // Cleared by Stop, after which ticks are ignored until Start or Continue. Starts out set so
+    // that a clock already playing is followed
+    pub running: bool,
+}
+
+#[derive(Resource)]
+pub struct Midi {
+    pub mappings: Vec<MidiMapping>,
+    // The action the next controller or note will be mapped to
+    pub learning: Option<MidiAction>,
+    // Each controller's last value, so triggers fire once on the way up
+    pub controllers: HashMap<(u8, u8), u8>,
+    pub clock: MidiClock,
+    receiver: Option<Mutex<mpsc::Receiver<MidiMessage>>>,
+}
+
+impl Default for MidiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            connect: Vec::new(),
+            quantum: 4.0,
+            mappings: Vec::new(),
+        }
+    }
+}
+
+impl MidiClock {
+    fn restart(&mut self) {
+        self.tick = -1;
+    }
+
+    fn tick(&mut self, at: Instant) {
+        if let Some(last_tick) = self.last_tick {
+            let interval = at.duration_since(last_tick);
+            if interval < CLOCK_TIMEOUT {
+                self.intervals.push_back(interval.as_secs_f32());
+                if self.intervals.len() > CLOCK_HISTORY {
+                    self.intervals.pop_front();
+                }
+            } else {
+                self.intervals.clear();
+            }
+        }
+
+        self.last_tick = Some(at);
+        self.tick += 1;
+    }
+
+    fn interval(&self) -> Option<f32> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+
+        Some(self.intervals.iter().sum::<f32>() / self.intervals.len() as f32)
+    }
+
+    // Held to the tempos the rest of the app accepts, so a jittery or stalled clock can't run away
+    pub fn bpm(&self) -> Option<f32> {
+        self.interval().map(|interval| {
+            (60.0 / (interval * CLOCK_TICKS_PER_BEAT as f32)).clamp(MIN_BPM, MAX_BPM)
+        })
+    }
+
+    // Beats since Start, running on between ticks but never past the next one
+    pub fn beats(&self, now: Instant) -> Option<f64> {
+        let last_tick = self.last_tick?;
+        let since_tick = now.saturating_duration_since(last_tick);
+        if since_tick > CLOCK_TIMEOUT {
+            return None;
+        }
+
+        let fraction = (since_tick.as_secs_f32() / self.interval()?).min(1.0);
+        Some((self.tick as f64 + fraction as f64) / CLOCK_TICKS_PER_BEAT as f64)
+    }
+}
+
+impl Midi {
+    pub fn new(config: &MidiConfig) -> Midi {
+        Midi {
+            mappings: config.mappings.clone(),
+            learning: None,
+            controllers: HashMap::new(),
+            clock: MidiClock {
+                tick: -1,
+                running: true,
+                ..default()
+            },
+            receiver: None,
+        }
+    }
+
+    // Replaces whatever the control was mapped to
+    pub fn learn(&mut self, control: MidiControl, action: MidiAction) {
+        self.mappings.retain(|mapping| mapping.control != control);
+        self.mappings.push(MidiMapping { control, action });
+    }
+}
+
+fn name_matches(connect: &[String], client: &str, port: &str) -> bool {
+    connect
+        .iter()
+        .any(|name| client.contains(name.as_str()) || port.contains(name.as_str()))
+}
+
+fn open_sequencer(connect: &[String]) -> alsa::Result<Seq> {
+    let seq = Seq::open(None, Some(Direction::Capture), false)?;
+    seq.set_client_name(MIDI_CLIENT_NAME)?;
+    let port = seq.create_simple_port(
+        MIDI_PORT_NAME,
+        PortCap::WRITE | PortCap::SUBS_WRITE,
+        PortType::MIDI_GENERIC | PortType::APPLICATION,
+    )?;
+    let destination = Addr {
+        client: seq.client_id()?,
+        port,
+    };
+
+    let mut sources = Vec::new();
+    for client in ClientIter::new(&seq) {
+        for source in PortIter::new(&seq, client.get_client()) {
+            let readable = source
+                .get_capability()
+                .contains(PortCap::READ | PortCap::SUBS_READ);
+
+            if readable
+                && name_matches(
+                    connect,
+                    client.get_name().unwrap_or_default(),
+                    source.get_name().unwrap_or_default(),
+                )
+            {
+                sources.push((
+                    source.addr(),
+                    source.get_name().unwrap_or_default().to_owned(),
+                ));
+            }
+        }
+    }
+
+    for (source, name) in sources {
+        let subscription = PortSubscribe::empty()?;
+        subscription.set_sender(source);
+        subscription.set_dest(destination);
+
+        match seq.subscribe_port(&subscription) {
+            Ok(()) => info!("Connected MIDI input from {name}"),
+            Err(err) => warn!("Could not connect MIDI input from {name}: {err}"),
+        }
+    }
+
+    Ok(seq)
+}
+
+fn listen_sequencer(seq: Seq, sender: mpsc::Sender<MidiMessage>) {
+    let mut input = seq.input();
+
+    loop {
+        let event = match input.event_input() {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("MIDI input closed: {err}");
+                return;
+            }
+        };
+
+        let message = match event.get_type() {
+            EventType::Controller => {
+                event
+                    .get_data::<EvCtrl>()
+                    .map(|control| MidiMessage::Controller {
+                        channel: control.channel,
+                        controller: control.param as u8,
+                        value: control.value.clamp(0, 127) as u8,
+                    })
+            }
+            // Note ons with no velocity are note offs under running status
+            EventType::Noteon => event.get_data::<EvNote>().map(|note| match note.velocity {
+                0 => MidiMessage::NoteOff {
+                    channel: note.channel,
+                    note: note.note,
+                },
+                velocity => MidiMessage::NoteOn {
+                    channel: note.channel,
+                    note: note.note,
+                    velocity,
+                },
+            }),
+            EventType::Noteoff => event.get_data::<EvNote>().map(|note| MidiMessage::NoteOff {
+                channel: note.channel,
+                note: note.note,
+            }),
+            EventType::Clock => Some(MidiMessage::Clock(Instant::now())),
+            EventType::Start => Some(MidiMessage::Start),
+            EventType::Continue => Some(MidiMessage::Continue),
+            EventType::Stop => Some(MidiMessage::Stop),
+            EventType::Songpos => event
+                .get_data::<EvCtrl>()
+                .map(|position| MidiMessage::SongPosition(position.value as i64)),
+            _ => None,
+        };
+
+        if let Some(message) = message
+            && sender.send(message).is_err()
+        {
+            return;
+        }
+    }
+}
+
+impl Plugin for MidiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                apply_remote_commands,
+                update
+                    .after(apply_remote_commands)
+                    .after(crate::parameters::control_parameters)
+                    .before(crate::tempo::control_tempo)
+                    .before(crate::analysis::update),
+            ),
+        );
+    }
+}
+
+pub fn setup(mut midi: ResMut<Midi>, config: Res<Config>) {
+    if !config.midi.enabled {
+        return;
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let connect = config.midi.connect.clone();
+
+    // ALSA sequencer handles can't leave the thread that opened them
+    thread::spawn(move || match open_sequencer(&connect) {
+        Ok(seq) => {
+            info!("Listening for MIDI on the ALSA sequencer");
+            listen_sequencer(seq, sender);
+        }
+        Err(err) => warn!("Could not open the ALSA sequencer: {err}"),
+    });
+
+    midi.receiver = Some(Mutex::new(receiver));
+}
+
+pub fn apply_remote_commands(
+    mut remote_commands: MessageReader<RemoteCommand>,
+    mut midi: ResMut<Midi>,
+    parameters: Res<Parameters>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    for command in remote_commands.read() {
+        if let RemoteCommand::MidiLearn(action) = command {
+            info!("Waiting for a MIDI controller or note to map to {action:?}");
+            midi.learning = Some(action.clone());
+        }
+    }
+
+    // The learn key maps the selected parameter, or cancels learning
+    if keyboard.just_pressed(config.keys.midi_learn) {
+        if midi.learning.take().is_some() {
+            info!("Cancelled MIDI learn");
+        } else if let Some(name) = parameters.selected_name() {
+            info!("Waiting for a MIDI controller or note to map to {name}");
+            midi.learning = Some(MidiAction::Parameter(name));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update(
+    mut midi: ResMut<Midi>,
+    mut parameters: ResMut<Parameters>,
+    mut scenes: ResMut<Scenes>,
+    mut beat_grid: ResMut<BeatGrid>,
+    mut remote_commands: MessageWriter<RemoteCommand>,
+    config_path: Res<ConfigPath>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let midi = midi.as_mut();
+    let messages: Vec<MidiMessage> = match &mut midi.receiver {
+        Some(receiver) => receiver
+            .get_mut()
+            .map(|receiver| receiver.try_iter().collect())
+            .unwrap_or_default(),
+        None => return,
+    };
+
+    let mut learned = false;
+
+    for message in messages {
+        // The control, its value from 0 to 1, and whether it just triggered
+        let (control, value, trigger) = match message {
+            MidiMessage::Controller {
+                channel,
+                controller,
+                value,
+            } => {
+                let previous = midi
+                    .controllers
+                    .insert((channel, controller), value)
+                    .unwrap_or(0);
+                (
+                    MidiControl::Controller {
+                        channel,
+                        controller,
+                    },
+                    value as f32 / 127.0,
+                    previous < TRIGGER_THRESHOLD && value >= TRIGGER_THRESHOLD,
+                )
+            }
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => (
+                MidiControl::Note { channel, note },
+                velocity as f32 / 127.0,
+                true,
+            ),
+            MidiMessage::NoteOff { channel, note } => {
+                (MidiControl::Note { channel, note }, 0.0, false)
+            }
+            MidiMessage::Clock(at) => {
+                if midi.clock.running {
+                    midi.clock.tick(at);
+                }
+                continue;
+            }
+            // Start plays from the top, so the next tick is a downbeat
+            MidiMessage::Start => {
+                midi.clock.restart();
+                midi.clock.running = true;
+                continue;
+            }
+            MidiMessage::Continue => {
+                midi.clock.running = true;
+                continue;
+            }
+            MidiMessage::Stop => {
+                midi.clock.running = false;
+                continue;
+            }
+            MidiMessage::SongPosition(position) => {
+                midi.clock.tick = position * CLOCK_TICKS_PER_SONG_POSITION - 1;
+                continue;
+            }
+        };
+
+        if let Some(action) = midi.learning.take() {
+            info!("Mapped MIDI {control:?} to {action:?}");
+            midi.learn(control, action);
+            learned = true;
+            continue;
+        }
+
+        for mapping in midi
+            .mappings
+            .iter()
+            .filter(|mapping| mapping.control == control)
+        {
+            match &mapping.action {
+                MidiAction::Parameter(name) => {
+                    if let Some(parameter) = parameters.parameter(name) {
+                        let value = parameter.min + value * (parameter.max - parameter.min);
+                        parameters.set(name, value);
+                    }
+                }
+                MidiAction::Input(name) => {
+                    remote_commands.write(RemoteCommand::SetInput(name.clone(), value));
+                }
+                MidiAction::Cue(name) if trigger => match scenes.index_of(name) {
+                    Some(index) => scenes.cue(index),
+                    None => warn!("MIDI cue for unknown scene {name}"),
+                },
+                MidiAction::Take if trigger => scenes.take(),
+                MidiAction::Preset(name) if trigger => {
+                    remote_commands.write(RemoteCommand::RecallPreset(name.clone(), None));
+                }
+                MidiAction::Remote(address) if trigger => match RemoteCommand::parse(address, "") {
+                    Some(command) => {
+                        remote_commands.write(command);
+                    }
+                    None => warn!("MIDI mapping to unknown address {address}"),
+                },
+                _ => {}
+            }
+        }
+    }
+
+    if learned {
+        let mappings = midi.mappings.clone();
+        SavedState::update_file(&config_path.0, |state| state.midi_mappings = Some(mappings));
+    }
+
+    if beat_grid.mode == TempoMode::Midi
+        && let (Some(beats), Some(bpm)) = (midi.clock.beats(Instant::now()), midi.clock.bpm())
+    {
+        let quantum = config.midi.quantum as f64;
+        // Whole bars of offset keep the grid's own beat count while its bars line up with the
+        // song position
+        let shift = ((beat_grid.position - beats) / quantum).round() * quantum;
+
+        beat_grid.bpm = bpm;
+        // analysis::update steps the grid on by this frame afterwards
+        beat_grid.position = beats + shift - (time.delta_secs() * bpm / 60.0) as f64;
+    }
+}
@@ -0,0 +1,426 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use bevy::{
+    app::{HierarchyPropagatePlugin, Propagate},
+    camera::visibility::RenderLayers,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{AudioAnalysis, Signal},
+    config::{ASSET_DIRECTORY, Config, ConfigError},
+    geometry::GeometryPlacement,
+    parameters::Parameters,
+    scene::Scenes,
+};
+
+pub const MODELS_SCENE: &str = "models";
+
+pub struct ModelPlugin {
+    pub mappings: BTreeMap<String, ModelMapping>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelConfig {
+    // A .glb or .gltf file in the assets directory
+    pub path: String,
+    // Which of the file's scenes to spawn
+    pub scene: usize,
+    // A RON file in the assets directory binding animations, morph targets and bones to the audio
+    pub mapping: Option<String>,
+    pub placement: GeometryPlacement,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelMapping {
+    pub animations: Vec<AnimationBinding>,
+    pub morphs: Vec<MorphBinding>,
+    pub bones: Vec<BoneBinding>,
+}
+
+// Plays a named animation clip on loop at `speed + depth * signal`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimationBinding {
+    pub clip: String,
+    pub speed: f32,
+    pub signal: Option<Signal>,
+    pub depth: f32,
+}
+
+// Sets a morph target's weight on the named node to `base + depth * signal`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MorphBinding {
+    pub node: String,
+    pub target: usize,
+    pub signal: Signal,
+    pub base: f32,
+    pub depth: f32,
+}
+
+// Offsets the named node from its rest pose by these amounts at full signal, rotation in degrees
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoneBinding {
+    pub node: String,
+    pub signal: Signal,
+    pub rotation: Vec3,
+    pub translation: Vec3,
+    pub scale: f32,
+}
+
+#[derive(Resource)]
+pub struct ModelMappings(pub BTreeMap<String, ModelMapping>);
+
+#[derive(Component)]
+pub struct Model {
+    pub name: String,
+    pub gltf: Handle<Gltf>,
+    pub graph: Option<Handle<AnimationGraph>>,
+    // Graph nodes of the clips that were found, with the index of their binding
+    pub animations: Vec<(usize, AnimationNodeIndex)>,
+}
+
+// An animation player spawned somewhere in a model's scene
+#[derive(Component)]
+pub struct ModelAnimator {
+    pub model: Entity,
+}
+
+// A named node that the model's mapping moves or morphs, with the pose it was loaded in
+#[derive(Component)]
+pub struct ModelNode {
+    pub model: Entity,
+    pub rest: Transform,
+}
+
+impl Default for ModelConfig {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            scene: 0,
+            mapping: None,
+            placement: GeometryPlacement {
+                translation: Vec3::new(0.0, -150.0, -600.0),
+                tilt: 0.0,
+                scale: 200.0,
+            },
+        }
+    }
+}
+
+impl Default for AnimationBinding {
+    fn default() -> Self {
+        Self {
+            clip: String::new(),
+            speed: 1.0,
+            signal: None,
+            depth: 0.0,
+        }
+    }
+}
+
+impl Default for MorphBinding {
+    fn default() -> Self {
+        Self {
+            node: String::new(),
+            target: 0,
+            signal: Signal::Bass,
+            base: 0.0,
+            depth: 1.0,
+        }
+    }
+}
+
+impl Default for BoneBinding {
+    fn default() -> Self {
+        Self {
+            node: String::new(),
+            signal: Signal::Beat,
+            rotation: Vec3::ZERO,
+            translation: Vec3::ZERO,
+            scale: 0.0,
+        }
+    }
+}
+
+impl ModelMapping {
+    fn references(&self, node: &str) -> bool {
+        self.morphs.iter().any(|morph| morph.node == node)
+            || self.bones.iter().any(|bone| bone.node == node)
+    }
+}
+
+pub fn load_mappings(
+    models: &BTreeMap<String, ModelConfig>,
+) -> Result<BTreeMap<String, ModelMapping>, ConfigError> {
+    let mut mappings = BTreeMap::new();
+
+    for (name, model) in models {
+        let Some(mapping) = &model.mapping else {
+            continue;
+        };
+
+        let path = Path::new(ASSET_DIRECTORY).join(mapping);
+        let contents =
+            fs::read_to_string(&path).map_err(|err| ConfigError::Io(path.clone(), err))?;
+        let mapping: ModelMapping =
+            ron::from_str(&contents).map_err(|err| ConfigError::Parse(path.clone(), err))?;
+
+        mappings.insert(name.clone(), mapping);
+    }
+
+    Ok(mappings)
+}
+
+impl Plugin for ModelPlugin {
+    fn build(&self, app: &mut App) {
+        // glTF scenes spawn as hierarchies, and every node needs the scene's render layer
+        app.add_plugins(HierarchyPropagatePlugin::<RenderLayers>::new(PostUpdate))
+            .insert_resource(ModelMappings(self.mappings.clone()))
+            .add_systems(Startup, setup.before(crate::control::setup))
+            .add_systems(
+                Update,
+                (
+                    build_animation_graphs,
+                    attach_model_parts,
+                    update
+                        .after(crate::analysis::update)
+                        .after(build_animation_graphs)
+                        .after(attach_model_parts),
+                ),
+            );
+    }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut parameters: ResMut<Parameters>,
+    asset_server: Res<AssetServer>,
+    scenes: Res<Scenes>,
+    config: Res<Config>,
+) {
+    if scenes.index_of(MODELS_SCENE).is_none() || config.models.is_empty() {
+        return;
+    }
+
+    parameters.register("models.response", 1.0, 0.0, 4.0, 0.05);
+
+    let render_layers = scenes.render_layers(MODELS_SCENE);
+
+    // glTF materials are lit, the rest of the show is not
+    commands.spawn((
+        DirectionalLight::default(),
+        Transform::from_xyz(1.0, 2.0, 2.0).looking_at(Vec3::ZERO, Vec3::Y),
+        render_layers.clone(),
+    ));
+
+    for (name, model) in &config.models {
+        commands.spawn((
+            SceneRoot(
+                asset_server
+                    .load(GltfAssetLabel::Scene(model.scene).from_asset(model.path.clone())),
+            ),
+            model.placement.transform(),
+            Propagate(render_layers.clone()),
+            Model {
+                name: name.clone(),
+                gltf: asset_server.load(model.path.clone()),
+                graph: None,
+                animations: Vec::new(),
+            },
+        ));
+    }
+}
+
+pub fn build_animation_graphs(
+    mut models: Query<&mut Model>,
+    mut graphs: ResMut<Assets<AnimationGraph>>,
+    gltfs: Res<Assets<Gltf>>,
+    mappings: Res<ModelMappings>,
+) {
+    for mut model in models.iter_mut() {
+        if model.graph.is_some() {
+            continue;
+        }
+        let Some(gltf) = gltfs.get(model.gltf.id()) else {
+            continue;
+        };
+        let Some(mapping) = mappings.0.get(&model.name) else {
+            model.graph = Some(graphs.add(AnimationGraph::new()));
+            continue;
+        };
+
+        let mut found = Vec::new();
+        for (index, binding) in mapping.animations.iter().enumerate() {
+            match gltf.named_animations.get(binding.clip.as_str()) {
+                Some(clip) => found.push((index, clip.clone())),
+                None => warn!(
+                    "Model {} has no animation named {}, available: {}",
+                    model.name,
+                    binding.clip,
+                    gltf.named_animations
+                        .keys()
+                        .map(|name| name.as_ref())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        }
+
+        let (graph, nodes) = AnimationGraph::from_clips(found.iter().map(|(_, clip)| clip.clone()));
+        model.animations = found.iter().map(|(index, _)| *index).zip(nodes).collect();
+        model.graph = Some(graphs.add(graph));
+    }
+}
+
+// Tags the animation players and mapped nodes of newly spawned model scenes
+pub fn attach_model_parts(
+    mut commands: Commands,
+    players: Query<Entity, Added<AnimationPlayer>>,
+    nodes: Query<(Entity, &Name, &Transform), Added<Name>>,
+    parents: Query<&ChildOf>,
+    models: Query<&Model>,
+    mappings: Res<ModelMappings>,
+) {
+    let find_model = |entity: Entity| {
+        parents
+            .iter_ancestors::<ChildOf>(entity)
+            .find(|ancestor| models.contains(*ancestor))
+    };
+
+    for entity in players.iter() {
+        if let Some(model) = find_model(entity) {
+            commands.entity(entity).insert(ModelAnimator { model });
+        }
+    }
+
+    for (entity, name, transform) in nodes.iter() {
+        let Some(model) = find_model(entity) else {
+            continue;
+        };
+        let Some(mapping) = models
+            .get(model)
+            .ok()
+            .and_then(|model| mappings.0.get(&model.name))
+        else {
+            continue;
+        };
+
+        if mapping.references(name.as_str()) {
+            commands.entity(entity).insert(ModelNode {
+                model,
+                rest: *transform,
+            });
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update(
+    mut commands: Commands,
+    mut animators: Query<(
+        Entity,
+        &ModelAnimator,
+        &mut AnimationPlayer,
+        Option<&AnimationGraphHandle>,
+    )>,
+    mut nodes: Query<(&ModelNode, &Name, &mut Transform, Option<&mut MorphWeights>)>,
+    models: Query<&Model>,
+    mappings: Res<ModelMappings>,
+    parameters: Res<Parameters>,
+    analysis: Res<AudioAnalysis>,
+) {
+    let response = parameters.get("models.response").unwrap_or(1.0);
+
+    for (entity, animator, mut player, graph_handle) in animators.iter_mut() {
+        let Ok(model) = models.get(animator.model) else {
+            continue;
+        };
+        let Some(graph) = &model.graph else {
+            continue;
+        };
+
+        // Players start once the model's graph is built, looping every bound clip
+        if graph_handle.is_none() {
+            commands
+                .entity(entity)
+                .insert(AnimationGraphHandle(graph.clone()));
+            for (_, node) in &model.animations {
+                player.play(*node).repeat();
+            }
+            continue;
+        }
+
+        let Some(mapping) = mappings.0.get(&model.name) else {
+            continue;
+        };
+        for (index, node) in &model.animations {
+            let binding = &mapping.animations[*index];
+            let signal = binding
+                .signal
+                .map(|signal| analysis.get(signal))
+                .unwrap_or(0.0);
+
+            if let Some(animation) = player.animation_mut(*node) {
+                animation.set_speed(binding.speed + binding.depth * response * signal);
+            }
+        }
+    }
+
+    for (node, name, mut transform, morph_weights) in nodes.iter_mut() {
+        let Some(mapping) = models
+            .get(node.model)
+            .ok()
+            .and_then(|model| mappings.0.get(&model.name))
+        else {
+            continue;
+        };
+
+        let mut bones = mapping
+            .bones
+            .iter()
+            .filter(|bone| bone.node == name.as_str())
+            .peekable();
+        if bones.peek().is_some() {
+            let mut rotation = Vec3::ZERO;
+            let mut translation = Vec3::ZERO;
+            let mut scale = 0.0;
+            for bone in bones {
+                let signal = analysis.get(bone.signal) * response;
+                rotation += bone.rotation * signal;
+                translation += bone.translation * signal;
+                scale += bone.scale * signal;
+            }
+
+            transform.rotation = node.rest.rotation
+                * Quat::from_euler(
+                    EulerRot::XYZ,
+                    rotation.x.to_radians(),
+                    rotation.y.to_radians(),
+                    rotation.z.to_radians(),
+                );
+            transform.translation = node.rest.translation + translation;
+            transform.scale = node.rest.scale * (1.0 + scale);
+        }
+
+        let Some(mut morph_weights) = morph_weights else {
+            continue;
+        };
+        let weights = morph_weights.weights_mut();
+        for morph in mapping
+            .morphs
+            .iter()
+            .filter(|morph| morph.node == name.as_str())
+        {
+            let signal = analysis.get(morph.signal) * response;
+            if let Some(weight) = weights.get_mut(morph.target) {
+                *weight = (morph.base + morph.depth * signal).clamp(0.0, 1.0);
+            }
+        }
+    }
+}
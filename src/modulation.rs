@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{AudioAnalysis, DFT_FIRST_ROW, MAX_BPM, MIN_BPM, Signal},
+    audiolink::{Audiolink, AudiolinkReadback},
+    config::{ConfigPath, SavedState},
+    generator::{Generators, LfoConfig, LfoShape},
+    parameters::Parameters,
+    remote::RemoteCommand,
+    tempo::BeatGrid,
+};
+
+pub struct ModulationPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModulationConfig {
+    pub routes: Vec<ModulationRoute>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ModulationSource {
+    // A band, the overall level or the beat pulse
+    Signal(Signal),
+    // A single DFT bin
+    Bin(u32),
+    // The louder channel's VU peak, falling back slowly
+    Peak,
+    // Rises from 0 on each beat to 1 at the next
+    BeatPhase,
+    // The tempo estimate across MIN_BPM to MAX_BPM
+    Bpm,
+    // A sine from 0 to 1 and back over this many beats, running on through breakdowns
+    Lfo(f32),
+    // The last value sent to /input/<name> over OSC or HTTP
+    Input(String),
+    // An LFO or envelope from `generators`
+    Generator(String),
+}
+
+// The source is shaped by `curve` as a power, then scaled, offset, smoothed and clamped to `range`.
+// Routes add to their destination parameter in fractions of its range, so a scale of 1 sweeps it
+// from minimum to maximum
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModulationRoute {
+    pub source: ModulationSource,
+    pub destination: String,
+    pub scale: f32,
+    pub offset: f32,
+    pub curve: f32,
+    // Seconds for the output to cover most of the way to a new value, zero following immediately
+    pub smoothing: f32,
+    pub range: Option<(f32, f32)>,
+}
+
+#[derive(Resource)]
+pub struct Modulation {
+    pub routes: Vec<ModulationRoute>,
+    pub inputs: BTreeMap<String, f32>,
+    // Each route's last output, what smoothing eases from
+    pub outputs: Vec<f32>,
+}
+
+impl Default for ModulationRoute {
+    fn default() -> Self {
+        Self {
+            source: ModulationSource::Signal(Signal::Bass),
+            destination: String::new(),
+            scale: 1.0,
+            offset: 0.0,
+            curve: 1.0,
+            smoothing: 0.0,
+            range: None,
+        }
+    }
+}
+
+impl ModulationRoute {
+    // Checked before a route is used, as a bad range makes clamp panic and a bad curve gives NaN,
+    // returning the offending field and what is wrong with it
+    pub fn validate(&self) -> Result<(), (&'static str, &'static str)> {
+        if self.destination.is_empty() {
+            return Err(("destination", "must not be empty"));
+        }
+        if !self.scale.is_finite() || !self.offset.is_finite() {
+            return Err(("scale", "scale and offset must be finite"));
+        }
+        if !(self.curve > 0.0 && self.curve.is_finite()) {
+            return Err(("curve", "must be positive"));
+        }
+        if !(self.smoothing >= 0.0 && self.smoothing.is_finite()) {
+            return Err(("smoothing", "must not be negative"));
+        }
+        if let Some((min, max)) = self.range
+            && (min > max || min.is_nan() || max.is_nan())
+        {
+            return Err(("range", "minimum must not exceed maximum"));
+        }
+        if let ModulationSource::Lfo(beats) = self.source
+            && !(beats > 0.0 && beats.is_finite())
+        {
+            return Err(("source", "LFO period must be positive"));
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, source: f32, previous: f32, delta_time: f32) -> f32 {
+        let shaped = source.abs().powf(self.curve) * source.signum();
+        let target = shaped * self.scale + self.offset;
+
+        let output = if self.smoothing > 0.0 {
+            previous + (target - previous) * (1.0 - (-delta_time / self.smoothing).exp())
+        } else {
+            target
+        };
+
+        match self.range {
+            Some((min, max)) => output.clamp(min, max),
+            None => output,
+        }
+    }
+}
+
+impl Modulation {
+    pub fn new(config: &ModulationConfig) -> Modulation {
+        Modulation {
+            routes: config.routes.clone(),
+            inputs: BTreeMap::new(),
+            outputs: vec![0.0; config.routes.len()],
+        }
+    }
+
+    pub fn add(&mut self, route: ModulationRoute) {
+        self.routes.push(route);
+        self.outputs.push(0.0);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.routes.len() {
+            self.routes.remove(index);
+            self.outputs.remove(index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.routes.clear();
+        self.outputs.clear();
+    }
+}
+
+impl Plugin for ModulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostStartup, warn_unknown_destinations)
+            .add_systems(
+                Update,
+                (
+                    apply_remote_commands,
+                    update
+                        .after(apply_remote_commands)
+                        .after(crate::generator::update),
+                ),
+            );
+    }
+}
+
+pub fn warn_unknown_destinations(modulation: Res<Modulation>, parameters: Res<Parameters>) {
+    for (index, route) in modulation.routes.iter().enumerate() {
+        if parameters.parameter(&route.destination).is_none() {
+            warn!(
+                "Modulation route {index} targets {}: no such parameter",
+                route.destination
+            );
+        }
+    }
+}
+
+pub fn apply_remote_commands(
+    mut remote_commands: MessageReader<RemoteCommand>,
+    mut modulation: ResMut<Modulation>,
+    config_path: Res<ConfigPath>,
+) {
+    let mut edited = false;
+
+    for command in remote_commands.read() {
+        match command {
+            // A NaN would stay in every route smoothing it, so MIDI and remotes alike are checked
+            RemoteCommand::SetInput(name, value) if value.is_finite() => {
+                modulation.inputs.insert(name.clone(), *value);
+            }
+            RemoteCommand::AddRoute(route) => {
+                if let Err((field, message)) = route.validate() {
+                    warn!(
+                        "Ignoring modulation route to {}: {field} {message}",
+                        route.destination
+                    );
+                    continue;
+                }
+                modulation.add(route.clone());
+                edited = true;
+            }
+            RemoteCommand::RemoveRoute(index) => {
+                modulation.remove(*index);
+                edited = true;
+            }
+            RemoteCommand::ClearRoutes => {
+                modulation.clear();
+                edited = true;
+            }
+            _ => {}
+        }
+    }
+
+    if edited {
+        let routes = modulation.routes.clone();
+        SavedState::update_file(&config_path.0, |state| {
+            state.modulation_routes = Some(routes)
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update(
+    mut modulation: ResMut<Modulation>,
+    mut parameters: ResMut<Parameters>,
+    audiolink: Single<&Audiolink>,
+    audiolink_readback: Res<AudiolinkReadback>,
+    generators: Res<Generators>,
+    analysis: Res<AudioAnalysis>,
+    beat_grid: Res<BeatGrid>,
+    time: Res<Time>,
+) {
+    let delta_time = time.delta_secs();
+    let width = audiolink_readback.width.max(1);
+
+    let source_value = |source: &ModulationSource, inputs: &BTreeMap<String, f32>| match source {
+        ModulationSource::Signal(signal) => analysis.get(*signal),
+        ModulationSource::Bin(bin) => audiolink_readback
+            .get(bin % width, DFT_FIRST_ROW + bin / width)
+            .map(|texel| texel.y)
+            .unwrap_or_default(),
+        ModulationSource::Peak => audiolink
+            .left_smoothed_max
+            .max(audiolink.right_smoothed_max),
+        ModulationSource::BeatPhase => analysis.beat_phase,
+        ModulationSource::Bpm => ((analysis.bpm - MIN_BPM) / (MAX_BPM - MIN_BPM)).clamp(0.0, 1.0),
+        ModulationSource::Lfo(beats) => {
+            let lfo = LfoConfig {
+                shape: LfoShape::Sine,
+                beats: *beats,
+                phase: 0.0,
+            };
+            lfo.sample(beat_grid.free_position()).1
+        }
+        ModulationSource::Input(name) => inputs.get(name).copied().unwrap_or_default(),
+        ModulationSource::Generator(name) => generators.get(name).unwrap_or_default(),
+    };
+
+    let modulation = modulation.as_mut();
+    let mut amounts: BTreeMap<String, f32> = BTreeMap::new();
+
+    for (route, output) in modulation.routes.iter().zip(modulation.outputs.iter_mut()) {
+        let source = source_value(&route.source, &modulation.inputs);
+        *output = route.apply(source, *output, delta_time);
+
+        *amounts.entry(route.destination.clone()).or_default() += *output;
+    }
+
+    // Parameters only count as changed when a modulated value moved, so idle routes don't wake
+    // everything that watches them
+    if parameters
+        .bypass_change_detection()
+        .set_modulation(&amounts)
+    {
+        parameters.set_changed();
+    }
+}
@@ -0,0 +1,822 @@
+use std::{fs, path::Path};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    camera::{RenderTarget, visibility::RenderLayers},
+    image::BevyDefault,
+    mesh::{Indices, PrimitiveTopology},
+    prelude::*,
+    render::render_resource::{AsBindGroup, Extent3d, ShaderType, TextureFormat, TextureUsages},
+    shader::ShaderRef,
+    sprite_render::{Material2d, Material2dPlugin},
+    window::{PrimaryWindow, WindowMode, WindowRef, WindowResized},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, ConfigPath, SavedState},
+    layer::LAYER_CAMERA_ORDER,
+    scene::Scenes,
+};
+
+pub const SHADER_ASSET_PATH: &str = "warp.wgsl";
+
+pub const OUTPUT_RENDER_LAYER: usize = 32;
+pub const OUTPUT_CAMERA_ORDER: isize = 100;
+
+pub const WARP_SUBDIVISIONS: u32 = 32;
+pub const DEFAULT_MESH_COLUMNS: usize = 4;
+pub const DEFAULT_MESH_ROWS: usize = 4;
+
+pub const HANDLE_GRAB_RADIUS: f32 = 24.0;
+
+// Corner pins were saved here before each output had a warp of its own, and are moved into the
+// first output the first time they are found
+pub const LEGACY_WARP_PATH: &str = "warp.ron";
+
+pub struct OutputPlugin;
+
+// Layers are composited into `scene`, which post-processing turns into the final `image`
+#[derive(Resource)]
+pub struct Canvas {
+    pub image: Handle<Image>,
+    pub scene: Handle<Image>,
+    pub size: UVec2,
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OutputsConfig {
+    pub canvas_size: Option<UVec2>,
+    pub outputs: Vec<OutputConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub title: String,
+    pub position: Option<IVec2>,
+    pub resolution: Option<UVec2>,
+    pub fullscreen_monitor: Option<usize>,
+    // Shows this scene from a camera of its own at the window's size instead of the canvas, without
+    // layers or post-processing, such as a monitor for a scene the audience doesn't see
+    pub scene: Option<String>,
+    pub region: Rect,
+    pub warp: Warp,
+    pub blend: EdgeBlend,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct EdgeBlend {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+    pub power: f32,
+    pub gamma: f32,
+}
+
+#[derive(Component)]
+pub struct OutputCamera {
+    pub index: usize,
+}
+
+#[derive(Component)]
+pub struct Output {
+    pub index: usize,
+    pub window: Entity,
+    pub material_handle: Handle<WarpMaterial>,
+    pub mesh_handle: Handle<Mesh>,
+    // What the output's own scene camera renders into, resized with its window
+    pub scene_image: Option<Handle<Image>>,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Warp {
+    pub corners: [Vec2; 4],
+    pub mesh: Option<BezierMesh>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BezierMesh {
+    pub columns: usize,
+    pub rows: usize,
+    pub points: Vec<Vec2>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WarpHandle {
+    Corner(usize),
+    Mesh(usize),
+}
+
+#[derive(Resource, Default)]
+pub struct WarpEditor {
+    pub enabled: bool,
+    pub output: usize,
+    pub dragging: Option<WarpHandle>,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct WarpMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    canvas_texture: Handle<Image>,
+    #[uniform(2)]
+    settings: WarpSettings,
+}
+
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct WarpSettings {
+    region: Vec4,
+    blend_edges: Vec4,
+    blend_power: f32,
+    blend_gamma: f32,
+}
+
+impl Material2d for WarpMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+impl Default for OutputsConfig {
+    fn default() -> Self {
+        Self {
+            canvas_size: None,
+            outputs: vec![OutputConfig::default()],
+        }
+    }
+}
+
+impl OutputsConfig {
+    pub fn primary_window(&self) -> Window {
+        self.outputs
+            .first()
+            .map(OutputConfig::window)
+            .unwrap_or_default()
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            title: "VJ Visualiser".to_owned(),
+            position: None,
+            resolution: None,
+            fullscreen_monitor: None,
+            scene: None,
+            region: Rect::new(0.0, 0.0, 1.0, 1.0),
+            warp: Warp::default(),
+            blend: EdgeBlend::default(),
+        }
+    }
+}
+
+impl OutputConfig {
+    pub fn window(&self) -> Window {
+        let mut window = Window {
+            title: self.title.clone(),
+            ..default()
+        };
+
+        if let Some(position) = self.position {
+            window.position = WindowPosition::At(position);
+        }
+        if let Some(resolution) = self.resolution {
+            window.resolution = resolution.into();
+        }
+        if let Some(monitor) = self.fullscreen_monitor {
+            window.mode = WindowMode::BorderlessFullscreen(MonitorSelection::Index(monitor));
+        }
+
+        window
+    }
+
+    fn warp_settings(&self) -> WarpSettings {
+        WarpSettings {
+            region: Vec4::new(
+                self.region.min.x,
+                self.region.min.y,
+                self.region.max.x,
+                self.region.max.y,
+            ),
+            blend_edges: Vec4::new(
+                self.blend.left,
+                self.blend.right,
+                self.blend.top,
+                self.blend.bottom,
+            ),
+            blend_power: self.blend.power,
+            blend_gamma: self.blend.gamma,
+        }
+    }
+}
+
+impl Default for EdgeBlend {
+    fn default() -> Self {
+        Self {
+            left: 0.0,
+            right: 0.0,
+            top: 0.0,
+            bottom: 0.0,
+            power: 2.0,
+            gamma: 2.2,
+        }
+    }
+}
+
+impl Default for Warp {
+    fn default() -> Self {
+        Self {
+            corners: [
+                Vec2::new(0.0, 0.0),
+                Vec2::new(1.0, 0.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(0.0, 1.0),
+            ],
+            mesh: None,
+        }
+    }
+}
+
+impl Warp {
+    // Maps the unit square onto the four corner pins (top left, top right, bottom right, bottom left)
+    pub fn homography(&self) -> Mat3 {
+        let [p0, p1, p2, p3] = self.corners;
+
+        let delta_1 = p1 - p2;
+        let delta_2 = p3 - p2;
+        let sum = p0 - p1 + p2 - p3;
+
+        let denominator = delta_1.perp_dot(delta_2);
+        let (g, h) = if denominator.abs() > f32::EPSILON {
+            (
+                sum.perp_dot(delta_2) / denominator,
+                delta_1.perp_dot(sum) / denominator,
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        Mat3::from_cols(
+            Vec3::new(p1.x - p0.x + g * p1.x, p1.y - p0.y + g * p1.y, g),
+            Vec3::new(p3.x - p0.x + h * p3.x, p3.y - p0.y + h * p3.y, h),
+            Vec3::new(p0.x, p0.y, 1.0),
+        )
+    }
+
+    pub fn evaluate(&self, uv: Vec2) -> Vec2 {
+        let local = match &self.mesh {
+            Some(mesh) => mesh.evaluate(uv),
+            None => uv,
+        };
+
+        project(self.homography(), local)
+    }
+
+    pub fn handle_position(&self, handle: WarpHandle) -> Option<Vec2> {
+        match handle {
+            WarpHandle::Corner(index) => self.corners.get(index).copied(),
+            WarpHandle::Mesh(index) => self
+                .mesh
+                .as_ref()
+                .and_then(|mesh| mesh.points.get(index))
+                .map(|point| project(self.homography(), *point)),
+        }
+    }
+
+    pub fn handles(&self) -> Vec<WarpHandle> {
+        let mut handles: Vec<WarpHandle> = (0..4).map(WarpHandle::Corner).collect();
+
+        if let Some(mesh) = &self.mesh {
+            handles.extend((0..mesh.points.len()).map(WarpHandle::Mesh));
+        }
+
+        handles
+    }
+
+    pub fn move_handle(&mut self, handle: WarpHandle, position: Vec2) {
+        match handle {
+            WarpHandle::Corner(index) => {
+                if let Some(corner) = self.corners.get_mut(index) {
+                    *corner = position;
+                }
+            }
+            WarpHandle::Mesh(index) => {
+                let inverse = self.homography().inverse();
+                if !inverse.is_finite() {
+                    return;
+                }
+
+                if let Some(point) = self
+                    .mesh
+                    .as_mut()
+                    .and_then(|mesh| mesh.points.get_mut(index))
+                {
+                    *point = project(inverse, position);
+                }
+            }
+        }
+    }
+
+    pub fn build_mesh(&self) -> Mesh {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+
+        for row in 0..=WARP_SUBDIVISIONS {
+            for column in 0..=WARP_SUBDIVISIONS {
+                let uv = Vec2::new(
+                    column as f32 / WARP_SUBDIVISIONS as f32,
+                    row as f32 / WARP_SUBDIVISIONS as f32,
+                );
+                let position = self.evaluate(uv);
+
+                positions.push([position.x - 0.5, 0.5 - position.y, 0.0]);
+                uvs.push([uv.x, uv.y]);
+            }
+        }
+
+        let stride = WARP_SUBDIVISIONS + 1;
+        for row in 0..WARP_SUBDIVISIONS {
+            for column in 0..WARP_SUBDIVISIONS {
+                let top_left = row * stride + column;
+                let top_right = top_left + 1;
+                let bottom_left = top_left + stride;
+                let bottom_right = bottom_left + 1;
+
+                indices.extend([top_left, bottom_left, top_right]);
+                indices.extend([top_right, bottom_left, bottom_right]);
+            }
+        }
+
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(indices))
+    }
+}
+
+impl BezierMesh {
+    pub fn identity(columns: usize, rows: usize) -> BezierMesh {
+        let columns = columns.max(2);
+        let rows = rows.max(2);
+
+        let mut points = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                points.push(Vec2::new(
+                    column as f32 / (columns - 1) as f32,
+                    row as f32 / (rows - 1) as f32,
+                ));
+            }
+        }
+
+        BezierMesh {
+            columns,
+            rows,
+            points,
+        }
+    }
+
+    pub fn evaluate(&self, uv: Vec2) -> Vec2 {
+        if self.columns < 2 || self.rows < 2 || self.points.len() != self.columns * self.rows {
+            return uv;
+        }
+
+        let mut position = Vec2::ZERO;
+
+        for row in 0..self.rows {
+            let row_weight = bernstein(self.rows - 1, row, uv.y);
+
+            for column in 0..self.columns {
+                let column_weight = bernstein(self.columns - 1, column, uv.x);
+
+                position += self.points[row * self.columns + column] * row_weight * column_weight;
+            }
+        }
+
+        position
+    }
+}
+
+fn bernstein(degree: usize, index: usize, t: f32) -> f32 {
+    let mut coefficient = 1.0;
+    for i in 0..index {
+        coefficient *= (degree - i) as f32 / (i + 1) as f32;
+    }
+
+    coefficient * t.powi(index as i32) * (1.0 - t).powi((degree - index) as i32)
+}
+
+fn project(matrix: Mat3, point: Vec2) -> Vec2 {
+    let projected = matrix * point.extend(1.0);
+
+    projected.truncate() / projected.z
+}
+
+pub fn migrate_legacy_warp(outputs_config: &mut OutputsConfig, config_path: &Path) {
+    let Ok(contents) = fs::read_to_string(LEGACY_WARP_PATH) else {
+        return;
+    };
+    let Some(output_config) = outputs_config.outputs.first_mut() else {
+        return;
+    };
+
+    if output_config.warp != Warp::default() {
+        warn!("Ignoring {LEGACY_WARP_PATH}, the first output already has a warp");
+        return;
+    }
+
+    let warp: Warp = match ron::from_str(&contents) {
+        Ok(warp) => warp,
+        Err(err) => {
+            warn!("Could not migrate {LEGACY_WARP_PATH}: {err}");
+            return;
+        }
+    };
+
+    output_config.warp = warp.clone();
+    SavedState::update_file(config_path, |state| {
+        state.warps.insert(0, warp);
+    });
+
+    let migrated_path = format!("{LEGACY_WARP_PATH}.migrated");
+    match fs::rename(LEGACY_WARP_PATH, &migrated_path) {
+        Ok(()) => info!(
+            "Moved {LEGACY_WARP_PATH} into the first output's warp in {}, keeping the old file as {migrated_path}",
+            SavedState::path(config_path).display()
+        ),
+        Err(err) => warn!("Could not rename {LEGACY_WARP_PATH}: {err}"),
+    }
+}
+
+impl Plugin for OutputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<WarpMaterial>::default())
+            .init_resource::<OutputsConfig>()
+            .init_resource::<WarpEditor>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    resize_outputs,
+                    edit_warp,
+                    rebuild_warp_mesh.after(edit_warp),
+                    draw_warp_handles.after(edit_warp),
+                ),
+            );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<WarpMaterial>>,
+    mut gizmo_config_store: ResMut<GizmoConfigStore>,
+    mut outputs_config: ResMut<OutputsConfig>,
+    editor: Res<WarpEditor>,
+    scenes: Res<Scenes>,
+    config_path: Res<ConfigPath>,
+    primary_window: Single<(Entity, &Window), With<PrimaryWindow>>,
+) {
+    let (primary_window_entity, primary_window) = *primary_window;
+
+    migrate_legacy_warp(&mut outputs_config, &config_path.0);
+
+    let size = outputs_config.canvas_size.unwrap_or(UVec2::new(
+        primary_window.physical_width(),
+        primary_window.physical_height(),
+    ));
+    let size = size.max(UVec2::ONE);
+
+    let mut image = Image::new_target_texture(size.x, size.y, TextureFormat::bevy_default());
+    image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+    let canvas_image = images.add(image);
+    let scene_image = images.add(Image::new_target_texture(
+        size.x,
+        size.y,
+        TextureFormat::bevy_default(),
+    ));
+
+    commands.insert_resource(Canvas {
+        image: canvas_image.clone(),
+        scene: scene_image,
+        size,
+    });
+
+    gizmo_config_store
+        .config_mut::<DefaultGizmoConfigGroup>()
+        .0
+        .render_layers = RenderLayers::layer(OUTPUT_RENDER_LAYER + editor.output);
+
+    for (index, output_config) in outputs_config.outputs.iter().enumerate() {
+        let (window_entity, window_size, physical_size) = if index == 0 {
+            (
+                primary_window_entity,
+                Vec2::new(primary_window.width(), primary_window.height()),
+                primary_window.physical_size(),
+            )
+        } else {
+            let window = output_config.window();
+            let window_size = Vec2::new(window.width(), window.height());
+            let physical_size = window.physical_size();
+
+            (commands.spawn(window).id(), window_size, physical_size)
+        };
+
+        let scene_image = output_config.scene.as_ref().map(|name| {
+            let scene_image = images.add(Image::new_target_texture(
+                physical_size.x.max(1),
+                physical_size.y.max(1),
+                TextureFormat::bevy_default(),
+            ));
+            commands.spawn((
+                Camera3d::default(),
+                Camera {
+                    order: LAYER_CAMERA_ORDER,
+                    target: RenderTarget::Image(scene_image.clone().into()),
+                    ..default()
+                },
+                scenes.render_layers(name),
+            ));
+
+            scene_image
+        });
+
+        commands.spawn((
+            Camera2d,
+            Camera {
+                order: OUTPUT_CAMERA_ORDER,
+                target: RenderTarget::Window(WindowRef::Entity(window_entity)),
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            RenderLayers::layer(OUTPUT_RENDER_LAYER + index),
+            OutputCamera { index },
+        ));
+
+        let material_handle = materials.add(WarpMaterial {
+            canvas_texture: scene_image.clone().unwrap_or(canvas_image.clone()),
+            settings: output_config.warp_settings(),
+        });
+        let mesh_handle = meshes.add(output_config.warp.build_mesh());
+
+        commands.spawn((
+            Mesh2d(mesh_handle.clone()),
+            MeshMaterial2d(material_handle.clone()),
+            Transform::from_scale(window_size.extend(1.0)),
+            RenderLayers::layer(OUTPUT_RENDER_LAYER + index),
+            output_config.warp.clone(),
+            Output {
+                index,
+                window: window_entity,
+                material_handle,
+                mesh_handle,
+                scene_image,
+            },
+        ));
+    }
+}
+
+pub fn resize_outputs(
+    mut resize_events: MessageReader<WindowResized>,
+    mut canvas: ResMut<Canvas>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<WarpMaterial>>,
+    mut outputs: Query<(&Output, &mut Transform)>,
+    outputs_config: Res<OutputsConfig>,
+    windows: Query<&Window>,
+) {
+    let mut primary_resized = false;
+
+    for resize_event in resize_events.read() {
+        for (output, mut transform) in outputs.iter_mut() {
+            if output.window != resize_event.window {
+                continue;
+            }
+
+            transform.scale = Vec3::new(resize_event.width, resize_event.height, 1.0);
+            primary_resized |= output.index == 0;
+
+            if let Some(scene_image) = &output.scene_image
+                && let Ok(window) = windows.get(output.window)
+                && let Some(image) = images.get_mut(scene_image.id())
+            {
+                let size = window.physical_size().max(UVec2::ONE);
+                image.resize(Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                });
+
+                // Reassigning the texture rebuilds the bind group against the resized image
+                if let Some(material) = materials.get_mut(output.material_handle.id()) {
+                    material.canvas_texture = scene_image.clone();
+                }
+            }
+        }
+    }
+
+    if !primary_resized || outputs_config.canvas_size.is_some() {
+        return;
+    }
+
+    let Some(window) = outputs
+        .iter()
+        .find(|(output, _)| output.index == 0)
+        .and_then(|(output, _)| windows.get(output.window).ok())
+    else {
+        return;
+    };
+
+    let size = UVec2::new(window.physical_width(), window.physical_height()).max(UVec2::ONE);
+    if size == canvas.size {
+        return;
+    }
+
+    for handle in [&canvas.image, &canvas.scene] {
+        if let Some(image) = images.get_mut(handle.id()) {
+            image.resize(Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            });
+        }
+    }
+    canvas.size = size;
+
+    // Reassigning the texture rebuilds the bind group against the resized canvas
+    for (output, _) in outputs
+        .iter()
+        .filter(|(output, _)| output.scene_image.is_none())
+    {
+        if let Some(material) = materials.get_mut(output.material_handle.id()) {
+            material.canvas_texture = canvas.image.clone();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn edit_warp(
+    mut editor: ResMut<WarpEditor>,
+    mut outputs_config: ResMut<OutputsConfig>,
+    config: Res<Config>,
+    config_path: Res<ConfigPath>,
+    mut gizmo_config_store: ResMut<GizmoConfigStore>,
+    mut warps: Query<(&Output, &mut Warp)>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window>,
+) {
+    if keyboard.just_pressed(config.keys.warp_edit) {
+        editor.enabled = !editor.enabled;
+        editor.dragging = None;
+    }
+
+    if !editor.enabled {
+        return;
+    }
+
+    if keyboard.just_pressed(config.keys.warp_next_output) {
+        editor.output = (editor.output + 1) % outputs_config.outputs.len().max(1);
+        editor.dragging = None;
+
+        gizmo_config_store
+            .config_mut::<DefaultGizmoConfigGroup>()
+            .0
+            .render_layers = RenderLayers::layer(OUTPUT_RENDER_LAYER + editor.output);
+    }
+
+    let Some((output, mut warp)) = warps
+        .iter_mut()
+        .find(|(output, _)| output.index == editor.output)
+    else {
+        return;
+    };
+
+    let mut changed = false;
+
+    if keyboard.just_pressed(config.keys.warp_toggle_mesh) {
+        warp.mesh = match warp.mesh {
+            Some(_) => None,
+            None => Some(BezierMesh::identity(
+                DEFAULT_MESH_COLUMNS,
+                DEFAULT_MESH_ROWS,
+            )),
+        };
+        changed = true;
+    }
+
+    if keyboard.just_pressed(config.keys.warp_reset) {
+        *warp = Warp::default();
+        changed = true;
+    }
+
+    if let Ok(window) = windows.get(output.window) {
+        let window_size = Vec2::new(window.width(), window.height());
+
+        if let Some(cursor) = window.cursor_position().map(|cursor| cursor / window_size) {
+            if mouse.just_pressed(MouseButton::Left) {
+                editor.dragging = warp
+                    .handles()
+                    .into_iter()
+                    .filter_map(|handle| {
+                        warp.handle_position(handle)
+                            .map(|position| (handle, ((position - cursor) * window_size).length()))
+                    })
+                    .filter(|(_, distance)| *distance <= HANDLE_GRAB_RADIUS)
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(handle, _)| handle);
+            }
+
+            if let Some(handle) = editor.dragging
+                && mouse.pressed(MouseButton::Left)
+                && warp.handle_position(handle) != Some(cursor)
+            {
+                warp.move_handle(handle, cursor);
+            }
+        }
+    }
+
+    if editor.dragging.is_some() && !mouse.pressed(MouseButton::Left) {
+        editor.dragging = None;
+        changed = true;
+    }
+
+    if changed && let Some(output_config) = outputs_config.outputs.get_mut(output.index) {
+        output_config.warp = warp.clone();
+
+        SavedState::update_file(&config_path.0, |state| {
+            state.warps.insert(output.index, warp.clone());
+        });
+    }
+}
+
+pub fn rebuild_warp_mesh(
+    warps: Query<(&Warp, &Output), Changed<Warp>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (warp, output) in warps.iter() {
+        if let Some(mesh) = meshes.get_mut(output.mesh_handle.id()) {
+            *mesh = warp.build_mesh();
+        }
+    }
+}
+
+pub fn draw_warp_handles(
+    mut gizmos: Gizmos,
+    editor: Res<WarpEditor>,
+    warps: Query<(&Output, &Warp)>,
+    windows: Query<&Window>,
+) {
+    if !editor.enabled {
+        return;
+    }
+
+    let Some((output, warp)) = warps
+        .iter()
+        .find(|(output, _)| output.index == editor.output)
+    else {
+        return;
+    };
+    let Ok(window) = windows.get(output.window) else {
+        return;
+    };
+
+    let window_size = Vec2::new(window.width(), window.height());
+    let to_world = |position: Vec2| (Vec2::new(position.x, 1.0 - position.y) - 0.5) * window_size;
+
+    for (start, end) in [(0, 1), (1, 2), (2, 3), (3, 0)] {
+        gizmos.line_2d(
+            to_world(warp.corners[start]),
+            to_world(warp.corners[end]),
+            Color::WHITE,
+        );
+    }
+
+    for handle in warp.handles() {
+        let Some(position) = warp.handle_position(handle) else {
+            continue;
+        };
+
+        let color = if editor.dragging == Some(handle) {
+            Color::srgb(1.0, 1.0, 0.0)
+        } else {
+            match handle {
+                WarpHandle::Corner(_) => Color::WHITE,
+                WarpHandle::Mesh(_) => Color::srgb(0.0, 1.0, 1.0),
+            }
+        };
+
+        gizmos.circle_2d(to_world(position), HANDLE_GRAB_RADIUS * 0.5, color);
+    }
+}
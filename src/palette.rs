@@ -0,0 +1,455 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{CompressedImageFormats, ImageSampler, ImageType},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::AudioAnalysis,
+    config::{ASSET_DIRECTORY, Config, ConfigError},
+};
+
+pub const PALETTE_WIDTH: u32 = 256;
+pub const IMAGE_STRIP_STOPS: u32 = 32;
+
+const ROTATION_SMOOTHING: f32 = 8.0;
+
+pub struct PalettePlugin;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PaletteStop {
+    pub position: f32,
+    pub color: Oklcha,
+}
+
+#[derive(Clone, Debug)]
+pub struct Palette {
+    pub stops: Vec<PaletteStop>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum PaletteSource {
+    Stops(Vec<PaletteStop>),
+    Gpl(String),
+    Image(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaletteConfig {
+    pub preset: String,
+    pub crossfade: f32,
+    pub beat_rotation: f32,
+    pub palettes: BTreeMap<String, PaletteSource>,
+}
+
+#[derive(Resource)]
+pub struct PaletteTexture(pub Handle<Image>);
+
+#[derive(Resource)]
+pub struct Palettes {
+    pub palettes: BTreeMap<String, Palette>,
+    pub current: String,
+    pub previous: Option<String>,
+    pub transition: f32,
+    pub rotation: f32,
+    pub target_rotation: f32,
+}
+
+impl Default for PaletteConfig {
+    fn default() -> Self {
+        Self {
+            preset: "audiolink".to_owned(),
+            crossfade: 2.0,
+            beat_rotation: 0.0,
+            palettes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Palette {
+    pub fn new(mut stops: Vec<PaletteStop>) -> Palette {
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+
+        Palette { stops }
+    }
+
+    pub fn evenly_spaced(colors: impl IntoIterator<Item = Oklcha>) -> Palette {
+        let colors: Vec<Oklcha> = colors.into_iter().collect();
+        let last = (colors.len().max(2) - 1) as f32;
+
+        Palette::new(
+            colors
+                .into_iter()
+                .enumerate()
+                .map(|(index, color)| PaletteStop {
+                    position: index as f32 / last,
+                    color,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn sample(&self, position: f32) -> Oklcha {
+        let (Some(first), Some(last)) = (self.stops.first(), self.stops.last()) else {
+            return Oklcha::new(0.0, 0.0, 0.0, 1.0);
+        };
+
+        if position <= first.position {
+            return first.color;
+        }
+
+        for window in self.stops.windows(2) {
+            let (from, to) = (window[0], window[1]);
+
+            if position <= to.position {
+                let span = (to.position - from.position).max(f32::EPSILON);
+                return from.color.mix(&to.color, (position - from.position) / span);
+            }
+        }
+
+        last.color
+    }
+
+    pub fn from_gpl(contents: &str) -> Result<Palette, String> {
+        let mut lines = contents.lines();
+
+        if lines.next().map(str::trim) != Some("GIMP Palette") {
+            return Err("missing \"GIMP Palette\" header".to_owned());
+        }
+
+        let mut colors = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:")
+            {
+                continue;
+            }
+
+            let channels: Vec<u8> = line
+                .split_whitespace()
+                .take(3)
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("line {}: expected \"R G B [name]\"", number + 2))?;
+
+            let [red, green, blue] = channels[..] else {
+                return Err(format!("line {}: expected \"R G B [name]\"", number + 2));
+            };
+
+            colors.push(Oklcha::from(Srgba::rgb_u8(red, green, blue)));
+        }
+
+        if colors.is_empty() {
+            return Err("no colors".to_owned());
+        }
+
+        Ok(Palette::evenly_spaced(colors))
+    }
+
+    // Samples the middle row of an image from left to right
+    pub fn from_image_strip(image: &Image) -> Result<Palette, String> {
+        let width = image.width();
+        if width == 0 {
+            return Err("image is empty".to_owned());
+        }
+
+        let stops = IMAGE_STRIP_STOPS.min(width);
+        let row = image.height() / 2;
+
+        let colors = (0..stops)
+            .map(|stop| {
+                let x = (stop * (width - 1)) / (stops - 1).max(1);
+                image
+                    .get_color_at(x, row)
+                    .map(Oklcha::from)
+                    .map_err(|err| err.to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Palette::evenly_spaced(colors))
+    }
+
+    fn load(source: &PaletteSource) -> Result<Palette, String> {
+        match source {
+            PaletteSource::Stops(stops) => {
+                if stops.is_empty() {
+                    return Err("needs at least one stop".to_owned());
+                }
+                if stops
+                    .iter()
+                    .any(|stop| !(0.0..=1.0).contains(&stop.position))
+                {
+                    return Err("stop positions must be within 0..1".to_owned());
+                }
+
+                Ok(Palette::new(stops.clone()))
+            }
+            PaletteSource::Gpl(path) => {
+                let contents = fs::read_to_string(Path::new(ASSET_DIRECTORY).join(path))
+                    .map_err(|err| format!("{path}: {err}"))?;
+
+                Palette::from_gpl(&contents).map_err(|err| format!("{path}: {err}"))
+            }
+            PaletteSource::Image(path) => {
+                let bytes = fs::read(Path::new(ASSET_DIRECTORY).join(path))
+                    .map_err(|err| format!("{path}: {err}"))?;
+                let extension = Path::new(path)
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or_default();
+
+                let image = Image::from_buffer(
+                    &bytes,
+                    ImageType::Extension(extension),
+                    CompressedImageFormats::NONE,
+                    true,
+                    ImageSampler::Default,
+                    RenderAssetUsages::MAIN_WORLD,
+                )
+                .map_err(|err| format!("{path}: {err}"))?;
+
+                Palette::from_image_strip(&image).map_err(|err| format!("{path}: {err}"))
+            }
+        }
+    }
+}
+
+pub fn presets() -> BTreeMap<String, Palette> {
+    let stops = |colors: &[(f32, f32, f32)]| {
+        Palette::evenly_spaced(
+            colors
+                .iter()
+                .map(|(lightness, chroma, hue)| Oklcha::new(*lightness, *chroma, *hue, 1.0)),
+        )
+    };
+
+    BTreeMap::from([
+        (
+            "audiolink".to_owned(),
+            stops(&[
+                (0.41, 0.13, 240.0),
+                (0.56, 0.13, 300.0),
+                (0.71, 0.13, 360.0),
+            ]),
+        ),
+        (
+            "fire".to_owned(),
+            stops(&[
+                (0.15, 0.05, 30.0),
+                (0.5, 0.2, 30.0),
+                (0.75, 0.18, 60.0),
+                (0.97, 0.07, 100.0),
+            ]),
+        ),
+        (
+            "ocean".to_owned(),
+            stops(&[(0.2, 0.06, 260.0), (0.5, 0.12, 230.0), (0.85, 0.1, 190.0)]),
+        ),
+        (
+            "neon".to_owned(),
+            stops(&[
+                (0.7, 0.3, 330.0),
+                (0.85, 0.2, 200.0),
+                (0.9, 0.22, 130.0),
+                (0.7, 0.3, 330.0),
+            ]),
+        ),
+        (
+            "mono".to_owned(),
+            stops(&[(0.0, 0.0, 0.0), (1.0, 0.0, 0.0)]),
+        ),
+    ])
+}
+
+pub fn load_palettes(config: &PaletteConfig) -> Result<BTreeMap<String, Palette>, ConfigError> {
+    let mut palettes = presets();
+
+    for (name, source) in config.palettes.iter() {
+        let palette = Palette::load(source).map_err(|message| ConfigError::Invalid {
+            key: format!("palette.palettes.{name}"),
+            message,
+        })?;
+
+        palettes.insert(name.clone(), palette);
+    }
+
+    if !palettes.contains_key(&config.preset) {
+        return Err(ConfigError::Invalid {
+            key: "palette.preset".to_owned(),
+            message: format!(
+                "unknown palette {}, expected one of {}",
+                config.preset,
+                palettes.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        });
+    }
+
+    Ok(palettes)
+}
+
+impl Palettes {
+    pub fn new(palettes: BTreeMap<String, Palette>, current: &str) -> Palettes {
+        Palettes {
+            palettes,
+            current: current.to_owned(),
+            previous: None,
+            transition: 1.0,
+            rotation: 0.0,
+            target_rotation: 0.0,
+        }
+    }
+
+    pub fn show(&mut self, name: &str) {
+        if name == self.current || !self.palettes.contains_key(name) {
+            return;
+        }
+
+        self.previous = Some(std::mem::replace(&mut self.current, name.to_owned()));
+        self.transition = 0.0;
+    }
+
+    pub fn next(&mut self) {
+        let next = self
+            .palettes
+            .range::<String, _>((
+                std::ops::Bound::Excluded(&self.current),
+                std::ops::Bound::Unbounded,
+            ))
+            .next()
+            .or_else(|| self.palettes.iter().next())
+            .map(|(name, _)| name.clone());
+
+        if let Some(next) = next {
+            self.show(&next);
+        }
+    }
+
+    pub fn sample(&self, position: f32) -> Oklaba {
+        let position = (position + self.rotation).rem_euclid(1.0);
+        let current = self
+            .palettes
+            .get(&self.current)
+            .map(|palette| Oklaba::from(palette.sample(position)))
+            .unwrap_or(Oklaba::BLACK);
+
+        match self
+            .previous
+            .as_ref()
+            .and_then(|previous| self.palettes.get(previous))
+        {
+            Some(previous) => {
+                Oklaba::from(previous.sample(position)).mix(&current, self.transition)
+            }
+            None => current,
+        }
+    }
+}
+
+impl Plugin for PalettePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup).add_systems(
+            Update,
+            (
+                control_palettes,
+                update_palettes
+                    .after(control_palettes)
+                    .after(crate::analysis::update),
+                update_palette_texture.after(update_palettes),
+            ),
+        );
+    }
+}
+
+pub fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: PALETTE_WIDTH,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::linear();
+
+    commands.insert_resource(PaletteTexture(images.add(image)));
+}
+
+pub fn control_palettes(
+    mut palettes: ResMut<Palettes>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    if keyboard.just_pressed(config.keys.palette_next) {
+        palettes.next();
+    }
+}
+
+pub fn update_palettes(
+    mut palettes: ResMut<Palettes>,
+    analysis: Res<AudioAnalysis>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let delta_time = time.delta_secs();
+
+    if palettes.transition < 1.0 {
+        palettes.transition = if config.palette.crossfade > 0.0 {
+            (palettes.transition + delta_time / config.palette.crossfade).min(1.0)
+        } else {
+            1.0
+        };
+
+        if palettes.transition >= 1.0 {
+            palettes.previous = None;
+        }
+    }
+
+    if analysis.beat && config.palette.beat_rotation != 0.0 {
+        palettes.target_rotation += config.palette.beat_rotation;
+    }
+
+    if palettes.rotation != palettes.target_rotation {
+        let difference = palettes.target_rotation - palettes.rotation;
+        palettes.rotation += difference * (ROTATION_SMOOTHING * delta_time).min(1.0);
+
+        if (palettes.target_rotation - palettes.rotation).abs() < 1e-4 {
+            palettes.rotation = palettes.target_rotation;
+        }
+    }
+}
+
+pub fn update_palette_texture(
+    palettes: Res<Palettes>,
+    palette_texture: Res<PaletteTexture>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !palettes.is_changed() {
+        return;
+    }
+
+    let Some(image) = images.get_mut(palette_texture.0.id()) else {
+        return;
+    };
+
+    let data: Vec<u8> = (0..PALETTE_WIDTH)
+        .flat_map(|x| {
+            let color = Srgba::from(palettes.sample(x as f32 / (PALETTE_WIDTH - 1) as f32));
+
+            [color.red, color.green, color.blue, color.alpha]
+                .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+        })
+        .collect();
+
+    image.data = Some(data);
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+use crate::{config::Config, remote::RemoteCommand};
+
+pub struct ParametersPlugin;
+
+#[derive(Clone, Debug)]
+pub struct Parameter {
+    pub value: f32,
+    pub default: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    // Added by the modulation matrix, as a fraction of the range
+    pub modulation: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct Parameters {
+    parameters: BTreeMap<String, Parameter>,
+    overrides: BTreeMap<String, f32>,
+    pub selected: usize,
+}
+
+impl Parameter {
+    // Clamping keeps NaN, so non-finite values are ignored
+    pub fn set(&mut self, value: f32) {
+        if value.is_finite() {
+            self.value = value.clamp(self.min, self.max);
+        }
+    }
+
+    pub fn modulated(&self) -> f32 {
+        (self.value + self.modulation * (self.max - self.min)).clamp(self.min, self.max)
+    }
+
+    pub fn normalized(&self) -> f32 {
+        if self.max > self.min {
+            (self.value - self.min) / (self.max - self.min)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Parameters {
+    pub fn new(overrides: &BTreeMap<String, f32>) -> Parameters {
+        Parameters {
+            parameters: BTreeMap::new(),
+            overrides: overrides.clone(),
+            selected: 0,
+        }
+    }
+
+    pub fn register(&mut self, name: &str, default: f32, min: f32, max: f32, step: f32) {
+        let mut parameter = Parameter {
+            value: default,
+            default,
+            min,
+            max,
+            step,
+            modulation: 0.0,
+        };
+        parameter.set(self.overrides.get(name).copied().unwrap_or(default));
+
+        self.parameters.insert(name.to_owned(), parameter);
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        self.parameters.get(name).map(Parameter::modulated)
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&Parameter> {
+        self.parameters.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: f32) -> bool {
+        match self.parameters.get_mut(name) {
+            Some(parameter) => {
+                parameter.set(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Replaces every parameter's modulation, returning whether any of them changed
+    pub fn set_modulation(&mut self, amounts: &BTreeMap<String, f32>) -> bool {
+        let mut changed = false;
+
+        for (name, parameter) in self.parameters.iter_mut() {
+            let amount = amounts.get(name).copied().unwrap_or(0.0);
+            if parameter.modulation != amount {
+                parameter.modulation = amount;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    pub fn nudge(&mut self, name: &str, steps: f32) {
+        if let Some(parameter) = self.parameters.get_mut(name) {
+            parameter.set(parameter.value + parameter.step * steps);
+        }
+    }
+
+    pub fn reset(&mut self, name: &str) {
+        if let Some(parameter) = self.parameters.get_mut(name) {
+            parameter.set(parameter.default);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Parameter)> {
+        self.parameters.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.parameters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parameters.is_empty()
+    }
+
+    pub fn selected_name(&self) -> Option<String> {
+        self.parameters.keys().nth(self.selected).cloned()
+    }
+}
+
+impl Plugin for ParametersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostStartup, warn_unknown_overrides)
+            .add_systems(
+                Update,
+                (
+                    control_parameters,
+                    apply_remote_commands.after(crate::remote::receive_commands),
+                ),
+            );
+    }
+}
+
+pub fn warn_unknown_overrides(parameters: Res<Parameters>) {
+    for name in parameters.overrides.keys() {
+        if !parameters.parameters.contains_key(name) {
+            warn!("Ignoring parameters.{name}: no such parameter");
+        }
+    }
+}
+
+pub fn control_parameters(
+    mut parameters: ResMut<Parameters>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    let keys = &config.keys;
+    let count = parameters.len().max(1);
+
+    if keyboard.just_pressed(keys.parameter_next) {
+        parameters.selected = (parameters.selected + 1) % count;
+    }
+    if keyboard.just_pressed(keys.parameter_previous) {
+        parameters.selected = (parameters.selected + count - 1) % count;
+    }
+
+    let Some(name) = parameters.selected_name() else {
+        return;
+    };
+
+    if keyboard.just_pressed(keys.parameter_increase) {
+        parameters.nudge(&name, 1.0);
+    }
+    if keyboard.just_pressed(keys.parameter_decrease) {
+        parameters.nudge(&name, -1.0);
+    }
+    if keyboard.just_pressed(keys.parameter_reset) {
+        parameters.reset(&name);
+    }
+}
+
+pub fn apply_remote_commands(
+    mut remote_commands: MessageReader<RemoteCommand>,
+    mut parameters: ResMut<Parameters>,
+) {
+    for command in remote_commands.read() {
+        if let RemoteCommand::SetParameter(name, value) = command
+            && !parameters.set(name, *value)
+        {
+            warn!("Remote value for unknown parameter {name}");
+        }
+    }
+}
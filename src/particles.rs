@@ -0,0 +1,483 @@
+use std::borrow::Cow;
+
+use bevy::{
+    asset::RenderAssetUsages,
+    camera::visibility::NoFrustumCulling,
+    mesh::{Indices, PrimitiveTopology},
+    prelude::*,
+    render::{
+        Render, RenderApp, RenderStartup, RenderSystems,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        graph::CameraDriverLabel,
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph, RenderLabel},
+        render_resource::{
+            binding_types::{
+                storage_buffer_read_only_sized, storage_buffer_sized, texture_2d, uniform_buffer,
+            },
+            *,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
+        texture::GpuImage,
+    },
+    shader::{PipelineCacheError, ShaderDefVal, ShaderRef},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::AudioAnalysis,
+    audiolink::{AudiolinkDataTexture, AudiolinkLabel, DFT_BINS},
+    config::Config,
+    palette::PaletteTexture,
+    parameters::Parameters,
+    scene::Scenes,
+};
+
+pub const SHADER_ASSET_PATH: &str = "particles.wgsl";
+pub const MATERIAL_SHADER_ASSET_PATH: &str = "particle_material.wgsl";
+
+pub const SCENE_NAME: &str = "particles";
+
+pub const WORKGROUP_SIZE: u32 = 64;
+
+// Matches `Particle` in particles.wgsl: position and age, velocity and lifetime, palette position,
+// size and padding
+pub const PARTICLE_SIZE: u64 = 48;
+
+pub struct ParticlesPlugin {
+    pub config: ParticlesConfig,
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParticlesConfig {
+    pub count: u32,
+    pub translation: Vec3,
+    pub scale: f32,
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+pub struct ParticlesLabel;
+
+// Two particle buffers swapped every frame: the compute pass reads the previous state from one and
+// writes the next into `current`, which the particle material draws
+#[derive(Resource, Clone, ExtractResource)]
+pub struct ParticleBuffers {
+    buffer_a: Handle<ShaderStorageBuffer>,
+    buffer_b: Handle<ShaderStorageBuffer>,
+    pub current: Handle<ShaderStorageBuffer>,
+}
+
+#[derive(Resource, Clone, Debug, Default, ExtractResource, ShaderType)]
+pub struct ParticleUniforms {
+    pub delta_time: f32,
+    pub seed: f32,
+    // Chance for each dead particle to respawn this frame
+    pub emission: f32,
+    pub burst: f32,
+    pub speed: f32,
+    pub drag: f32,
+    pub gravity: f32,
+    pub lifetime: f32,
+    pub size: f32,
+}
+
+#[derive(Resource)]
+pub struct ParticleBindGroup(BindGroup);
+
+#[derive(Resource)]
+pub struct ParticlePipeline {
+    bind_group_layout: BindGroupLayout,
+    init_pipeline: CachedComputePipelineId,
+    update_pipeline: CachedComputePipelineId,
+}
+
+pub enum ParticleState {
+    Loading,
+    Init,
+    Update,
+}
+
+pub struct ParticleNode {
+    state: ParticleState,
+}
+
+#[derive(Component)]
+pub struct Particles {
+    pub material_handle: Handle<ParticleMaterial>,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct ParticleMaterial {
+    #[storage(0, read_only)]
+    particles: Handle<ShaderStorageBuffer>,
+    #[texture(1)]
+    #[sampler(2)]
+    palette_texture: Option<Handle<Image>>,
+}
+
+impl Default for ParticlesConfig {
+    fn default() -> Self {
+        Self {
+            count: 65536,
+            translation: Vec3::new(0.0, 0.0, -900.0),
+            scale: 400.0,
+        }
+    }
+}
+
+impl ParticleBuffers {
+    fn previous(&self) -> &Handle<ShaderStorageBuffer> {
+        if self.current == self.buffer_a {
+            &self.buffer_b
+        } else {
+            &self.buffer_a
+        }
+    }
+}
+
+impl Default for ParticleNode {
+    fn default() -> Self {
+        Self {
+            state: ParticleState::Loading,
+        }
+    }
+}
+
+impl Material for ParticleMaterial {
+    fn vertex_shader() -> ShaderRef {
+        MATERIAL_SHADER_ASSET_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        MATERIAL_SHADER_ASSET_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Add
+    }
+}
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            MaterialPlugin::<ParticleMaterial>::default(),
+            ExtractResourcePlugin::<ParticleBuffers>::default(),
+            ExtractResourcePlugin::<ParticleUniforms>::default(),
+            ExtractResourcePlugin::<AudiolinkDataTexture>::default(),
+        ))
+        .init_resource::<ParticleUniforms>()
+        .add_systems(
+            Startup,
+            setup
+                .after(crate::audiolink::setup)
+                .after(crate::palette::setup)
+                .before(crate::control::setup),
+        )
+        .add_systems(
+            Update,
+            update
+                .after(crate::audiolink::update)
+                .after(crate::analysis::update),
+        );
+
+        let particles_render_app = app.sub_app_mut(RenderApp);
+        particles_render_app
+            .insert_resource(self.config.clone())
+            .add_systems(RenderStartup, init_particle_pipeline)
+            .add_systems(
+                Render,
+                prepare_bind_group.in_set(RenderSystems::PrepareBindGroups),
+            );
+
+        let mut particles_render_graph = particles_render_app
+            .world_mut()
+            .resource_mut::<RenderGraph>();
+        particles_render_graph.add_node(ParticlesLabel, ParticleNode::default());
+        particles_render_graph.add_node_edge(AudiolinkLabel, ParticlesLabel);
+        particles_render_graph.add_node_edge(ParticlesLabel, CameraDriverLabel);
+    }
+}
+
+fn init_particle_pipeline(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    pipeline_cache: Res<PipelineCache>,
+    render_device: Res<RenderDevice>,
+) {
+    let bind_group_layout = render_device.create_bind_group_layout(
+        "ParticleBuffers",
+        &BindGroupLayoutEntries::sequential(
+            ShaderStages::COMPUTE,
+            (
+                storage_buffer_read_only_sized(false, None),
+                storage_buffer_sized(false, None),
+                texture_2d(TextureSampleType::Float { filterable: false }),
+                uniform_buffer::<ParticleUniforms>(false),
+            ),
+        ),
+    );
+
+    let shader = asset_server.load(SHADER_ASSET_PATH);
+
+    let shader_defs = vec![
+        ShaderDefVal::UInt("WORKGROUP_SIZE".into(), WORKGROUP_SIZE),
+        ShaderDefVal::UInt("DFT_BINS".into(), DFT_BINS),
+    ];
+
+    let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+        layout: vec![bind_group_layout.clone()],
+        shader: shader.clone(),
+        shader_defs: shader_defs.clone(),
+        entry_point: Some(Cow::from("init")),
+        ..default()
+    });
+
+    let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+        layout: vec![bind_group_layout.clone()],
+        shader,
+        shader_defs,
+        entry_point: Some(Cow::from("update")),
+        ..default()
+    });
+
+    commands.insert_resource(ParticlePipeline {
+        bind_group_layout,
+        init_pipeline,
+        update_pipeline,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn prepare_bind_group(
+    mut commands: Commands,
+    pipeline: Res<ParticlePipeline>,
+    buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    particle_buffers: Option<Res<ParticleBuffers>>,
+    particle_uniforms: Res<ParticleUniforms>,
+    audiolink_data_texture: Res<AudiolinkDataTexture>,
+    render_device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+) {
+    let Some(particle_buffers) = particle_buffers else {
+        return;
+    };
+    let (Some(previous), Some(current), Some(audiolink_view)) = (
+        buffers.get(particle_buffers.previous()),
+        buffers.get(&particle_buffers.current),
+        gpu_images.get(&audiolink_data_texture.0),
+    ) else {
+        return;
+    };
+
+    let mut uniform_buffer = UniformBuffer::from(particle_uniforms.clone());
+
+    uniform_buffer.write_buffer(&render_device, &queue);
+
+    let bind_group = render_device.create_bind_group(
+        None,
+        &pipeline.bind_group_layout,
+        &BindGroupEntries::sequential((
+            previous.buffer.as_entire_buffer_binding(),
+            current.buffer.as_entire_buffer_binding(),
+            &audiolink_view.texture_view,
+            &uniform_buffer,
+        )),
+    );
+
+    commands.insert_resource(ParticleBindGroup(bind_group));
+}
+
+fn particle_mesh(count: u32) -> Mesh {
+    // Every particle is a quad whose corner and particle index the vertex shader reads from its
+    // position, placing it where the compute pass moved the particle
+    let mut positions = Vec::with_capacity(count as usize * 4);
+    let mut indices = Vec::with_capacity(count as usize * 6);
+
+    for particle in 0..count {
+        let index = particle as f32;
+        positions.extend([
+            [-1.0, -1.0, index],
+            [1.0, -1.0, index],
+            [1.0, 1.0, index],
+            [-1.0, 1.0, index],
+        ]);
+
+        let first = particle * 4;
+        indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+    mut materials: ResMut<Assets<ParticleMaterial>>,
+    mut parameters: ResMut<Parameters>,
+    palette_texture: Res<PaletteTexture>,
+    scenes: Res<Scenes>,
+    config: Res<Config>,
+) {
+    if scenes.index_of(SCENE_NAME).is_none() {
+        return;
+    }
+
+    parameters.register("particles.emission", 20000.0, 0.0, 100000.0, 1000.0);
+    parameters.register("particles.burst", 0.2, 0.0, 1.0, 0.05);
+    parameters.register("particles.speed", 0.6, 0.0, 4.0, 0.05);
+    parameters.register("particles.drag", 0.8, 0.0, 8.0, 0.1);
+    parameters.register("particles.gravity", 0.1, -2.0, 2.0, 0.05);
+    parameters.register("particles.lifetime", 2.5, 0.1, 10.0, 0.1);
+    parameters.register("particles.size", 0.006, 0.001, 0.05, 0.001);
+
+    let particles_config = &config.particles;
+
+    // Zeroed particles have no lifetime, so both buffers start out empty
+    let particle_buffer = || {
+        let mut buffer = ShaderStorageBuffer::with_size(
+            (particles_config.count as u64 * PARTICLE_SIZE) as usize,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        buffer.buffer_description.usage |= BufferUsages::COPY_DST;
+        buffer
+    };
+    let buffer_a = buffers.add(particle_buffer());
+    let buffer_b = buffers.add(particle_buffer());
+
+    let material_handle = materials.add(ParticleMaterial {
+        particles: buffer_a.clone(),
+        palette_texture: Some(palette_texture.0.clone()),
+    });
+
+    commands.spawn((
+        Mesh3d(meshes.add(particle_mesh(particles_config.count))),
+        MeshMaterial3d(material_handle.clone()),
+        Transform::from_translation(particles_config.translation)
+            .with_scale(Vec3::splat(particles_config.scale)),
+        NoFrustumCulling,
+        Particles { material_handle },
+        scenes.render_layers(SCENE_NAME),
+    ));
+
+    commands.insert_resource(ParticleBuffers {
+        buffer_a: buffer_a.clone(),
+        buffer_b,
+        current: buffer_a,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update(
+    particles: Option<Single<&Particles>>,
+    particle_buffers: Option<ResMut<ParticleBuffers>>,
+    mut particle_uniforms: ResMut<ParticleUniforms>,
+    mut materials: ResMut<Assets<ParticleMaterial>>,
+    parameters: Res<Parameters>,
+    analysis: Res<AudioAnalysis>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let (Some(particles), Some(mut particle_buffers)) = (particles, particle_buffers) else {
+        return;
+    };
+
+    particle_buffers.current = particle_buffers.previous().clone();
+
+    if let Some(material) = materials.get_mut(particles.material_handle.id()) {
+        material.particles = particle_buffers.current.clone();
+    }
+
+    let get = |name: &str| parameters.get(name).unwrap_or_default();
+    let delta_time = time.delta_secs();
+
+    // Bass energy sets how many particles are born per second, spread over the dead ones
+    let born = get("particles.emission") * analysis.bands[0] * delta_time;
+
+    *particle_uniforms = ParticleUniforms {
+        delta_time,
+        seed: time.elapsed_secs().fract() * 1000.0,
+        emission: (born / config.particles.count as f32).min(1.0),
+        burst: if analysis.beat {
+            get("particles.burst")
+        } else {
+            0.0
+        },
+        speed: get("particles.speed"),
+        drag: get("particles.drag"),
+        gravity: get("particles.gravity"),
+        lifetime: get("particles.lifetime"),
+        size: get("particles.size"),
+    };
+}
+
+impl render_graph::Node for ParticleNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<ParticlePipeline>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+
+        match self.state {
+            ParticleState::Loading => {
+                match pipeline_cache.get_compute_pipeline_state(pipeline.init_pipeline) {
+                    CachedPipelineState::Ok(_) => {
+                        self.state = ParticleState::Init;
+                    }
+                    CachedPipelineState::Err(PipelineCacheError::ShaderNotLoaded(_)) => {}
+                    CachedPipelineState::Err(err) => {
+                        panic!("Initializing assets/{SHADER_ASSET_PATH}:\n{err}")
+                    }
+                    _ => {}
+                }
+            }
+            ParticleState::Init => {
+                if let CachedPipelineState::Ok(_) =
+                    pipeline_cache.get_compute_pipeline_state(pipeline.update_pipeline)
+                {
+                    self.state = ParticleState::Update;
+                }
+            }
+            ParticleState::Update => {}
+        }
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let Some(bind_group) = world.get_resource::<ParticleBindGroup>() else {
+            return Ok(());
+        };
+        let config = world.resource::<ParticlesConfig>();
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<ParticlePipeline>();
+
+        let pipeline_id = match self.state {
+            ParticleState::Loading => return Ok(()),
+            ParticleState::Init => pipeline.init_pipeline,
+            ParticleState::Update => pipeline.update_pipeline,
+        };
+        let Some(compute_pipeline) = pipeline_cache.get_compute_pipeline(pipeline_id) else {
+            return Ok(());
+        };
+
+        let mut pass = render_context
+            .command_encoder()
+            .begin_compute_pass(&ComputePassDescriptor::default());
+
+        pass.set_bind_group(0, &bind_group.0, &[]);
+        pass.set_pipeline(compute_pipeline);
+        pass.dispatch_workgroups(config.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+
+        Ok(())
+    }
+}
@@ -15,12 +15,35 @@ use pipewire::{
     },
     stream::{Stream, StreamFlags},
 };
+use serde::{Deserialize, Serialize};
 use std::{
     mem,
     sync::mpsc,
     thread::{self, JoinHandle},
 };
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipewireConfig {
+    pub input: Option<String>,
+    pub stream_name: String,
+    pub media_category: String,
+    pub media_role: String,
+    pub channels: u32,
+}
+
+impl Default for PipewireConfig {
+    fn default() -> Self {
+        Self {
+            input: None,
+            stream_name: "audio-input".to_owned(),
+            media_category: "Capture".to_owned(),
+            media_role: "DSP".to_owned(),
+            channels: 2,
+        }
+    }
+}
+
 pub enum PipewireOutgoingMessage {
     Terminate,
 }
@@ -44,11 +67,13 @@ pub struct PipewireInput {
 }
 
 impl PipewireInput {
-    pub fn new() -> Result<PipewireInput, Box<dyn std::error::Error>> {
+    pub fn new(config: &PipewireConfig) -> Result<PipewireInput, Box<dyn std::error::Error>> {
         let (from_pipewire_tx, from_pipewire_rx) =
             std::sync::mpsc::channel::<PipewireIncomingMessage>();
         let (to_pipewire_tx, to_pipewire_rx) = pipewire::channel::channel();
 
+        let config = config.clone();
+
         let pipewire_thread = thread::spawn(move || {
             let mainloop = match MainLoop::new(None) {
                 Ok(mainloop) => mainloop,
@@ -72,22 +97,25 @@ impl PipewireInput {
                 }
             };
 
-            let audio_stream = match Stream::new(
-                &core,
-                "audio-input",
-                properties! {
-                    *keys::MEDIA_TYPE => "Audio",
-                    *keys::MEDIA_CATEGORY => "Capture",
-                    *keys::MEDIA_ROLE => "DSP",
-                    *keys::AUDIO_CHANNELS => "2",
-                },
-            ) {
-                Ok(core) => core,
-                Err(err) => {
-                    let _ = from_pipewire_tx.send(PipewireIncomingMessage::Error(Box::new(err)));
-                    return;
-                }
+            let mut audio_stream_properties = properties! {
+                *keys::MEDIA_TYPE => "Audio",
+                *keys::MEDIA_CATEGORY => config.media_category.as_str(),
+                *keys::MEDIA_ROLE => config.media_role.as_str(),
+                *keys::AUDIO_CHANNELS => config.channels.to_string(),
             };
+            if let Some(input) = &config.input {
+                audio_stream_properties.insert("target.object", input.as_str());
+            }
+
+            let audio_stream =
+                match Stream::new(&core, &config.stream_name, audio_stream_properties) {
+                    Ok(core) => core,
+                    Err(err) => {
+                        let _ =
+                            from_pipewire_tx.send(PipewireIncomingMessage::Error(Box::new(err)));
+                        return;
+                    }
+                };
 
             let audio_stream_data = AudioStreamData {
                 format: Default::default(),
@@ -0,0 +1,451 @@
+use bevy::{
+    camera::{RenderTarget, visibility::RenderLayers},
+    image::BevyDefault,
+    prelude::*,
+    render::render_resource::{AsBindGroup, Extent3d, ShaderType, TextureFormat},
+    shader::ShaderRef,
+    sprite_render::{Material2d, Material2dPlugin},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{AudioAnalysis, Signal},
+    config::Config,
+    history::PreviousFrame,
+    output::Canvas,
+    parameters::Parameters,
+};
+
+pub const SHADER_ASSET_PATH: &str = "post.wgsl";
+
+pub const POST_RENDER_LAYER_BASE: usize = 48;
+pub const POST_CAMERA_ORDER_BASE: isize = 1;
+
+pub struct PostPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PostEffect {
+    Feedback,
+    Bloom,
+    ChromaticAberration,
+    Kaleidoscope,
+    Mirror,
+    RgbSplit,
+    Pixelate,
+    Strobe,
+    Vignette,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostConfig {
+    pub chain: Vec<PostEffect>,
+    pub modulation: Vec<PostModulation>,
+}
+
+// Adds `depth * signal` to the effect's amount
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PostModulation {
+    pub effect: PostEffect,
+    pub signal: Signal,
+    pub depth: f32,
+}
+
+#[derive(Resource)]
+pub struct PostChain {
+    pub order: Vec<PostEffect>,
+    pub selected: usize,
+    pub images: [Handle<Image>; 2],
+}
+
+#[derive(Component)]
+pub struct PostPass {
+    pub index: usize,
+    pub material_handle: Handle<PostMaterial>,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct PostMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    input_texture: Handle<Image>,
+    #[texture(2)]
+    #[sampler(3)]
+    previous_frame: Handle<Image>,
+    #[uniform(4)]
+    settings: PostSettings,
+}
+
+#[derive(Clone, Copy, Debug, Default, ShaderType)]
+pub struct PostSettings {
+    resolution: Vec2,
+    effect: u32,
+    amount: f32,
+    parameter: f32,
+    time: f32,
+}
+
+impl PostEffect {
+    pub const ALL: [PostEffect; 9] = [
+        PostEffect::Feedback,
+        PostEffect::Bloom,
+        PostEffect::ChromaticAberration,
+        PostEffect::Kaleidoscope,
+        PostEffect::Mirror,
+        PostEffect::RgbSplit,
+        PostEffect::Pixelate,
+        PostEffect::Strobe,
+        PostEffect::Vignette,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostEffect::Feedback => "feedback",
+            PostEffect::Bloom => "bloom",
+            PostEffect::ChromaticAberration => "chromatic_aberration",
+            PostEffect::Kaleidoscope => "kaleidoscope",
+            PostEffect::Mirror => "mirror",
+            PostEffect::RgbSplit => "rgb_split",
+            PostEffect::Pixelate => "pixelate",
+            PostEffect::Strobe => "strobe",
+            PostEffect::Vignette => "vignette",
+        }
+    }
+
+    // Matches the EFFECT_* constants in post.wgsl, zero being a plain copy
+    fn shader_index(&self) -> u32 {
+        match self {
+            PostEffect::Feedback => 1,
+            PostEffect::Bloom => 2,
+            PostEffect::ChromaticAberration => 3,
+            PostEffect::Kaleidoscope => 4,
+            PostEffect::Mirror => 5,
+            PostEffect::RgbSplit => 6,
+            PostEffect::Pixelate => 7,
+            PostEffect::Strobe => 8,
+            PostEffect::Vignette => 9,
+        }
+    }
+
+    // Every effect has an amount, where zero leaves the frame untouched, and one secondary parameter
+    fn parameters(&self) -> [(&'static str, f32, f32, f32, f32); 2] {
+        match self {
+            PostEffect::Feedback => [
+                ("amount", 0.0, 0.0, 0.99, 0.01),
+                ("zoom", 1.01, 0.9, 1.1, 0.005),
+            ],
+            PostEffect::Bloom => [
+                ("amount", 0.0, 0.0, 4.0, 0.1),
+                ("threshold", 0.7, 0.0, 1.0, 0.05),
+            ],
+            PostEffect::ChromaticAberration => [
+                ("amount", 0.0, 0.0, 0.1, 0.005),
+                ("falloff", 1.0, 0.0, 4.0, 0.1),
+            ],
+            PostEffect::Kaleidoscope => [
+                ("amount", 0.0, 0.0, 1.0, 0.05),
+                ("segments", 6.0, 2.0, 24.0, 1.0),
+            ],
+            PostEffect::Mirror => [
+                ("amount", 0.0, 0.0, 1.0, 0.05),
+                ("axis", 0.0, 0.0, 2.0, 1.0),
+            ],
+            PostEffect::RgbSplit => [
+                ("amount", 0.0, 0.0, 0.1, 0.005),
+                ("angle", 0.0, 0.0, 360.0, 15.0),
+            ],
+            PostEffect::Pixelate => [
+                ("amount", 0.0, 0.0, 1.0, 0.05),
+                ("cell_size", 64.0, 2.0, 256.0, 2.0),
+            ],
+            PostEffect::Strobe => [
+                ("amount", 0.0, 0.0, 1.0, 0.05),
+                ("rate", 0.0, 0.0, 30.0, 0.5),
+            ],
+            PostEffect::Vignette => [
+                ("amount", 0.0, 0.0, 1.0, 0.05),
+                ("softness", 0.5, 0.05, 1.0, 0.05),
+            ],
+        }
+    }
+
+    fn parameter_name(&self, index: usize) -> String {
+        format!("post.{}.{}", self.name(), self.parameters()[index].0)
+    }
+}
+
+impl Default for PostConfig {
+    fn default() -> Self {
+        Self {
+            chain: PostEffect::ALL.to_vec(),
+            modulation: Vec::new(),
+        }
+    }
+}
+
+impl PostChain {
+    pub fn move_selected(&mut self, offset: isize) {
+        let count = self.order.len();
+        if count < 2 {
+            return;
+        }
+
+        let target = (self.selected as isize + offset).rem_euclid(count as isize) as usize;
+        self.order.swap(self.selected, target);
+        self.selected = target;
+    }
+}
+
+impl Material2d for PostMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SHADER_ASSET_PATH.into()
+    }
+}
+
+impl Plugin for PostPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<PostMaterial>::default())
+            .add_systems(Startup, setup.after(crate::history::setup))
+            .add_systems(
+                Update,
+                (
+                    control_post_chain,
+                    resize_post_images.after(crate::output::resize_outputs),
+                    update_post_passes
+                        .after(control_post_chain)
+                        .after(resize_post_images)
+                        .after(crate::history::update)
+                        .after(crate::analysis::update),
+                ),
+            );
+    }
+}
+
+fn target_image(size: UVec2) -> Image {
+    Image::new_target_texture(size.x, size.y, TextureFormat::bevy_default())
+}
+
+fn spawn_pass(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    order: isize,
+    target: Handle<Image>,
+    material_handle: Handle<PostMaterial>,
+    size: UVec2,
+) -> Entity {
+    let render_layers = RenderLayers::layer(POST_RENDER_LAYER_BASE + order as usize);
+
+    commands.spawn((
+        Camera2d,
+        Camera {
+            order,
+            target: RenderTarget::Image(target.into()),
+            ..default()
+        },
+        Msaa::Off,
+        render_layers.clone(),
+    ));
+
+    commands
+        .spawn((
+            Mesh2d(meshes.add(Rectangle::new(1.0, 1.0))),
+            MeshMaterial2d(material_handle),
+            Transform::from_scale(size.as_vec2().extend(1.0)),
+            render_layers,
+        ))
+        .id()
+}
+
+// The program camera renders into the canvas' scene image and each effect of the chain is a fullscreen
+// pass ping-ponging between two images, the last one writing the canvas itself
+pub fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<PostMaterial>>,
+    mut parameters: ResMut<Parameters>,
+    previous_frame: Res<PreviousFrame>,
+    canvas: Res<Canvas>,
+    config: Res<Config>,
+) {
+    for effect in PostEffect::ALL {
+        for (index, (_, default, min, max, step)) in effect.parameters().into_iter().enumerate() {
+            parameters.register(&effect.parameter_name(index), default, min, max, step);
+        }
+    }
+
+    let post_images = [
+        images.add(target_image(canvas.size)),
+        images.add(target_image(canvas.size)),
+    ];
+
+    let pass_count = config.post.chain.len().max(1);
+    for index in 0..pass_count {
+        let input_texture = if index == 0 {
+            canvas.scene.clone()
+        } else {
+            post_images[(index - 1) % 2].clone()
+        };
+        let target = if index + 1 == pass_count {
+            canvas.image.clone()
+        } else {
+            post_images[index % 2].clone()
+        };
+
+        let material_handle = materials.add(PostMaterial {
+            input_texture,
+            previous_frame: previous_frame.0.clone(),
+            settings: PostSettings::default(),
+        });
+
+        let entity = spawn_pass(
+            &mut commands,
+            &mut meshes,
+            POST_CAMERA_ORDER_BASE + index as isize,
+            target,
+            material_handle.clone(),
+            canvas.size,
+        );
+        commands.entity(entity).insert(PostPass {
+            index,
+            material_handle,
+        });
+    }
+
+    commands.insert_resource(PostChain {
+        order: config.post.chain.clone(),
+        selected: 0,
+        images: post_images,
+    });
+}
+
+pub fn control_post_chain(
+    mut post_chain: ResMut<PostChain>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    let keys = &config.keys;
+    let count = post_chain.order.len();
+    if count == 0 {
+        return;
+    }
+
+    if keyboard.just_pressed(keys.post_select) {
+        post_chain.selected = (post_chain.selected + 1) % count;
+        info!(
+            "Selected post effect {}",
+            post_chain.order[post_chain.selected].name()
+        );
+    }
+
+    let offset = if keyboard.just_pressed(keys.post_move_earlier) {
+        -1
+    } else if keyboard.just_pressed(keys.post_move_later) {
+        1
+    } else {
+        return;
+    };
+
+    post_chain.move_selected(offset);
+    info!(
+        "Post chain: {}",
+        post_chain
+            .order
+            .iter()
+            .map(PostEffect::name)
+            .collect::<Vec<_>>()
+            .join(" > ")
+    );
+}
+
+pub fn resize_post_images(
+    canvas: Res<Canvas>,
+    post_chain: Res<PostChain>,
+    mut images: ResMut<Assets<Image>>,
+    mut passes: Query<&mut Transform, With<MeshMaterial2d<PostMaterial>>>,
+) {
+    if !canvas.is_changed() || canvas.is_added() {
+        return;
+    }
+
+    let size = Extent3d {
+        width: canvas.size.x,
+        height: canvas.size.y,
+        depth_or_array_layers: 1,
+    };
+
+    for handle in post_chain.images.iter() {
+        if let Some(image) = images.get_mut(handle.id()) {
+            image.resize(size);
+        }
+    }
+
+    // The materials are touched every frame by update_post_passes, which rebuilds their bind groups
+    for mut transform in passes.iter_mut() {
+        transform.scale = canvas.size.as_vec2().extend(1.0);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_post_passes(
+    passes: Query<&PostPass>,
+    post_chain: Res<PostChain>,
+    previous_frame: Res<PreviousFrame>,
+    mut materials: ResMut<Assets<PostMaterial>>,
+    parameters: Res<Parameters>,
+    analysis: Res<AudioAnalysis>,
+    canvas: Res<Canvas>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let resolution = canvas.size.as_vec2();
+    let elapsed = time.elapsed_secs();
+
+    for pass in passes.iter() {
+        let Some(material) = materials.get_mut(pass.material_handle.id()) else {
+            continue;
+        };
+
+        let settings = match post_chain.order.get(pass.index) {
+            Some(effect) => {
+                let amount_name = effect.parameter_name(0);
+                let modulation: f32 = config
+                    .post
+                    .modulation
+                    .iter()
+                    .filter(|modulation| modulation.effect == *effect)
+                    .map(|modulation| modulation.depth * analysis.get(modulation.signal))
+                    .sum();
+
+                let amount = parameters.get(&amount_name).unwrap_or_default() + modulation;
+                let amount = match parameters.parameter(&amount_name) {
+                    Some(parameter) => amount.clamp(parameter.min, parameter.max),
+                    None => amount,
+                };
+
+                PostSettings {
+                    resolution,
+                    effect: if amount > 0.0 {
+                        effect.shader_index()
+                    } else {
+                        0
+                    },
+                    amount,
+                    parameter: parameters
+                        .get(&effect.parameter_name(1))
+                        .unwrap_or_default(),
+                    time: elapsed,
+                }
+            }
+            None => PostSettings {
+                resolution,
+                time: elapsed,
+                ..default()
+            },
+        };
+
+        material.settings = settings;
+        material.previous_frame = previous_frame.0.clone();
+    }
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ASSET_DIRECTORY, Config, ConfigError},
+    modulation::{Modulation, ModulationRoute},
+    palette::Palettes,
+    parameters::Parameters,
+    post::{PostChain, PostEffect},
+    remote::RemoteCommand,
+    scene::Scenes,
+    tempo::BeatGrid,
+};
+
+pub const PRESET_EXTENSION: &str = "ron";
+
+pub struct PresetPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresetConfig {
+    // Where presets are saved as <name>.ron, relative to the assets unless absolute
+    pub directory: String,
+    // Beats that keys, MIDI and /preset/morph without a length take to morph, zero being instant
+    pub morph_beats: f32,
+    // Presets recalled by keys.preset_recall, or saved while keys.preset_save is held
+    pub slots: Vec<String>,
+}
+
+// Everything but the parameters is optional so that hand-written presets can leave them alone,
+// while saved presets always hold all of it. Maps are sorted, so saving the same state twice
+// writes the same file
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub parameters: BTreeMap<String, f32>,
+    pub modulation: Option<Vec<ModulationRoute>>,
+    pub post_chain: Option<Vec<PostEffect>>,
+    pub palette: Option<String>,
+    // The scene on program, cut to when recalled
+    pub scene: Option<String>,
+}
+
+// Parameters move in a straight line from where they were to the preset over a number of beats,
+// counted on the free-running beat position so the morph neither stalls nor runs backwards
+#[derive(Clone, Debug)]
+pub struct PresetMorph {
+    pub from: BTreeMap<String, f32>,
+    pub to: BTreeMap<String, f32>,
+    pub elapsed: f32,
+    pub last_position: f64,
+    pub beats: f32,
+}
+
+#[derive(Resource)]
+pub struct Presets {
+    pub presets: BTreeMap<String, Preset>,
+    pub directory: PathBuf,
+    pub morph: Option<PresetMorph>,
+}
+
+impl Default for PresetConfig {
+    fn default() -> Self {
+        Self {
+            directory: "presets".to_owned(),
+            morph_beats: 4.0,
+            slots: Vec::new(),
+        }
+    }
+}
+
+// Names become file names, so they can't leave the directory
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+pub fn load_presets(config: &PresetConfig) -> Result<Presets, ConfigError> {
+    let directory = Path::new(ASSET_DIRECTORY).join(&config.directory);
+    let mut presets = BTreeMap::new();
+
+    let entries = match fs::read_dir(&directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Presets::new(presets, directory));
+        }
+        Err(err) => return Err(ConfigError::Io(directory, err)),
+    };
+
+    for entry in entries {
+        let path = entry
+            .map_err(|err| ConfigError::Io(directory.clone(), err))?
+            .path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(PRESET_EXTENSION) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+
+        let contents =
+            fs::read_to_string(&path).map_err(|err| ConfigError::Io(path.clone(), err))?;
+        let preset: Preset =
+            ron::from_str(&contents).map_err(|err| ConfigError::Parse(path.clone(), err))?;
+        for (index, route) in preset.modulation.iter().flatten().enumerate() {
+            route
+                .validate()
+                .map_err(|(field, message)| ConfigError::Invalid {
+                    key: format!("{}: modulation[{index}].{field}", path.display()),
+                    message: message.to_owned(),
+                })?;
+        }
+
+        presets.insert(name.to_owned(), preset);
+    }
+
+    Ok(Presets::new(presets, directory))
+}
+
+impl Presets {
+    pub fn new(presets: BTreeMap<String, Preset>, directory: PathBuf) -> Presets {
+        Presets {
+            presets,
+            directory,
+            morph: None,
+        }
+    }
+
+    pub fn save(&mut self, name: &str, preset: Preset) {
+        if !is_valid_name(name) {
+            warn!("Not saving preset {name:?}: not a valid file name");
+            return;
+        }
+
+        let path = self.directory.join(format!("{name}.{PRESET_EXTENSION}"));
+        let contents = match ron::ser::to_string_pretty(&preset, ron::ser::PrettyConfig::default())
+        {
+            Ok(contents) => contents,
+            Err(err) => {
+                warn!("Could not serialize preset {name}: {err}");
+                return;
+            }
+        };
+
+        if let Err(err) =
+            fs::create_dir_all(&self.directory).and_then(|_| fs::write(&path, contents + "\n"))
+        {
+            warn!("Could not write {}: {err}", path.display());
+            return;
+        }
+
+        info!("Saved preset {name}");
+        self.presets.insert(name.to_owned(), preset);
+    }
+}
+
+impl Plugin for PresetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                control_presets,
+                apply_remote_commands
+                    .after(control_presets)
+                    .after(crate::remote::receive_commands)
+                    .after(crate::midi::update),
+                update
+                    .after(apply_remote_commands)
+                    .after(crate::analysis::update)
+                    .before(crate::modulation::update),
+            ),
+        );
+    }
+}
+
+// Whether both chains hold the same effects the same number of times, in any order
+fn same_effects(order: &[PostEffect], other: &[PostEffect]) -> bool {
+    let mut order = order.to_vec();
+    let mut other = other.to_vec();
+    order.sort();
+    other.sort();
+
+    order == other
+}
+
+// Presets go through the same commands as OSC and HTTP, so keys and remotes behave the same
+pub fn control_presets(
+    mut remote_commands: MessageWriter<RemoteCommand>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    let saving = keyboard.pressed(config.keys.preset_save);
+
+    for (key, name) in config.keys.preset_recall.iter().zip(&config.preset.slots) {
+        if !keyboard.just_pressed(*key) {
+            continue;
+        }
+
+        remote_commands.write(if saving {
+            RemoteCommand::SavePreset(name.clone())
+        } else {
+            RemoteCommand::RecallPreset(name.clone(), None)
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn apply_remote_commands(
+    mut remote_commands: MessageReader<RemoteCommand>,
+    mut presets: ResMut<Presets>,
+    mut parameters: ResMut<Parameters>,
+    mut modulation: ResMut<Modulation>,
+    mut post_chain: ResMut<PostChain>,
+    mut palettes: ResMut<Palettes>,
+    mut scenes: ResMut<Scenes>,
+    beat_grid: Res<BeatGrid>,
+    config: Res<Config>,
+) {
+    for command in remote_commands.read() {
+        match command {
+            RemoteCommand::SavePreset(name) => {
+                let preset = Preset {
+                    parameters: parameters
+                        .iter()
+                        .map(|(name, parameter)| (name.clone(), parameter.value))
+                        .collect(),
+                    modulation: Some(modulation.routes.clone()),
+                    post_chain: Some(post_chain.order.clone()),
+                    palette: Some(palettes.current.clone()),
+                    scene: scenes.names.get(scenes.program).cloned(),
+                };
+                presets.save(name, preset);
+            }
+            RemoteCommand::RecallPreset(name, beats) => {
+                let Some(preset) = presets.presets.get(name).cloned() else {
+                    warn!("Unknown preset {name}");
+                    continue;
+                };
+
+                if let Some(routes) = &preset.modulation {
+                    modulation.clear();
+                    for route in routes {
+                        modulation.add(route.clone());
+                    }
+                }
+
+                // The passes are built at startup, so only a reordering of the same effects applies
+                if let Some(order) = &preset.post_chain {
+                    if same_effects(order, &post_chain.order) {
+                        post_chain.order = order.clone();
+                    } else {
+                        warn!(
+                            "Preset {name} has a different set of post effects, keeping the chain"
+                        );
+                    }
+                }
+
+                if let Some(palette) = &preset.palette {
+                    if palettes.palettes.contains_key(palette) {
+                        palettes.show(palette);
+                    } else {
+                        warn!("Preset {name} uses unknown palette {palette}");
+                    }
+                }
+
+                if let Some(scene) = &preset.scene {
+                    match scenes.index_of(scene) {
+                        Some(index) => scenes.show(index),
+                        None => warn!("Preset {name} uses unknown scene {scene}"),
+                    }
+                }
+
+                let mut from = BTreeMap::new();
+                for parameter in preset.parameters.keys() {
+                    match parameters.parameter(parameter) {
+                        Some(current) => {
+                            from.insert(parameter.clone(), current.value);
+                        }
+                        None => warn!("Preset {name} sets unknown parameter {parameter}"),
+                    }
+                }
+
+                let beats = beats.unwrap_or(config.preset.morph_beats);
+                if beats > 0.0 {
+                    presets.morph = Some(PresetMorph {
+                        from,
+                        to: preset.parameters,
+                        elapsed: 0.0,
+                        last_position: beat_grid.free_position(),
+                        beats,
+                    });
+                } else {
+                    presets.morph = None;
+                    for (parameter, value) in &preset.parameters {
+                        parameters.set(parameter, *value);
+                    }
+                }
+                info!("Recalled preset {name}");
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn update(
+    mut presets: ResMut<Presets>,
+    mut parameters: ResMut<Parameters>,
+    beat_grid: Res<BeatGrid>,
+) {
+    let Some(morph) = &mut presets.morph else {
+        return;
+    };
+
+    let position = beat_grid.free_position();
+    morph.elapsed += (position - morph.last_position).max(0.0) as f32;
+    morph.last_position = position;
+
+    let progress = (morph.elapsed / morph.beats).clamp(0.0, 1.0);
+    for (name, from) in &morph.from {
+        if let Some(to) = morph.to.get(name) {
+            parameters.set(name, from + (to - from) * progress);
+        }
+    }
+
+    if progress >= 1.0 {
+        presets.morph = None;
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::{Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use rosc::{OscPacket, OscType};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    midi::MidiAction,
+    modulation::ModulationRoute,
+    tempo::TempoCommand,
+    text::TextSlot,
+    web::{self, REMOTE_PAGE, RemoteStatus},
+};
+
+pub const OSC_BUFFER_SIZE: usize = 65536;
+pub const HTTP_MAX_BODY: usize = 1 << 20;
+pub const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct RemotePlugin;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteConfig {
+    // Addresses to listen on, either one disabled with None. Both only accept local clients by
+    // default, as anyone who can reach them drives the show. HTTP also serves the control surface
+    // and its API, so listen on 0.0.0.0 to reach it from a phone
+    pub osc: Option<String>,
+    pub http: Option<String>,
+}
+
+// The same addresses are used over OSC, with a single string argument, HTTP, as the path of a POST
+// or PUT whose body is the argument, and the WebSocket. Under /api the body is JSON instead
+#[derive(Message, Clone, Debug)]
+pub enum RemoteCommand {
+    // /text/title, /text/ticker and /text/lyrics, the latter taking LRC contents
+    SetText(TextSlot, String),
+    // /text/lyrics_file, an LRC path relative to the assets
+    LoadLyrics(String),
+    // /input/<name>, a number read by Input modulation sources
+    SetInput(String, f32),
+    // /modulation/add with a route in RON, /modulation/remove with its index and /modulation/clear
+    AddRoute(ModulationRoute),
+    RemoveRoute(usize),
+    ClearRoutes,
+    // /tempo/tap, /tempo/resync, /tempo/nudge, /tempo/bpm and /tempo/mode
+    Tempo(TempoCommand),
+    // /midi/learn with an action in RON, mapped to the next MIDI controller or note received
+    MidiLearn(MidiAction),
+    // /parameters/<name> with a value
+    SetParameter(String, f32),
+    // /scene/cue and /scene/show with a scene name, the latter taking it straight away, and
+    // /scene/take
+    CueScene(String),
+    ShowScene(String),
+    TakeScene,
+    // /preset/save with a name, /preset/recall with a name to recall it instantly and
+    // /preset/morph with a name and optionally a number of beats to morph over
+    SavePreset(String),
+    RecallPreset(String, Option<f32>),
+}
+
+#[derive(Resource)]
+pub struct RemoteReceiver(Mutex<mpsc::Receiver<RemoteCommand>>);
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            osc: Some("127.0.0.1:9000".to_owned()),
+            http: Some("127.0.0.1:8080".to_owned()),
+        }
+    }
+}
+
+impl RemoteCommand {
+    pub fn parse(address: &str, argument: &str) -> Option<RemoteCommand> {
+        let argument = argument.to_owned();
+
+        match address.trim_end_matches('/') {
+            "/text/lyrics_file" => Some(RemoteCommand::LoadLyrics(argument)),
+            "/modulation/add" => match ron::from_str(&argument) {
+                Ok(route) => Some(RemoteCommand::AddRoute(route)),
+                Err(err) => {
+                    warn!("Invalid modulation route: {err}");
+                    None
+                }
+            },
+            "/modulation/remove" => argument.trim().parse().ok().map(RemoteCommand::RemoveRoute),
+            "/modulation/clear" => Some(RemoteCommand::ClearRoutes),
+            "/midi/learn" => match ron::from_str(&argument) {
+                Ok(action) => Some(RemoteCommand::MidiLearn(action)),
+                Err(err) => {
+                    warn!("Invalid MIDI action: {err}");
+                    None
+                }
+            },
+            "/scene/cue" => Some(RemoteCommand::CueScene(argument)),
+            "/scene/show" => Some(RemoteCommand::ShowScene(argument)),
+            "/scene/take" => Some(RemoteCommand::TakeScene),
+            "/preset/save" => Some(RemoteCommand::SavePreset(argument.trim().to_owned())),
+            "/preset/recall" => Some(RemoteCommand::RecallPreset(
+                argument.trim().to_owned(),
+                Some(0.0),
+            )),
+            "/preset/morph" => {
+                let argument = argument.trim();
+                match argument
+                    .rsplit_once(' ')
+                    .and_then(|(name, beats)| Some((name, beats.parse::<f32>().ok()?)))
+                {
+                    Some((_, beats)) if !beats.is_finite() || beats < 0.0 => None,
+                    Some((name, beats)) => Some(RemoteCommand::RecallPreset(
+                        name.trim_end().to_owned(),
+                        Some(beats),
+                    )),
+                    None => Some(RemoteCommand::RecallPreset(argument.to_owned(), None)),
+                }
+            }
+            address if address.starts_with("/parameters/") => {
+                let name = address.strip_prefix("/parameters/")?;
+                let value = argument
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|value: &f32| value.is_finite())?;
+                Some(RemoteCommand::SetParameter(name.to_owned(), value))
+            }
+            address if address.starts_with("/tempo/") => {
+                let command = address.strip_prefix("/tempo/")?;
+                TempoCommand::parse(command, &argument).map(RemoteCommand::Tempo)
+            }
+            address if address.starts_with("/input/") => {
+                let name = address.strip_prefix("/input/")?;
+                let value = argument
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|value: &f32| value.is_finite())?;
+                Some(RemoteCommand::SetInput(name.to_owned(), value))
+            }
+            address => {
+                let slot = TextSlot::from_name(address.strip_prefix("/text/")?)?;
+                Some(RemoteCommand::SetText(slot, argument))
+            }
+        }
+    }
+}
+
+impl Plugin for RemotePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<RemoteCommand>()
+            .init_resource::<RemoteStatus>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, (receive_commands, web::update_status));
+    }
+}
+
+fn osc_argument(argument: &OscType) -> Option<String> {
+    match argument {
+        OscType::String(value) => Some(value.clone()),
+        OscType::Int(value) => Some(value.to_string()),
+        OscType::Float(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn handle_osc_packet(packet: OscPacket, sender: &mpsc::Sender<RemoteCommand>) {
+    match packet {
+        OscPacket::Message(message) => {
+            let argument = message
+                .args
+                .first()
+                .and_then(osc_argument)
+                .unwrap_or_default();
+
+            match RemoteCommand::parse(&message.addr, &argument) {
+                Some(command) => {
+                    let _ = sender.send(command);
+                }
+                None => debug!("Ignoring OSC message to {}", message.addr),
+            }
+        }
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_osc_packet(packet, sender);
+            }
+        }
+    }
+}
+
+fn listen_osc(socket: UdpSocket, sender: mpsc::Sender<RemoteCommand>) {
+    let mut buffer = vec![0; OSC_BUFFER_SIZE];
+
+    loop {
+        let length = match socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(err) => {
+                warn!("OSC socket closed: {err}");
+                return;
+            }
+        };
+
+        match rosc::decoder::decode_udp(&buffer[..length]) {
+            Ok((_, packet)) => handle_osc_packet(packet, &sender),
+            Err(err) => debug!("Invalid OSC packet: {err:?}"),
+        }
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    );
+}
+
+fn respond_with(stream: &mut TcpStream, content_type: &str, body: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+}
+
+// Just enough HTTP/1.1 for curl, fetch and WebSocket upgrades, one request per connection
+fn handle_http(mut stream: TcpStream, sender: &mpsc::Sender<RemoteCommand>, status: &RemoteStatus) {
+    let Ok(reader_stream) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(reader_stream);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        respond(&mut stream, "400 Bad Request");
+        return;
+    };
+
+    let mut content_length = 0;
+    let mut websocket_key = None;
+    let mut origin = None;
+    let mut host = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).is_err() {
+            return;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.trim().to_owned());
+            } else if name.eq_ignore_ascii_case("origin") {
+                origin = Some(value.trim().to_owned());
+            } else if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    // Browsers send an Origin with every WebSocket upgrade and cross-site POST, so other pages open
+    // on this machine can't drive the show. Clients without one, such as curl, are let through
+    let same_origin = web::is_same_origin(origin.as_deref(), host.as_deref());
+
+    if method == "GET" {
+        match (path, websocket_key) {
+            ("/api/ws", Some(_)) if !same_origin => respond(&mut stream, "403 Forbidden"),
+            ("/", _) => respond_with(&mut stream, "text/html; charset=utf-8", REMOTE_PAGE),
+            ("/api/ws", Some(key)) => {
+                web::accept_websocket(stream, &key, sender.clone(), status.clone())
+            }
+            (path, _) => match status.json(path) {
+                Some(json) => respond_with(&mut stream, "application/json", &json),
+                None => respond(&mut stream, "404 Not Found"),
+            },
+        }
+        return;
+    }
+
+    if content_length > HTTP_MAX_BODY {
+        respond(&mut stream, "413 Payload Too Large");
+        return;
+    }
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).is_err() {
+        respond(&mut stream, "400 Bad Request");
+        return;
+    }
+
+    if method != "POST" && method != "PUT" {
+        respond(&mut stream, "405 Method Not Allowed");
+        return;
+    }
+    if !same_origin {
+        respond(&mut stream, "403 Forbidden");
+        return;
+    }
+
+    let body = String::from_utf8_lossy(&body);
+    let command = match path.strip_prefix("/api") {
+        Some(path) => RemoteCommand::parse(path, &web::json_argument(body.trim_end())),
+        None => RemoteCommand::parse(path, body.trim_end()),
+    };
+
+    match command {
+        Some(command) => {
+            let _ = sender.send(command);
+            respond(&mut stream, "204 No Content");
+        }
+        None => respond(&mut stream, "404 Not Found"),
+    }
+}
+
+// Each connection gets a thread of its own, and a client that stops sending times out
+fn listen_http(listener: TcpListener, sender: mpsc::Sender<RemoteCommand>, status: RemoteStatus) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let _ = stream.set_read_timeout(Some(HTTP_TIMEOUT));
+                let _ = stream.set_write_timeout(Some(HTTP_TIMEOUT));
+
+                let sender = sender.clone();
+                let status = status.clone();
+                thread::spawn(move || handle_http(stream, &sender, &status));
+            }
+            Err(err) => debug!("HTTP connection failed: {err}"),
+        }
+    }
+}
+
+pub fn setup(mut commands: Commands, status: Res<RemoteStatus>, config: Res<Config>) {
+    let (sender, receiver) = mpsc::channel();
+
+    if let Some(address) = &config.remote.osc {
+        match UdpSocket::bind(address) {
+            Ok(socket) => {
+                info!("Listening for OSC on {address}");
+                let sender = sender.clone();
+                thread::spawn(move || listen_osc(socket, sender));
+            }
+            Err(err) => warn!("Could not listen for OSC on {address}: {err}"),
+        }
+    }
+
+    if let Some(address) = &config.remote.http {
+        match TcpListener::bind(address) {
+            Ok(listener) => {
+                info!("Listening for HTTP on {address}");
+                let sender = sender.clone();
+                let status = status.clone();
+                thread::spawn(move || listen_http(listener, sender, status));
+            }
+            Err(err) => warn!("Could not listen for HTTP on {address}: {err}"),
+        }
+    }
+
+    commands.insert_resource(RemoteReceiver(Mutex::new(receiver)));
+}
+
+pub fn receive_commands(
+    mut receiver: ResMut<RemoteReceiver>,
+    mut remote_commands: MessageWriter<RemoteCommand>,
+) {
+    let Ok(receiver) = receiver.0.get_mut() else {
+        return;
+    };
+
+    remote_commands.write_batch(receiver.try_iter());
+}
@@ -0,0 +1,179 @@
+use bevy::{
+    camera::{RenderTarget, visibility::RenderLayers},
+    image::BevyDefault,
+    prelude::*,
+    render::render_resource::TextureFormat,
+};
+
+use crate::{config::Config, output::Canvas, remote::RemoteCommand};
+
+pub const OVERLAY_RENDER_LAYER: usize = 0;
+pub const SCENE_RENDER_LAYER_BASE: usize = 1;
+
+pub const PREVIEW_DOWNSCALE: u32 = 2;
+
+pub const AVAILABLE_SCENES: [&str; 7] = [
+    "visualizer",
+    "particles",
+    "bars",
+    "terrain",
+    "tunnel",
+    "models",
+    "blank",
+];
+
+pub struct ScenePlugin;
+
+#[derive(Component)]
+pub struct PreviewCamera;
+
+#[derive(Resource)]
+pub struct PreviewCanvas {
+    pub image: Handle<Image>,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct Scenes {
+    pub names: Vec<String>,
+    pub program: usize,
+    pub preview: usize,
+}
+
+impl Scenes {
+    pub fn new(names: &[String]) -> Scenes {
+        Scenes {
+            names: names.to_vec(),
+            program: 0,
+            preview: 1 % names.len().max(1),
+        }
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|scene_name| scene_name == name)
+    }
+
+    pub fn render_layers(&self, name: &str) -> RenderLayers {
+        match self.index_of(name) {
+            Some(index) => RenderLayers::layer(SCENE_RENDER_LAYER_BASE + index),
+            None => {
+                warn!("Scene {name} is not registered and will never be shown");
+                RenderLayers::none()
+            }
+        }
+    }
+
+    pub fn camera_render_layers(index: usize) -> RenderLayers {
+        RenderLayers::from_layers(&[OVERLAY_RENDER_LAYER, SCENE_RENDER_LAYER_BASE + index])
+    }
+
+    pub fn cue(&mut self, index: usize) {
+        if index < self.names.len() {
+            self.preview = index;
+        }
+    }
+
+    pub fn take(&mut self) {
+        std::mem::swap(&mut self.program, &mut self.preview);
+    }
+
+    // Cuts straight to a scene, leaving whatever is cued alone if it is already on program
+    pub fn show(&mut self, index: usize) {
+        if index < self.names.len() && index != self.program {
+            self.cue(index);
+            self.take();
+        }
+    }
+}
+
+impl Plugin for ScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup.after(crate::output::setup))
+            .add_systems(
+                Update,
+                (
+                    control_scenes,
+                    apply_remote_commands.after(crate::remote::receive_commands),
+                    apply_scenes
+                        .after(control_scenes)
+                        .after(apply_remote_commands),
+                ),
+            );
+    }
+}
+
+pub fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>, canvas: Res<Canvas>) {
+    let size = (canvas.size / PREVIEW_DOWNSCALE).max(UVec2::ONE);
+
+    let preview_image = images.add(Image::new_target_texture(
+        size.x,
+        size.y,
+        TextureFormat::bevy_default(),
+    ));
+
+    commands.spawn((
+        Camera3d::default(),
+        Camera {
+            target: RenderTarget::Image(preview_image.clone().into()),
+            ..default()
+        },
+        PreviewCamera,
+    ));
+
+    commands.insert_resource(PreviewCanvas {
+        image: preview_image,
+    });
+}
+
+pub fn control_scenes(
+    mut scenes: ResMut<Scenes>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    config: Res<Config>,
+) {
+    for (index, key) in config.keys.cue.iter().enumerate() {
+        if keyboard.just_pressed(*key) {
+            scenes.cue(index);
+        }
+    }
+
+    if keyboard.just_pressed(config.keys.take) {
+        scenes.take();
+    }
+}
+
+pub fn apply_remote_commands(
+    mut remote_commands: MessageReader<RemoteCommand>,
+    mut scenes: ResMut<Scenes>,
+) {
+    for command in remote_commands.read() {
+        match command {
+            RemoteCommand::CueScene(name) | RemoteCommand::ShowScene(name) => {
+                let Some(index) = scenes.index_of(name) else {
+                    warn!("Remote cue for unknown scene {name}");
+                    continue;
+                };
+
+                if matches!(command, RemoteCommand::ShowScene(_)) {
+                    scenes.show(index);
+                } else {
+                    scenes.cue(index);
+                }
+            }
+            RemoteCommand::TakeScene => scenes.take(),
+            _ => {}
+        }
+    }
+}
+
+pub fn apply_scenes(
+    mut commands: Commands,
+    scenes: Res<Scenes>,
+    preview_cameras: Query<(Entity, Ref<PreviewCamera>)>,
+) {
+    for (entity, preview_camera) in preview_cameras.iter() {
+        if scenes.is_changed() || preview_camera.is_added() {
+            commands
+                .entity(entity)
+                .insert(Scenes::camera_render_layers(scenes.preview));
+        }
+    }
+}
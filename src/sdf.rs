@@ -0,0 +1,262 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    color::Alpha,
+    image::Image,
+    math::{Vec2, Vec3},
+    mesh::{Indices, Mesh, VertexAttributeValues},
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+// Squared distances are kept finite so the parabola intersections never produce NaN
+const FAR: f32 = 1e20;
+
+pub struct Mask {
+    pub width: usize,
+    pub height: usize,
+    pub coverage: Vec<f32>,
+}
+
+impl Mask {
+    pub fn new(width: usize, height: usize) -> Mask {
+        Mask {
+            width,
+            height,
+            coverage: vec![0.0; width * height],
+        }
+    }
+
+    // Rasterizes a triangle mesh, mapping `bounds_min..bounds_max` onto the whole mask
+    pub fn from_mesh(
+        mesh: &Mesh,
+        bounds_min: Vec2,
+        bounds_max: Vec2,
+        width: usize,
+        height: usize,
+    ) -> Option<Mask> {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return None;
+        };
+
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices.iter().map(|index| *index as usize).collect(),
+            Some(Indices::U16(indices)) => indices.iter().map(|index| *index as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        let size = Vec2::new(width as f32, height as f32);
+        let to_pixel = |position: &[f32; 3]| {
+            let uv = (Vec2::new(position[0], position[1]) - bounds_min) / (bounds_max - bounds_min);
+            Vec2::new(uv.x, 1.0 - uv.y) * size
+        };
+
+        let mut mask = Mask::new(width, height);
+        for triangle in indices.chunks_exact(3) {
+            let [Some(a), Some(b), Some(c)] =
+                [0, 1, 2].map(|corner| positions.get(triangle[corner]))
+            else {
+                continue;
+            };
+
+            mask.fill_triangle(to_pixel(a), to_pixel(b), to_pixel(c));
+        }
+
+        Some(mask)
+    }
+
+    // Samples the alpha channel of an image into the inner `content` rectangle of the mask
+    pub fn from_image_alpha(
+        image: &Image,
+        content_min: Vec2,
+        content_max: Vec2,
+        width: usize,
+        height: usize,
+    ) -> Mask {
+        let mut mask = Mask::new(width, height);
+        let image_size = Vec2::new(image.width() as f32, image.height() as f32);
+
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let uv = (pixel - content_min) / (content_max - content_min);
+                if uv.cmplt(Vec2::ZERO).any() || uv.cmpge(Vec2::ONE).any() {
+                    continue;
+                }
+
+                let texel = (uv * image_size).as_uvec2();
+                if let Ok(color) = image.get_color_at(texel.x, texel.y) {
+                    mask.coverage[y * width + x] = color.alpha();
+                }
+            }
+        }
+
+        mask
+    }
+
+    fn fill_triangle(&mut self, a: Vec2, b: Vec2, c: Vec2) {
+        let area = (b - a).perp_dot(c - a);
+        if area.abs() <= f32::EPSILON {
+            return;
+        }
+
+        let min = a.min(b).min(c).floor().max(Vec2::ZERO);
+        let max = a
+            .max(b)
+            .max(c)
+            .ceil()
+            .min(Vec2::new(self.width as f32, self.height as f32));
+
+        for y in min.y as usize..max.y as usize {
+            for x in min.x as usize..max.x as usize {
+                let pixel = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+                // Edge functions share the triangle's sign when the pixel is inside, whatever the winding
+                let weights = Vec3::new(
+                    (c - b).perp_dot(pixel - b),
+                    (a - c).perp_dot(pixel - c),
+                    (b - a).perp_dot(pixel - a),
+                ) * area.signum();
+
+                if weights.cmpge(Vec3::ZERO).all() {
+                    self.coverage[y * self.width + x] = 1.0;
+                }
+            }
+        }
+    }
+
+    // Encodes the signed distance to the mask edge, in pixels, as 0.5 + distance / (2 * spread)
+    pub fn to_signed_distance_field(&self, spread: f32) -> Image {
+        let inside: Vec<bool> = self
+            .coverage
+            .iter()
+            .map(|coverage| *coverage >= 0.5)
+            .collect();
+
+        let distance_outside = distance_transform(
+            &inside
+                .iter()
+                .map(|inside| if *inside { 0.0 } else { FAR })
+                .collect::<Vec<_>>(),
+            self.width,
+            self.height,
+        );
+        let distance_inside = distance_transform(
+            &inside
+                .iter()
+                .map(|inside| if *inside { FAR } else { 0.0 })
+                .collect::<Vec<_>>(),
+            self.width,
+            self.height,
+        );
+
+        let mut data = Vec::with_capacity(self.width * self.height * 4);
+        for index in 0..self.width * self.height {
+            let distance = distance_outside[index].sqrt() - distance_inside[index].sqrt();
+            let encoded = (0.5 + distance / (2.0 * spread)).clamp(0.0, 1.0);
+
+            data.extend_from_slice(&[
+                (encoded * 255.0).round() as u8,
+                (self.coverage[index].clamp(0.0, 1.0) * 255.0).round() as u8,
+                0,
+                255,
+            ]);
+        }
+
+        Image::new(
+            Extent3d {
+                width: self.width as u32,
+                height: self.height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8Unorm,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+    }
+}
+
+// Exact squared euclidean distance transform (Felzenszwalb & Huttenlocher), separable over rows and columns
+fn distance_transform(grid: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut distances = grid.to_vec();
+
+    let length = width.max(height);
+    let mut line = vec![0.0; length];
+    let mut output = vec![0.0; length];
+    let mut vertices = vec![0; length];
+    let mut boundaries = vec![0.0; length + 1];
+
+    for x in 0..width {
+        for (y, value) in line[..height].iter_mut().enumerate() {
+            *value = distances[y * width + x];
+        }
+        distance_transform_line(
+            &line[..height],
+            &mut output[..height],
+            &mut vertices,
+            &mut boundaries,
+        );
+        for (y, value) in output[..height].iter().enumerate() {
+            distances[y * width + x] = *value;
+        }
+    }
+
+    for y in 0..height {
+        line[..width].copy_from_slice(&distances[y * width..(y + 1) * width]);
+        distance_transform_line(
+            &line[..width],
+            &mut output[..width],
+            &mut vertices,
+            &mut boundaries,
+        );
+        distances[y * width..(y + 1) * width].copy_from_slice(&output[..width]);
+    }
+
+    distances
+}
+
+fn distance_transform_line(
+    line: &[f32],
+    output: &mut [f32],
+    vertices: &mut [usize],
+    boundaries: &mut [f32],
+) {
+    if line.is_empty() {
+        return;
+    }
+
+    let intersection = |q: usize, p: usize| {
+        let (q_position, p_position) = (q as f32, p as f32);
+        ((line[q] + q_position * q_position) - (line[p] + p_position * p_position))
+            / (2.0 * (q_position - p_position))
+    };
+
+    let mut k = 0;
+    vertices[0] = 0;
+    boundaries[0] = f32::NEG_INFINITY;
+    boundaries[1] = f32::INFINITY;
+
+    for q in 1..line.len() {
+        let mut s = intersection(q, vertices[k]);
+        while s <= boundaries[k] {
+            k -= 1;
+            s = intersection(q, vertices[k]);
+        }
+
+        k += 1;
+        vertices[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, distance) in output.iter_mut().enumerate() {
+        while boundaries[k + 1] < q as f32 {
+            k += 1;
+        }
+
+        let offset = q as f32 - vertices[k] as f32;
+        *distance = offset * offset + line[vertices[k]];
+    }
+}
@@ -0,0 +1,296 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{AudioAnalysis, BPM_SMOOTHING, MAX_BPM, MIN_BPM},
+    audiolink::AudiolinkTempo,
+    config::Config,
+    remote::RemoteCommand,
+};
+
+// Taps further apart than this start a new measurement
+pub const TAP_TIMEOUT: f64 = 2.0;
+pub const TAP_HISTORY: usize = 8;
+
+pub struct TempoPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TempoMode {
+    // Beats and tempo come from onset detection
+    Auto,
+    // The grid runs on its own at the tapped or set tempo
+    Manual,
+    // The grid runs on its own, pulled towards detected onsets by `blend`
+    Blend,
+    // The grid follows the Ableton Link session, running as in Manual while there is none
+    Link,
+    // The grid follows incoming MIDI clock, running as in Manual while there is none
+    Midi,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TempoConfig {
+    pub mode: TempoMode,
+    // How much of the phase error and tempo difference each detected onset corrects in Blend mode
+    pub blend: f32,
+    // Beats moved by each press of the nudge keys
+    pub nudge: f32,
+}
+
+// Over OSC and HTTP as /tempo/tap, /tempo/resync, /tempo/nudge <beats>, /tempo/bpm <bpm> and
+// /tempo/mode <auto|manual|blend|link|midi>
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TempoCommand {
+    Tap,
+    Resync,
+    Nudge(f32),
+    SetBpm(f32),
+    SetMode(TempoMode),
+}
+
+// Sent on every beat of the grid, whichever mode drives it
+#[derive(Message, Clone, Copy, Debug)]
+pub struct Beat {
+    pub index: u64,
+    pub bpm: f32,
+}
+
+#[derive(Resource)]
+pub struct BeatGrid {
+    pub mode: TempoMode,
+    pub blend: f32,
+    pub bpm: f32,
+    // Beats since startup, the fraction being the phase within the current beat
+    pub position: f64,
+    // How far the tempo has carried on past `position` while Auto waits for an onset
+    pub overrun: f64,
+    pub last_beat: i64,
+    taps: Vec<f64>,
+}
+
+impl Default for TempoConfig {
+    fn default() -> Self {
+        Self {
+            mode: TempoMode::Auto,
+            blend: 0.3,
+            nudge: 0.02,
+        }
+    }
+}
+
+impl TempoMode {
+    pub fn from_name(name: &str) -> Option<TempoMode> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "auto" => Some(TempoMode::Auto),
+            "manual" => Some(TempoMode::Manual),
+            "blend" => Some(TempoMode::Blend),
+            "link" => Some(TempoMode::Link),
+            "midi" => Some(TempoMode::Midi),
+            _ => None,
+        }
+    }
+
+    fn next(&self) -> TempoMode {
+        match self {
+            TempoMode::Auto => TempoMode::Manual,
+            TempoMode::Manual => TempoMode::Blend,
+            TempoMode::Blend => TempoMode::Link,
+            TempoMode::Link => TempoMode::Midi,
+            TempoMode::Midi => TempoMode::Auto,
+        }
+    }
+}
+
+// Clamping keeps NaN, which would spread to the grid and Link peers
+fn parse_finite(argument: &str) -> Option<f32> {
+    argument
+        .trim()
+        .parse()
+        .ok()
+        .filter(|value: &f32| value.is_finite())
+}
+
+impl TempoCommand {
+    pub fn parse(command: &str, argument: &str) -> Option<TempoCommand> {
+        match command {
+            "tap" => Some(TempoCommand::Tap),
+            "resync" => Some(TempoCommand::Resync),
+            "nudge" => parse_finite(argument).map(TempoCommand::Nudge),
+            "bpm" => parse_finite(argument).map(TempoCommand::SetBpm),
+            "mode" => TempoMode::from_name(argument).map(TempoCommand::SetMode),
+            _ => None,
+        }
+    }
+}
+
+impl BeatGrid {
+    pub fn new(config: &TempoConfig, bpm: f32) -> BeatGrid {
+        BeatGrid {
+            mode: config.mode,
+            blend: config.blend,
+            bpm,
+            position: 0.0,
+            overrun: 0.0,
+            last_beat: 0,
+            taps: Vec::new(),
+        }
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.position.fract() as f32
+    }
+
+    // Keeps counting at the current tempo while Auto waits for an onset, so that whatever loops over
+    // beats carries on through breakdowns
+    pub fn free_position(&self) -> f64 {
+        self.position + self.overrun
+    }
+
+    // Moves the grid on by a frame, returning whether a new beat started
+    pub fn advance(&mut self, onset: bool, detected_bpm: f32, delta_time: f32) -> bool {
+        let step = (delta_time * self.bpm / 60.0) as f64;
+
+        match self.mode {
+            // Each onset is a beat, and the phase waits just short of the next one until it arrives
+            TempoMode::Auto => {
+                self.bpm = detected_bpm;
+                if onset {
+                    self.position = self.position.floor() + 1.0;
+                    // Whole beats of overrun stay so the free position only moves by its phase
+                    self.overrun = self.overrun.round();
+                } else {
+                    let waiting = (self.position + step).min(self.position.floor() + 0.999);
+                    self.overrun += self.position + step - waiting;
+                    self.position = waiting;
+                }
+            }
+            TempoMode::Manual | TempoMode::Link | TempoMode::Midi => self.position += step,
+            TempoMode::Blend => {
+                self.position += step;
+                if onset {
+                    let error = self.position - self.position.round();
+                    self.position -= error * self.blend as f64;
+                    self.bpm += (detected_bpm - self.bpm) * self.blend * BPM_SMOOTHING;
+                }
+            }
+        }
+
+        let beat = self.position.floor() as i64;
+        if beat > self.last_beat {
+            self.last_beat = beat;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn apply(&mut self, command: TempoCommand, now: f64) {
+        match command {
+            TempoCommand::Tap => self.tap(now),
+            // The next beat starts now
+            TempoCommand::Resync => self.position = self.position.floor() + 1.0,
+            TempoCommand::Nudge(beats) => self.position += beats as f64,
+            TempoCommand::SetBpm(bpm) => {
+                self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+                self.take_over();
+            }
+            TempoCommand::SetMode(mode) => self.mode = mode,
+        }
+    }
+
+    // Tapping or setting a tempo hands the grid over from detection, keeping Blend if it was chosen
+    fn take_over(&mut self) {
+        if self.mode == TempoMode::Auto {
+            self.mode = TempoMode::Manual;
+        }
+    }
+
+    fn tap(&mut self, now: f64) {
+        if self
+            .taps
+            .last()
+            .is_some_and(|last| now - last > TAP_TIMEOUT)
+        {
+            self.taps.clear();
+        }
+        self.taps.push(now);
+        if self.taps.len() > TAP_HISTORY {
+            self.taps.remove(0);
+        }
+
+        // Each tap lands on a beat, rounding to whichever is nearer
+        self.position = self.position.round();
+
+        if let (Some(first), Some(last)) = (self.taps.first(), self.taps.last())
+            && self.taps.len() >= 2
+        {
+            let interval = (last - first) / (self.taps.len() - 1) as f64;
+            self.bpm = ((60.0 / interval) as f32).clamp(MIN_BPM, MAX_BPM);
+            self.take_over();
+        }
+    }
+}
+
+impl Plugin for TempoPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<Beat>().add_systems(
+            Update,
+            (
+                control_tempo.before(crate::analysis::update),
+                export_tempo.after(crate::analysis::update),
+            ),
+        );
+    }
+}
+
+pub fn control_tempo(
+    mut beat_grid: ResMut<BeatGrid>,
+    mut remote_commands: MessageReader<RemoteCommand>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let keys = &config.keys;
+    let now = time.elapsed_secs_f64();
+
+    let mut commands: Vec<TempoCommand> = remote_commands
+        .read()
+        .filter_map(|command| match command {
+            RemoteCommand::Tempo(command) => Some(*command),
+            _ => None,
+        })
+        .collect();
+
+    if keyboard.just_pressed(keys.tempo_tap) {
+        commands.push(TempoCommand::Tap);
+    }
+    if keyboard.just_pressed(keys.tempo_resync) {
+        commands.push(TempoCommand::Resync);
+    }
+    if keyboard.just_pressed(keys.tempo_nudge_earlier) {
+        commands.push(TempoCommand::Nudge(config.tempo.nudge));
+    }
+    if keyboard.just_pressed(keys.tempo_nudge_later) {
+        commands.push(TempoCommand::Nudge(-config.tempo.nudge));
+    }
+    if keyboard.just_pressed(keys.tempo_mode) {
+        commands.push(TempoCommand::SetMode(beat_grid.mode.next()));
+    }
+
+    for command in commands {
+        beat_grid.apply(command, now);
+        if let TempoCommand::SetMode(mode) = command {
+            info!("Tempo mode {mode:?}");
+        }
+    }
+}
+
+pub fn export_tempo(mut audiolink_tempo: ResMut<AudiolinkTempo>, analysis: Res<AudioAnalysis>) {
+    *audiolink_tempo = AudiolinkTempo {
+        phase: analysis.beat_phase,
+        bpm: analysis.bpm,
+        beats: analysis.beats as f32,
+        pulse: analysis.beat_pulse,
+    };
+}
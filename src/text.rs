@@ -0,0 +1,522 @@
+use std::{fs, path::Path, time::SystemTime};
+
+use bevy::{camera::visibility::RenderLayers, prelude::*, sprite::Anchor, text::TextLayoutInfo};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{AudioAnalysis, Signal},
+    config::{self, ASSET_DIRECTORY, Config},
+    output::Canvas,
+    palette::Palettes,
+    remote::RemoteCommand,
+};
+
+pub const TEXT_RENDER_LAYER: usize = 46;
+
+pub const NOW_PLAYING_POLL_INTERVAL: f32 = 1.0;
+
+// Copies of each text drawn around it in a ring, faded in with the signal
+pub const GLOW_COPIES: usize = 8;
+pub const GLOW_RADIUS: f32 = 0.06;
+pub const GLOW_ALPHA: f32 = 0.3;
+
+pub struct TextPlugin;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextSlot {
+    Title,
+    Ticker,
+    Lyrics,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct TextConfig {
+    // A font asset, the built-in font when unset
+    pub font: Option<String>,
+    // Font height as a fraction of the canvas height
+    pub font_size: f32,
+    pub title: String,
+    // {title}, {artist} and {album} are replaced from the now playing metadata
+    pub title_format: String,
+    pub ticker: String,
+    // Canvas widths per second
+    pub ticker_speed: f32,
+    // An LRC file relative to the assets, timed from when it is loaded or the track changes
+    pub lyrics: Option<String>,
+    // A file of MPRIS metadata lines, such as "xesam:title Song", written by a local stand-in for a
+    // media player and polled for changes
+    pub now_playing: Option<String>,
+    // Positions as a fraction of the canvas, from the top left
+    pub title_position: Vec2,
+    pub ticker_position: f32,
+    pub lyrics_position: Vec2,
+    pub signal: Signal,
+    pub scale_depth: f32,
+    pub glow_depth: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Lyrics {
+    lines: Vec<(f32, String)>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    // Seconds into the track when the metadata was written
+    pub position: Option<f32>,
+}
+
+#[derive(Resource, Default)]
+pub struct TextContent {
+    pub title: String,
+    pub ticker: String,
+    pub lyrics: Option<Lyrics>,
+    pub now_playing: NowPlaying,
+    track_started: f32,
+    now_playing_modified: Option<SystemTime>,
+    since_poll: f32,
+    ticker_offset: f32,
+}
+
+#[derive(Component)]
+pub struct TextElement {
+    pub slot: TextSlot,
+}
+
+#[derive(Component)]
+pub struct TextGlow {
+    pub slot: TextSlot,
+    pub direction: Vec2,
+}
+
+impl Default for TextConfig {
+    fn default() -> Self {
+        Self {
+            font: None,
+            font_size: 0.06,
+            title: String::new(),
+            title_format: "{artist} - {title}".to_owned(),
+            ticker: String::new(),
+            ticker_speed: 0.1,
+            lyrics: None,
+            now_playing: None,
+            title_position: Vec2::new(0.05, 0.08),
+            ticker_position: 0.94,
+            lyrics_position: Vec2::new(0.5, 0.75),
+            signal: Signal::Bass,
+            scale_depth: 0.15,
+            glow_depth: 1.0,
+        }
+    }
+}
+
+impl TextSlot {
+    pub const ALL: [TextSlot; 3] = [TextSlot::Title, TextSlot::Ticker, TextSlot::Lyrics];
+
+    pub fn from_name(name: &str) -> Option<TextSlot> {
+        TextSlot::ALL.into_iter().find(|slot| slot.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TextSlot::Title => "title",
+            TextSlot::Ticker => "ticker",
+            TextSlot::Lyrics => "lyrics",
+        }
+    }
+
+    fn anchor(&self) -> Anchor {
+        match self {
+            TextSlot::Title => Anchor::TOP_LEFT,
+            TextSlot::Ticker => Anchor::CENTER_LEFT,
+            TextSlot::Lyrics => Anchor::CENTER,
+        }
+    }
+
+    // Each slot takes its color from a different part of the palette
+    fn palette_position(&self) -> f32 {
+        match self {
+            TextSlot::Title => 0.2,
+            TextSlot::Ticker => 0.5,
+            TextSlot::Lyrics => 0.8,
+        }
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<f32> {
+    let (minutes, seconds) = timestamp.split_once(':')?;
+
+    Some(minutes.trim().parse::<f32>().ok()? * 60.0 + seconds.trim().parse::<f32>().ok()?)
+}
+
+impl Lyrics {
+    // Lines look like "[01:02.50]text", possibly with several timestamps, and an "[offset:ms]" tag
+    // shifts them all
+    pub fn parse_lrc(contents: &str) -> Lyrics {
+        let mut offset = 0.0;
+        let mut lines = Vec::new();
+
+        for line in contents.lines() {
+            let mut rest = line.trim();
+            let mut timestamps = Vec::new();
+
+            while let Some(tag) = rest.strip_prefix('[')
+                && let Some((tag, remainder)) = tag.split_once(']')
+            {
+                if let Some(milliseconds) = tag.strip_prefix("offset:") {
+                    offset = milliseconds.trim().parse::<f32>().unwrap_or(0.0) / 1000.0;
+                } else if let Some(timestamp) = parse_timestamp(tag) {
+                    timestamps.push(timestamp);
+                }
+                rest = remainder;
+            }
+
+            for timestamp in timestamps {
+                lines.push((timestamp, rest.trim().to_owned()));
+            }
+        }
+
+        // A positive offset shows lyrics earlier
+        for line in &mut lines {
+            line.0 -= offset;
+        }
+        lines.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Lyrics { lines }
+    }
+
+    pub fn line_at(&self, seconds: f32) -> &str {
+        let index = self
+            .lines
+            .partition_point(|(timestamp, _)| *timestamp <= seconds);
+
+        index
+            .checked_sub(1)
+            .map(|index| self.lines[index].1.as_str())
+            .unwrap_or_default()
+    }
+}
+
+impl NowPlaying {
+    // Accepts `playerctl metadata` output, where each line may start with the player's name
+    pub fn parse(contents: &str) -> NowPlaying {
+        let mut now_playing = NowPlaying::default();
+
+        for line in contents.lines() {
+            let mut words = line.trim().splitn(3, char::is_whitespace);
+            let (Some(first), Some(second)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let (key, value) = if first.contains(':') || first == "position" {
+                (first, line.trim()[first.len()..].trim())
+            } else {
+                (second, words.next().unwrap_or_default().trim())
+            };
+
+            match key {
+                "xesam:title" => now_playing.title = value.to_owned(),
+                "xesam:artist" => now_playing.artist = value.to_owned(),
+                "xesam:album" => now_playing.album = value.to_owned(),
+                // Microseconds, like the MPRIS Position property
+                "position" => {
+                    now_playing.position = value.parse::<f32>().ok().map(|micros| micros / 1e6)
+                }
+                _ => {}
+            }
+        }
+
+        now_playing
+    }
+
+    fn same_track(&self, other: &NowPlaying) -> bool {
+        self.title == other.title && self.artist == other.artist && self.album == other.album
+    }
+
+    fn format(&self, format: &str) -> String {
+        if self.title.is_empty() {
+            return String::new();
+        }
+
+        let formatted = format
+            .replace("{title}", &self.title)
+            .replace("{artist}", &self.artist)
+            .replace("{album}", &self.album);
+
+        // Drop separators left dangling by missing fields
+        formatted
+            .trim_matches(|c: char| c.is_whitespace() || c == '-')
+            .to_owned()
+    }
+}
+
+impl TextContent {
+    fn track_time(&self, time: &Time) -> f32 {
+        time.elapsed_secs() - self.track_started
+    }
+
+    fn restart_track(&mut self, time: &Time, position: f32) {
+        self.track_started = time.elapsed_secs() - position;
+    }
+
+    fn get(&self, slot: TextSlot, time: &Time) -> String {
+        match slot {
+            TextSlot::Title => self.title.clone(),
+            TextSlot::Ticker => self.ticker.clone(),
+            TextSlot::Lyrics => self
+                .lyrics
+                .as_ref()
+                .map(|lyrics| lyrics.line_at(self.track_time(time)).to_owned())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Plugin for TextPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TextContent>()
+            .add_systems(Startup, setup.after(crate::output::setup))
+            .add_systems(
+                Update,
+                (
+                    apply_remote_commands.after(crate::remote::receive_commands),
+                    poll_now_playing,
+                    update_text
+                        .after(apply_remote_commands)
+                        .after(poll_now_playing)
+                        .after(crate::analysis::update)
+                        .after(crate::palette::update_palettes),
+                ),
+            );
+    }
+}
+
+fn load_lyrics(path: &str) -> Option<Lyrics> {
+    if !config::is_asset_path(path) {
+        warn!("Not loading lyrics {path}: must be a path within the assets");
+        return None;
+    }
+
+    let path = Path::new(ASSET_DIRECTORY).join(path);
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => Some(Lyrics::parse_lrc(&contents)),
+        Err(err) => {
+            warn!("Could not read lyrics {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut content: ResMut<TextContent>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let text_config = &config.text;
+
+    content.title = text_config.title.clone();
+    content.ticker = text_config.ticker.clone();
+    content.lyrics = text_config.lyrics.as_deref().and_then(load_lyrics);
+    content.restart_track(&time, 0.0);
+
+    let font = TextFont {
+        font: text_config
+            .font
+            .as_ref()
+            .map(|font| asset_server.load(font))
+            .unwrap_or_default(),
+        ..default()
+    };
+
+    for slot in TextSlot::ALL {
+        for copy in 0..GLOW_COPIES {
+            let angle = copy as f32 / GLOW_COPIES as f32 * std::f32::consts::TAU;
+
+            commands.spawn((
+                Text2d::default(),
+                font.clone(),
+                TextColor(Color::NONE),
+                slot.anchor(),
+                RenderLayers::layer(TEXT_RENDER_LAYER),
+                TextGlow {
+                    slot,
+                    direction: Vec2::from_angle(angle),
+                },
+            ));
+        }
+
+        commands.spawn((
+            Text2d::default(),
+            font.clone(),
+            slot.anchor(),
+            RenderLayers::layer(TEXT_RENDER_LAYER),
+            TextElement { slot },
+        ));
+    }
+}
+
+pub fn apply_remote_commands(
+    mut remote_commands: MessageReader<RemoteCommand>,
+    mut content: ResMut<TextContent>,
+    time: Res<Time>,
+) {
+    for command in remote_commands.read() {
+        match command {
+            RemoteCommand::SetText(TextSlot::Title, text) => content.title = text.clone(),
+            RemoteCommand::SetText(TextSlot::Ticker, text) => {
+                content.ticker = text.clone();
+                content.ticker_offset = 0.0;
+            }
+            RemoteCommand::SetText(TextSlot::Lyrics, text) => {
+                content.lyrics = Some(Lyrics::parse_lrc(text));
+                content.restart_track(&time, 0.0);
+            }
+            RemoteCommand::LoadLyrics(path) => {
+                if let Some(lyrics) = load_lyrics(path) {
+                    content.lyrics = Some(lyrics);
+                    content.restart_track(&time, 0.0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn poll_now_playing(mut content: ResMut<TextContent>, time: Res<Time>, config: Res<Config>) {
+    let Some(path) = &config.text.now_playing else {
+        return;
+    };
+
+    content.since_poll += time.delta_secs();
+    if content.since_poll < NOW_PLAYING_POLL_INTERVAL {
+        return;
+    }
+    content.since_poll = 0.0;
+
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if modified.is_none() || modified == content.now_playing_modified {
+        return;
+    }
+    content.now_playing_modified = modified;
+
+    let Ok(contents) = fs::read_to_string(path) else {
+        return;
+    };
+    let now_playing = NowPlaying::parse(&contents);
+
+    // Lyrics follow the reported position, or restart when the track changes without one
+    if let Some(position) = now_playing.position {
+        content.restart_track(&time, position);
+    } else if !now_playing.same_track(&content.now_playing) {
+        content.restart_track(&time, 0.0);
+    }
+
+    if !now_playing.same_track(&content.now_playing) {
+        let title = now_playing.format(&config.text.title_format);
+        if !title.is_empty() {
+            info!("Now playing {title}");
+            content.title = title;
+        }
+    }
+    content.now_playing = now_playing;
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_text(
+    mut content: ResMut<TextContent>,
+    mut elements: Query<
+        (
+            &TextElement,
+            &mut Text2d,
+            &mut TextFont,
+            &mut TextColor,
+            &mut Transform,
+            &TextLayoutInfo,
+        ),
+        Without<TextGlow>,
+    >,
+    mut glows: Query<
+        (
+            &TextGlow,
+            &mut Text2d,
+            &mut TextFont,
+            &mut TextColor,
+            &mut Transform,
+        ),
+        Without<TextElement>,
+    >,
+    palettes: Res<Palettes>,
+    analysis: Res<AudioAnalysis>,
+    canvas: Res<Canvas>,
+    time: Res<Time>,
+    config: Res<Config>,
+) {
+    let text_config = &config.text;
+    let size = canvas.size.as_vec2();
+    let font_size = text_config.font_size * size.y;
+
+    let signal = analysis.get(text_config.signal);
+    let scale = 1.0 + text_config.scale_depth * signal;
+    let glow = (text_config.glow_depth * signal).clamp(0.0, 1.0);
+
+    let ticker_width = elements
+        .iter()
+        .find(|(element, ..)| element.slot == TextSlot::Ticker)
+        .map(|(.., layout)| layout.size.x)
+        .unwrap_or_default();
+
+    // The ticker enters from the right edge and wraps once it has left on the left
+    content.ticker_offset += text_config.ticker_speed * size.x * time.delta_secs();
+    if content.ticker_offset > size.x + ticker_width {
+        content.ticker_offset = 0.0;
+    }
+
+    // From the top left fraction of the canvas to the centered camera's pixels
+    let to_pixels = |position: Vec2| Vec2::new(position.x - 0.5, 0.5 - position.y) * size;
+    let translation = |slot: TextSlot| match slot {
+        TextSlot::Title => to_pixels(text_config.title_position),
+        TextSlot::Ticker => Vec2::new(
+            size.x * 0.5 - content.ticker_offset,
+            (0.5 - text_config.ticker_position) * size.y,
+        ),
+        TextSlot::Lyrics => to_pixels(text_config.lyrics_position),
+    };
+    let color = |slot: TextSlot| Color::from(palettes.sample(slot.palette_position()));
+
+    for (element, mut text, mut font, mut text_color, mut transform, _) in elements.iter_mut() {
+        let value = content.get(element.slot, &time);
+        if text.0 != value {
+            text.0 = value;
+        }
+        if font.font_size != font_size {
+            font.font_size = font_size;
+        }
+
+        text_color.0 = color(element.slot);
+        transform.translation = translation(element.slot).extend(1.0);
+        transform.scale = Vec3::splat(scale);
+    }
+
+    for (glow_copy, mut text, mut font, mut text_color, mut transform) in glows.iter_mut() {
+        let value = content.get(glow_copy.slot, &time);
+        if text.0 != value {
+            text.0 = value;
+        }
+        if font.font_size != font_size {
+            font.font_size = font_size;
+        }
+
+        let offset = glow_copy.direction * GLOW_RADIUS * font_size * scale * glow;
+        text_color.0 = color(glow_copy.slot).with_alpha(GLOW_ALPHA * glow);
+        transform.translation = (translation(glow_copy.slot) + offset).extend(0.0);
+        transform.scale = Vec3::splat(scale);
+    }
+}
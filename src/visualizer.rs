@@ -4,21 +4,29 @@ use bevy::{
     asset::{Asset, Assets, Handle},
     ecs::{
         component::Component,
-        system::{Commands, Query, Res, ResMut, Single},
+        system::{Commands, Res, ResMut, Single},
     },
     image::Image,
     math::{Quat, Vec3, primitives::Plane3d},
     mesh::{Mesh, Mesh3d},
     pbr::{Material, MeshMaterial3d},
     reflect::TypePath,
-    render::render_resource::AsBindGroup,
+    render::render_resource::{AsBindGroup, ShaderType},
     shader::ShaderRef,
     transform::components::Transform,
-    window::Window,
 };
 use bevy_svg::prelude::Origin;
+use serde::{Deserialize, Serialize};
 
-use crate::audiolink::AudiolinkDataTexture;
+use crate::{
+    audiolink::{AudiolinkDataTexture, AudiolinkSettings, GENERATOR_ROW, WAVEFORM_FIRST_ROW},
+    config::Config,
+    history::PreviousFrame,
+    output::Canvas,
+    palette::PaletteTexture,
+    parameters::Parameters,
+    scene::Scenes,
+};
 
 const SHADER_ASSET_PATH: &str = "visualizer.wgsl";
 
@@ -32,6 +40,57 @@ pub struct VisualizerMaterial {
     #[texture(0)]
     #[sampler(1)]
     color_texture: Option<Handle<Image>>,
+    #[uniform(2)]
+    settings: VisualizerSettings,
+    #[texture(3)]
+    #[sampler(4)]
+    palette_texture: Option<Handle<Image>>,
+    #[texture(5)]
+    #[sampler(6)]
+    previous_frame: Option<Handle<Image>>,
+}
+
+#[derive(Clone, Copy, Debug, ShaderType)]
+pub struct VisualizerSettings {
+    samples_used: f32,
+    zoom_strength: f32,
+    zoom_scale: f32,
+    contrast: f32,
+    gamma: f32,
+    palette_mix: f32,
+    feedback: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct VisualizerConfig {
+    pub translation: Vec3,
+    pub scale: f32,
+}
+
+impl Default for VisualizerConfig {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::new(0.0, 0.0, -905.0),
+            scale: 750.0,
+        }
+    }
+}
+
+impl VisualizerSettings {
+    fn from_parameters(parameters: &Parameters) -> VisualizerSettings {
+        let get = |name: &str| parameters.get(name).unwrap_or_default();
+
+        VisualizerSettings {
+            samples_used: get("visualizer.samples_used"),
+            zoom_strength: get("visualizer.zoom_strength"),
+            zoom_scale: get("visualizer.zoom_scale"),
+            contrast: get("visualizer.contrast"),
+            gamma: get("visualizer.gamma"),
+            palette_mix: get("visualizer.palette_mix"),
+            feedback: get("visualizer.feedback"),
+        }
+    }
 }
 
 impl Material for VisualizerMaterial {
@@ -44,9 +103,32 @@ pub fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<VisualizerMaterial>>,
+    mut parameters: ResMut<Parameters>,
+    audiolink_settings: Res<AudiolinkSettings>,
+    scenes: Res<Scenes>,
+    config: Res<Config>,
 ) {
+    let waveform_samples = ((GENERATOR_ROW - WAVEFORM_FIRST_ROW) * audiolink_settings.width) as f32;
+
+    parameters.register(
+        "visualizer.samples_used",
+        2048.0_f32.min(waveform_samples),
+        16.0,
+        waveform_samples,
+        64.0,
+    );
+    parameters.register("visualizer.zoom_strength", 5.0, 0.0, 20.0, 0.5);
+    parameters.register("visualizer.zoom_scale", 0.1, 0.0, 1.0, 0.05);
+    parameters.register("visualizer.contrast", 2.0, 0.25, 4.0, 0.1);
+    parameters.register("visualizer.gamma", 1.0, 0.25, 4.0, 0.1);
+    parameters.register("visualizer.palette_mix", 0.0, 0.0, 1.0, 0.05);
+    parameters.register("visualizer.feedback", 0.0, 0.0, 0.99, 0.01);
+
     let visualizer_material = materials.add(VisualizerMaterial {
         color_texture: None,
+        settings: VisualizerSettings::from_parameters(&parameters),
+        palette_texture: None,
+        previous_frame: None,
     });
 
     commands.spawn((
@@ -54,29 +136,39 @@ pub fn setup(
         MeshMaterial3d(visualizer_material.clone()),
         Origin::Center,
         Transform {
-            translation: Vec3::new(0.0, 0.0, -905.0),
-            scale: Vec3::new(750.0, 750.0, 750.0),
+            translation: config.visualizer.translation,
+            scale: Vec3::splat(config.visualizer.scale),
             rotation: Quat::from_rotation_x(PI * 0.5),
         },
         Visualizer {
             material_handle: visualizer_material,
         },
+        scenes.render_layers("visualizer"),
     ));
 }
 
 pub fn update(
     mut visualizer: Single<(&mut Visualizer, &mut Transform)>,
     audiolink_data_texture: Res<AudiolinkDataTexture>,
+    palette_texture: Res<PaletteTexture>,
+    previous_frame: Res<PreviousFrame>,
     mut materials: ResMut<Assets<VisualizerMaterial>>,
-    windows: Query<&mut Window>,
+    parameters: Res<Parameters>,
+    canvas: Res<Canvas>,
+    config: Res<Config>,
 ) {
-    if let Ok(window) = windows.single() {
-        let aspect = window.resolution.width() / window.resolution.height();
+    let aspect = canvas.size.x as f32 / canvas.size.y as f32;
+    let scale = config.visualizer.scale;
 
-        visualizer.1.scale = Vec3::new(750.0 * aspect, 750.0, 750.0);
-    }
+    visualizer.1.scale = Vec3::new(scale * aspect, scale, scale);
 
     if let Some(material_reference) = materials.get_mut(visualizer.0.material_handle.id()) {
         material_reference.color_texture = Some(audiolink_data_texture.0.clone());
+        material_reference.palette_texture = Some(palette_texture.0.clone());
+        material_reference.previous_frame = Some(previous_frame.0.clone());
+
+        if parameters.is_changed() {
+            material_reference.settings = VisualizerSettings::from_parameters(&parameters);
+        }
     }
 }
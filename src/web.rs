@@ -0,0 +1,387 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::AudioAnalysis, parameters::Parameters, remote::RemoteCommand, scene::Scenes,
+};
+
+// The control surface served at /, talking to the WebSocket at /api/ws
+pub const REMOTE_PAGE: &str = include_str!("../assets/remote.html");
+
+// How often the status is snapshotted for the API and pushed to WebSocket clients
+pub const STATUS_PERIOD: Duration = Duration::from_millis(50);
+
+pub const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+pub const WEBSOCKET_MAX_MESSAGE: u64 = 1 << 16;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct SceneStatus {
+    pub names: Vec<String>,
+    pub program: usize,
+    pub preview: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ParameterStatus {
+    pub value: f32,
+    // With the modulation matrix applied
+    pub modulated: f32,
+    pub default: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct AnalysisStatus {
+    pub bands: [f32; 4],
+    pub level: f32,
+    pub beat_pulse: f32,
+    pub beat_phase: f32,
+    pub beats: u64,
+    pub bpm: f32,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Status {
+    pub scenes: SceneStatus,
+    pub parameters: BTreeMap<String, ParameterStatus>,
+    pub analysis: AnalysisStatus,
+}
+
+// Shared with the HTTP threads, which only ever see a snapshot
+#[derive(Resource, Clone, Default)]
+pub struct RemoteStatus(pub Arc<Mutex<Status>>);
+
+// Messages from WebSocket clients use the OSC addresses, with the argument as a string
+#[derive(Deserialize, Debug)]
+pub struct WebSocketMessage {
+    pub address: String,
+    #[serde(default)]
+    pub argument: String,
+}
+
+impl RemoteStatus {
+    pub fn json(&self, path: &str) -> Option<String> {
+        let status = self.0.lock().ok()?;
+
+        match path.trim_end_matches('/') {
+            "/api/state" => serde_json::to_string(&*status).ok(),
+            "/api/scenes" => serde_json::to_string(&status.scenes).ok(),
+            "/api/parameters" => serde_json::to_string(&status.parameters).ok(),
+            "/api/analysis" => serde_json::to_string(&status.analysis).ok(),
+            _ => None,
+        }
+    }
+}
+
+// Missing origins count as the same, as only browsers send them
+pub fn is_same_origin(origin: Option<&str>, host: Option<&str>) -> bool {
+    let Some(origin) = origin else {
+        return true;
+    };
+
+    match (origin.split_once("://"), host) {
+        (Some((_, origin_host)), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+// API bodies are JSON, so strings lose their quotes and numbers pass through, anything else
+// being taken as it is
+pub fn json_argument(body: &str) -> String {
+    match serde_json::from_str(body) {
+        Ok(serde_json::Value::String(argument)) => argument,
+        Ok(serde_json::Value::Number(number)) => number.to_string(),
+        _ => body.to_owned(),
+    }
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for index in 16..80 {
+            words[index] =
+                (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16])
+                    .rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let next = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| {
+            bits | ((*byte as u32) << (16 - 8 * index))
+        });
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[((bits >> (18 - 6 * index)) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn write_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..126 => frame.push(length as u8),
+        length @ 126..65536 => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+
+    stream.write_all(&frame)
+}
+
+// Client frames must be masked, and the control surface never fragments them
+fn read_frame(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0f;
+    if header[1] & 0x80 == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unmasked WebSocket frame",
+        ));
+    }
+
+    let length = match header[1] & 0x7f {
+        126 => {
+            let mut length = [0; 2];
+            stream.read_exact(&mut length)?;
+            u16::from_be_bytes(length) as u64
+        }
+        127 => {
+            let mut length = [0; 8];
+            stream.read_exact(&mut length)?;
+            u64::from_be_bytes(length)
+        }
+        length => length as u64,
+    };
+    if length > WEBSOCKET_MAX_MESSAGE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "WebSocket message too large",
+        ));
+    }
+
+    let mut mask = [0; 4];
+    stream.read_exact(&mut mask)?;
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload)?;
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+
+    Ok((opcode, payload))
+}
+
+// Shutting the socket down once reading stops also ends the writer's loop, so nothing is sent
+// after a Close
+fn read_websocket(mut stream: TcpStream, sender: mpsc::Sender<RemoteCommand>) {
+    read_websocket_frames(&mut stream, sender);
+    let _ = stream.shutdown(Shutdown::Both);
+}
+
+fn read_websocket_frames(stream: &mut TcpStream, sender: mpsc::Sender<RemoteCommand>) {
+    loop {
+        let (opcode, payload) = match read_frame(stream) {
+            Ok(frame) => frame,
+            Err(err) => {
+                debug!("WebSocket closed: {err}");
+                return;
+            }
+        };
+
+        match opcode {
+            OPCODE_TEXT => {
+                let message: WebSocketMessage = match serde_json::from_slice(&payload) {
+                    Ok(message) => message,
+                    Err(err) => {
+                        debug!("Invalid WebSocket message: {err}");
+                        continue;
+                    }
+                };
+
+                match RemoteCommand::parse(&message.address, &message.argument) {
+                    Some(command) => {
+                        let _ = sender.send(command);
+                    }
+                    None => debug!("Ignoring WebSocket message to {}", message.address),
+                }
+            }
+            OPCODE_PING => {
+                let _ = write_frame(stream, OPCODE_PONG, &payload);
+            }
+            // The reply echoes the client's status code, if it sent one
+            OPCODE_CLOSE => {
+                let _ = write_frame(stream, OPCODE_CLOSE, &payload[..payload.len().min(2)]);
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn write_websocket(mut stream: TcpStream, status: RemoteStatus) {
+    loop {
+        let Some(json) = status.json("/api/state") else {
+            return;
+        };
+        if write_frame(&mut stream, OPCODE_TEXT, json.as_bytes()).is_err() {
+            // Likewise ends the reader once the client is gone
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+
+        thread::sleep(STATUS_PERIOD);
+    }
+}
+
+// Completes the upgrade handshake, then pushes the status to the client and reads its messages
+// on threads of their own
+pub fn accept_websocket(
+    mut stream: TcpStream,
+    key: &str,
+    sender: mpsc::Sender<RemoteCommand>,
+    status: RemoteStatus,
+) {
+    let accept = base64(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes()));
+    if write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    )
+    .is_err()
+    {
+        return;
+    }
+
+    // Clients may stay quiet for as long as they like once connected
+    let _ = stream.set_read_timeout(None);
+    let Ok(reader_stream) = stream.try_clone() else {
+        return;
+    };
+    thread::spawn(move || read_websocket(reader_stream, sender));
+    thread::spawn(move || write_websocket(stream, status));
+}
+
+pub fn update_status(
+    status: Res<RemoteStatus>,
+    parameters: Res<Parameters>,
+    scenes: Res<Scenes>,
+    analysis: Res<AudioAnalysis>,
+    time: Res<Time>,
+    mut last_update: Local<Duration>,
+) {
+    if time.elapsed() < *last_update + STATUS_PERIOD {
+        return;
+    }
+    *last_update = time.elapsed();
+
+    let Ok(mut status) = status.0.lock() else {
+        return;
+    };
+
+    *status = Status {
+        scenes: SceneStatus {
+            names: scenes.names.clone(),
+            program: scenes.program,
+            preview: scenes.preview,
+        },
+        parameters: parameters
+            .iter()
+            .map(|(name, parameter)| {
+                (
+                    name.clone(),
+                    ParameterStatus {
+                        value: parameter.value,
+                        modulated: parameter.modulated(),
+                        default: parameter.default,
+                        min: parameter.min,
+                        max: parameter.max,
+                        step: parameter.step,
+                    },
+                )
+            })
+            .collect(),
+        analysis: AnalysisStatus {
+            bands: analysis.bands,
+            level: analysis.level,
+            beat_pulse: analysis.beat_pulse,
+            beat_phase: analysis.beat_phase,
+            beats: analysis.beats,
+            bpm: analysis.bpm,
+        },
+    };
+}